@@ -40,6 +40,53 @@ pub static REDIS_URI: Lazy<String> = Lazy::new(|| {
     env::var("REDIS_URI").unwrap_or("redis://localhost:6379".to_string())
 });
 
+/// How long a soft-deleted quiz stays in the trash before it is purged for good.
+pub static TRASH_RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+    env::var("TRASH_RETENTION_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("Invalid TRASH_RETENTION_DAYS")
+});
+
+/// Directory uploaded images are stored under by the local storage backend.
+pub static MEDIA_DIR: Lazy<String> = Lazy::new(|| {
+    env::var("MEDIA_DIR").unwrap_or("media".to_string())
+});
+
+/// Largest image upload accepted, in bytes.
+pub static MEDIA_MAX_BYTES: Lazy<usize> = Lazy::new(|| {
+    env::var("MEDIA_MAX_BYTES")
+        .unwrap_or_else(|_| "5242880".to_string())
+        .parse()
+        .expect("Invalid MEDIA_MAX_BYTES")
+});
+
+/// Largest audio or video upload accepted, in bytes.
+pub static MEDIA_CLIP_MAX_BYTES: Lazy<usize> = Lazy::new(|| {
+    env::var("MEDIA_CLIP_MAX_BYTES")
+        .unwrap_or_else(|_| "52428800".to_string())
+        .parse()
+        .expect("Invalid MEDIA_CLIP_MAX_BYTES")
+});
+
+/// Unreferenced media uploaded or last seen in use less than this long ago is left alone by the
+/// sweep, so an image uploaded just before the quiz using it is saved, or removed by an edit that
+/// is then undone, isn't collected in between.
+pub static MEDIA_GC_GRACE_HOURS: Lazy<i64> = Lazy::new(|| {
+    env::var("MEDIA_GC_GRACE_HOURS")
+        .unwrap_or_else(|_| "24".to_string())
+        .parse()
+        .expect("Invalid MEDIA_GC_GRACE_HOURS")
+});
+
+/// When set, the media sweep deletes nothing; its report in `media_sweeps` lists what it would
+/// have deleted.
+pub static MEDIA_GC_DRY_RUN: Lazy<bool> = Lazy::new(|| {
+    env::var("MEDIA_GC_DRY_RUN")
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+});
+
 pub static JWT_SECRET: Lazy<String> = Lazy::new(|| {
     env::var("JWT_SECRET").expect("JWT_SECRET must be set")
 });
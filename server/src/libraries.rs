@@ -1,10 +1,31 @@
 use actix_web::{HttpResponse, HttpResponseBuilder};
+use serde::Serialize;
 use serde_json::json;
 
 pub mod mongodb;
 
 pub mod redis;
 
+pub mod profanity;
+
+pub mod rich_text;
+
+pub mod spreadsheet;
+
+pub mod interchange;
+
+pub mod moodle;
+
+pub mod qti;
+
+pub mod media;
+
+pub mod media_gc;
+
+pub mod storage;
+
+pub mod trash;
+
 pub fn response_ok_builder() -> HttpResponseBuilder {
     HttpResponse::Ok()
 }
@@ -25,6 +46,14 @@ pub fn response_forbidden() -> HttpResponse {
     HttpResponse::Forbidden().json(json!({ "message": "Forbidden." }))
 }
 
+pub fn response_precondition_required() -> HttpResponse {
+    HttpResponse::PreconditionRequired().json(json!({ "message": "Precondition required." }))
+}
+
+pub fn response_unprocessable_entity<T: Serialize>(errors: T) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(json!({ "message": "Unprocessable entity.", "errors": errors }))
+}
+
 pub fn response_internal_server_error() -> HttpResponse {
     HttpResponse::InternalServerError().json(json!({ "message": "Internal server error." }))
 }
\ No newline at end of file
@@ -0,0 +1,117 @@
+//! Question file formats from other quiz tools, and what they have in common: a file goes in as
+//! bytes and comes out as slides plus the images it embedded, with warnings for whatever didn't
+//! carry over.
+
+use std::collections::HashMap;
+use std::io;
+use serde::Serialize;
+
+use crate::libraries::{moodle, qti};
+use crate::models::slide::Slide;
+
+/// Something about question or slide `index` that didn't carry over.
+#[derive(Serialize)]
+pub struct Warning {
+    pub index: usize,
+    pub message: String,
+}
+
+/// An embedded image file.
+pub struct Image {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// A file's questions as slides, with the images embedded in it by slide index, still to be
+/// stored.
+pub struct Imported {
+    /// How many questions the file held, counting those left out.
+    pub count: usize,
+    pub slides: Vec<Slide>,
+    /// Where each slide's question sits in the file, which is what warnings refer to.
+    pub positions: Vec<usize>,
+    pub images: Vec<(usize, Image)>,
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Gift,
+    MoodleXml,
+    Qti21,
+    Qti30,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Format> {
+        match value {
+            "gift" => Some(Format::Gift),
+            "moodle_xml" => Some(Format::MoodleXml),
+            "qti21" => Some(Format::Qti21),
+            "qti30" => Some(Format::Qti30),
+            _ => None,
+        }
+    }
+
+    /// Whether the format embeds images, so exports need them loaded from storage.
+    pub fn has_images(self) -> bool {
+        self != Format::Gift
+    }
+
+    pub fn file_name(self, title: &str) -> String {
+        let stem: String = title
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect::<String>()
+            .split('-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let stem = if stem.is_empty() { "quiz".to_string() } else { stem };
+
+        match self {
+            Format::Gift => format!("{}.gift.txt", stem),
+            Format::MoodleXml => format!("{}.xml", stem),
+            Format::Qti21 => format!("{}.qti21.zip", stem),
+            Format::Qti30 => format!("{}.qti30.zip", stem),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Gift => "text/plain; charset=utf-8",
+            Format::MoodleXml => "application/xml; charset=utf-8",
+            Format::Qti21 | Format::Qti30 => "application/zip",
+        }
+    }
+}
+
+/// Reads a file in `format`, or says why it isn't one.
+pub fn import(format: Format, bytes: &[u8]) -> Result<Imported, &'static str> {
+    let text = || match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.trim_start_matches('\u{feff}')),
+        Err(_) => Err("must be UTF-8 text"),
+    };
+
+    match format {
+        Format::Gift => Ok(moodle::gift::import(text()?)),
+        Format::MoodleXml => moodle::xml::import(text()?),
+        Format::Qti21 | Format::Qti30 => qti::package::import(bytes),
+    }
+}
+
+/// Writes `slides` in `format`, embedding `images` (by slide index) where the format can.
+pub fn export(format: Format, title: &str, slides: &[Slide], images: HashMap<usize, Image>) -> io::Result<(Vec<u8>, Vec<Warning>)> {
+    match format {
+        Format::Gift => {
+            let (content, warnings) = moodle::gift::export(slides);
+            Ok((content.into_bytes(), warnings))
+        }
+        Format::MoodleXml => {
+            let (content, warnings) = moodle::xml::export(slides, images);
+            Ok((content.into_bytes(), warnings))
+        }
+        Format::Qti21 => qti::package::export(qti::Version::V2p1, title, slides, images),
+        Format::Qti30 => qti::package::export(qti::Version::V3p0, title, slides, images),
+    }
+}
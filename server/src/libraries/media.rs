@@ -0,0 +1,327 @@
+use std::io::Cursor;
+use actix_web::web::Bytes;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, ImageFormat, ImageReader, Limits, Rgba};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use symphonia::core::codecs::{self, CodecType};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Largest width or height accepted, to keep decoding memory bounded.
+pub const MAX_DIMENSION: u32 = 8192;
+
+/// Longest audio or video clip accepted, in seconds.
+pub const CLIP_MAX_SECONDS: f64 = 300.0;
+
+/// Number of obscured stages shown before the full image.
+pub const REVEAL_STAGES: u32 = 4;
+
+/// Reveal stages are rendered no larger than this; they are meant to be looked at, not studied.
+const REVEAL_MAX_SIZE: u32 = 800;
+
+/// The tiled mask splits the image into a `TILE_GRID` x `TILE_GRID` grid.
+const TILE_GRID: u32 = 4;
+
+/// How a slide's `image_reveal` uncovers its image over the answering time.
+#[derive(Clone, Copy)]
+pub enum RevealMode {
+    Blur,
+    Pixelate,
+    Tiles,
+}
+
+impl RevealMode {
+    /// Any other `image_reveal` value (`""`, `"none"`, ...) shows the image straight away.
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "blur" => Some(RevealMode::Blur),
+            "pixelate" => Some(RevealMode::Pixelate),
+            "tiles" => Some(RevealMode::Tiles),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevealMode::Blur => "blur",
+            RevealMode::Pixelate => "pixelate",
+            RevealMode::Tiles => "tiles",
+        }
+    }
+}
+
+/// An upload after it has been decoded and encoded again. Only pixels survive the round trip, so
+/// EXIF data (camera, GPS position, ...) and anything smuggled after the image data is dropped.
+pub struct ProcessedImage {
+    pub key: String,
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    #[default]
+    Image,
+    Audio,
+    Video,
+}
+
+impl MediaKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaKind::Image => "image",
+            MediaKind::Audio => "audio",
+            MediaKind::Video => "video",
+        }
+    }
+}
+
+/// An audio or video upload that passed the checks in `probe_clip`. Clips are stored as
+/// uploaded; there is no transcoder to re-encode them with.
+pub struct ProcessedClip {
+    pub key: String,
+    pub kind: MediaKind,
+    pub content_type: &'static str,
+    pub codec: &'static str,
+    pub duration: f64,
+}
+
+/// Maps the extension at the end of a media key to the type it is served with.
+pub fn content_type(key: &str) -> Option<&'static str> {
+    match key.rsplit_once('.')?.1 {
+        "jpg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "mp3" => Some("audio/mpeg"),
+        "m4a" => Some("audio/mp4"),
+        "ogg" => Some("audio/ogg"),
+        "wav" => Some("audio/wav"),
+        "weba" => Some("audio/webm"),
+        "mp4" => Some("video/mp4"),
+        "webm" => Some("video/webm"),
+        _ => None,
+    }
+}
+
+/// Whether the media behind `key` is an image, audio or video.
+pub fn kind(key: &str) -> Option<MediaKind> {
+    match content_type(key)?.split_once('/')?.0 {
+        "image" => Some(MediaKind::Image),
+        "audio" => Some(MediaKind::Audio),
+        "video" => Some(MediaKind::Video),
+        _ => None,
+    }
+}
+
+/// Whether `key` looks like one `process` produces: a SHA-256 hex digest and a known extension.
+/// Keys come from URLs, so this also keeps them from naming anything outside the store.
+pub fn is_valid_key(key: &str) -> bool {
+    let hash = match key.split_once('.') {
+        Some((hash, _)) => hash,
+        None => return false,
+    };
+
+    hash.len() == 64
+        && hash.bytes().all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c))
+        && content_type(key).is_some()
+}
+
+/// Whether the upload is one of the image formats `process` accepts, judged by its magic bytes.
+pub fn is_image(bytes: &[u8]) -> bool {
+    matches!(image::guess_format(bytes), Ok(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP))
+}
+
+/// Sniffs the format from the bytes themselves (the client's Content-Type is not trusted),
+/// decodes the image and re-encodes it: JPEG stays JPEG, everything else becomes PNG. Returns
+/// `None` for anything that isn't a supported, well-formed image.
+pub fn process(bytes: &[u8]) -> Option<ProcessedImage> {
+    let format = match image::guess_format(bytes).ok()? {
+        format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP) => format,
+        _ => return None,
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+
+    let image = reader.decode().ok()?;
+
+    encode(image, format == ImageFormat::Jpeg)
+}
+
+/// JPEG has no alpha channel to keep, everything else is stored as PNG.
+fn encode(image: DynamicImage, jpeg: bool) -> Option<ProcessedImage> {
+    let (image, format, extension, content_type) = if jpeg {
+        (DynamicImage::ImageRgb8(image.to_rgb8()), ImageFormat::Jpeg, "jpg", "image/jpeg")
+    } else {
+        (image, ImageFormat::Png, "png", "image/png")
+    };
+
+    let mut encoded = Vec::new();
+    image.write_to(&mut Cursor::new(&mut encoded), format).ok()?;
+
+    Some(ProcessedImage {
+        key: format!("{:x}.{}", Sha256::digest(&encoded), extension),
+        bytes: encoded,
+        content_type,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// Renders the obscured stages of a stored image, most obscured first. Each stage is stored under
+/// its own content hash, so a stage's URL says nothing about the full image's.
+pub fn render_reveal(bytes: &[u8], key: &str, mode: RevealMode) -> Option<Vec<ProcessedImage>> {
+    let image = image::load_from_memory(bytes).ok()?.thumbnail(REVEAL_MAX_SIZE, REVEAL_MAX_SIZE);
+    let jpeg = content_type(key) == Some("image/jpeg");
+
+    // The order tiles are uncovered in is fixed per image, so every stage uncovers more of the
+    // same picture.
+    let mut tiles: Vec<u32> = (0..TILE_GRID * TILE_GRID).collect();
+    tiles.shuffle(&mut StdRng::from_seed(Sha256::digest(key.as_bytes()).into()));
+
+    (0..REVEAL_STAGES)
+        .map(|stage| {
+            // 1.0 for the first stage, shrinking towards 0 as the reveal goes on.
+            let strength = (REVEAL_STAGES - stage) as f32 / REVEAL_STAGES as f32;
+
+            let rendered = match mode {
+                RevealMode::Blur => image.blur(24.0 * strength),
+                RevealMode::Pixelate => {
+                    let columns = 6 << stage;
+                    let rows = (columns * image.height() / image.width().max(1)).max(1);
+
+                    image
+                        .resize_exact(columns, rows, FilterType::Triangle)
+                        .resize_exact(image.width(), image.height(), FilterType::Nearest)
+                }
+                RevealMode::Tiles => {
+                    let mut masked = DynamicImage::ImageRgba8(image.to_rgba8());
+                    let (tile_width, tile_height) = (image.width().div_ceil(TILE_GRID), image.height().div_ceil(TILE_GRID));
+                    let uncovered = (TILE_GRID * TILE_GRID * stage / REVEAL_STAGES) as usize;
+
+                    for tile in &tiles[uncovered..] {
+                        let (left, top) = (tile % TILE_GRID * tile_width, tile / TILE_GRID * tile_height);
+
+                        for y in top..(top + tile_height).min(image.height()) {
+                            for x in left..(left + tile_width).min(image.width()) {
+                                masked.put_pixel(x, y, Rgba([128, 128, 128, 255]));
+                            }
+                        }
+                    }
+
+                    masked
+                }
+            };
+
+            encode(rendered, jpeg)
+        })
+        .collect()
+}
+
+/// Recognises the container from its magic bytes and returns the extension it is stored with.
+/// MP4 and WebM can hold either audio or video, so for those the declared Content-Type decides.
+fn sniff_container(bytes: &[u8], declared: Option<&str>) -> Option<&'static str> {
+    let audio = declared.is_some_and(|declared| declared.starts_with("audio/"));
+
+    match bytes {
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("mp3"),
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [_, _, _, _, b'f', b't', b'y', b'p', b'M', b'4', b'A', ..] => Some("m4a"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(if audio { "m4a" } else { "mp4" }),
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => Some(if audio { "weba" } else { "webm" }),
+        _ => None,
+    }
+}
+
+fn codec_name(codec: CodecType) -> Option<&'static str> {
+    match codec {
+        codecs::CODEC_TYPE_AAC => Some("aac"),
+        codecs::CODEC_TYPE_MP3 => Some("mp3"),
+        codecs::CODEC_TYPE_OPUS => Some("opus"),
+        codecs::CODEC_TYPE_VORBIS => Some("vorbis"),
+        codecs::CODEC_TYPE_FLAC => Some("flac"),
+        codecs::CODEC_TYPE_PCM_S16LE | codecs::CODEC_TYPE_PCM_S24LE | codecs::CODEC_TYPE_PCM_F32LE => Some("pcm"),
+        _ => None,
+    }
+}
+
+/// Checks an audio or video upload: a supported container, a supported audio codec and a
+/// duration within `CLIP_MAX_SECONDS`. The duration is read from the audio track, so video
+/// clips need one; video tracks themselves aren't inspected. Errors are reasons for the client.
+pub fn probe_clip(bytes: Bytes, declared: Option<&str>) -> Result<ProcessedClip, &'static str> {
+    let extension = sniff_container(&bytes, declared).ok_or("must be an image, or an MP3, M4A, MP4, OGG, WAV or WebM clip")?;
+    let key = format!("{:x}.{}", Sha256::digest(&bytes), extension);
+
+    let mut hint = Hint::new();
+    hint.with_extension(match extension {
+        "m4a" | "mp4" => "mp4",
+        "weba" | "webm" => "webm",
+        extension => extension,
+    });
+
+    let source = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|_| "could not be read")?
+        .format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != codecs::CODEC_TYPE_NULL)
+        .ok_or("must contain an audio track")?;
+
+    let codec = codec_name(track.codec_params.codec).ok_or("must use AAC, MP3, Opus, Vorbis, FLAC or PCM audio")?;
+    let time_base = track.codec_params.time_base.ok_or("could not be read")?;
+    let track_id = track.id;
+
+    // Containers that don't state the length up front are measured by walking their packets.
+    let frames = match track.codec_params.n_frames {
+        Some(frames) => frames,
+        None => {
+            let mut end = 0;
+
+            while let Ok(packet) = format.next_packet() {
+                if packet.track_id() == track_id {
+                    end = end.max(packet.ts() + packet.dur());
+                }
+            }
+
+            end
+        }
+    };
+
+    let time = time_base.calc_time(frames);
+    let duration = time.seconds as f64 + time.frac;
+
+    if duration <= 0.0 {
+        return Err("could not be read");
+    }
+
+    if duration > CLIP_MAX_SECONDS {
+        return Err("must be at most 300 seconds long");
+    }
+
+    Ok(ProcessedClip {
+        kind: kind(&key).unwrap_or(MediaKind::Audio),
+        content_type: content_type(&key).unwrap_or("application/octet-stream"),
+        key,
+        codec,
+        duration,
+    })
+}
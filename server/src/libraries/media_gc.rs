@@ -0,0 +1,331 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use mongodb::bson::{doc, oid::ObjectId, Bson, DateTime, Document, Regex};
+use mongodb::{Collection, Database};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::env::{MEDIA_GC_DRY_RUN, MEDIA_GC_GRACE_HOURS};
+use crate::libraries::redis::RedisConn;
+use crate::libraries::storage::get_storage;
+use crate::models::bank::BankItem;
+use crate::models::id::MediaId;
+use crate::models::media::{Media, RevealVariants};
+use crate::models::quiz::Quiz;
+use crate::models::revision::QuizRevision;
+use crate::models::room::QuizSnapshot;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How many keys go into one `$in` when marking media as referenced.
+const MARK_BATCH: usize = 1000;
+
+/// What one sweep removed, or would have removed on a dry run. Every sweep stores one in
+/// `media_sweeps`, so a dry run can be reviewed key by key before turning deletion on.
+#[derive(Serialize, Deserialize)]
+pub struct SweepReport {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub dry_run: bool,
+    /// Per-user media records nothing references any more.
+    pub records: u64,
+    /// Stored images whose last record went, with their reveal stages.
+    pub keys: Vec<String>,
+    pub stages: u64,
+    pub bytes: i64,
+    pub created_at: DateTime,
+}
+
+impl SweepReport {
+    pub const COLLECTION: &'static str = "media_sweeps";
+
+    pub fn collection(db: &Database) -> Collection<SweepReport> {
+        db.collection(Self::COLLECTION)
+    }
+}
+
+/// Adds the media key of every `image_path` and clip `path` found anywhere in `value`.
+fn collect_paths(value: &Bson, keys: &mut HashSet<String>) {
+    match value {
+        Bson::Document(document) => {
+            for (name, value) in document {
+                match value {
+                    Bson::String(path) if name == "image_path" || name == "path" => {
+                        if let Some(key) = Media::key_from_path(path) {
+                            keys.insert(key.to_string());
+                        }
+                    }
+                    value => collect_paths(value, keys),
+                }
+            }
+        }
+        Bson::Array(values) => values.iter().for_each(|value| collect_paths(value, keys)),
+        _ => {}
+    }
+}
+
+async fn collect_keys(db: &Database, collection: &str, slides: &str, keys: &mut HashSet<String>) -> mongodb::error::Result<()> {
+    let mut projection = Document::new();
+    projection.insert(format!("{}.image_path", slides), 1);
+    projection.insert(format!("{}.clip.path", slides), 1);
+
+    let mut cursor = db.collection::<Document>(collection)
+        .find(doc! {})
+        .projection(projection)
+        .await?;
+
+    while let Some(document) = cursor.try_next().await? {
+        collect_paths(&Bson::Document(document), keys);
+    }
+
+    Ok(())
+}
+
+/// Adds the media keys a running room uses: its slides, bank draws included, and the reveal
+/// stages rendered for them.
+fn collect_snapshot_keys(snapshot: &QuizSnapshot, keys: &mut HashSet<String>) {
+    let slide_paths = snapshot.slides.iter().flat_map(|slide| slide.media_paths());
+    let stage_paths = snapshot.reveals.values().flatten().map(String::as_str);
+
+    for path in slide_paths.chain(stage_paths) {
+        if let Some(key) = Media::key_from_path(path) {
+            keys.insert(key.to_string());
+        }
+    }
+}
+
+/// Adds the media keys of every room snapshot in Redis. Rooms play from their snapshot, so a
+/// quiz edited or deleted mid-game must not lose the images its players are looking at.
+fn collect_live_keys(keys: &mut HashSet<String>) -> mongodb::error::Result<()> {
+    let redis_error = |e: redis::RedisError| mongodb::error::Error::custom(e.to_string());
+
+    let mut redis_connect = RedisConn::get_connection().map_err(redis_error)?;
+
+    for key in redis_connect.scan_match(&QuizSnapshot::key("*")).map_err(redis_error)? {
+        // Gone since the scan: the room ended, so nothing to keep.
+        let snapshot = match redis_connect.get(&key).map_err(redis_error)? {
+            Some(snapshot) => snapshot,
+            None => continue,
+        };
+
+        let snapshot: QuizSnapshot = serde_json::from_str(&snapshot)
+            .map_err(|e| mongodb::error::Error::custom(format!("Unreadable snapshot {}: {}", key, e)))?;
+
+        collect_snapshot_keys(&snapshot, keys);
+    }
+
+    Ok(())
+}
+
+/// Every media key still used by a quiz (trashed ones included), a past revision, a bank item or
+/// a running room.
+pub async fn referenced_keys(db: &Database) -> mongodb::error::Result<HashSet<String>> {
+    let mut keys = HashSet::new();
+
+    collect_keys(db, Quiz::COLLECTION, "slides", &mut keys).await?;
+    collect_keys(db, QuizRevision::COLLECTION, "slides", &mut keys).await?;
+    collect_keys(db, BankItem::COLLECTION, "slide", &mut keys).await?;
+    collect_live_keys(&mut keys)?;
+
+    Ok(keys)
+}
+
+/// Stamps the records of every referenced key with `now`, which starts the grace period over
+/// for media that stops being referenced later.
+async fn mark_referenced(db: &Database, referenced: &HashSet<String>, now: DateTime) -> mongodb::error::Result<()> {
+    let referenced: Vec<&String> = referenced.iter().collect();
+
+    for keys in referenced.chunks(MARK_BATCH) {
+        Media::collection(db)
+            .update_many(
+                doc! { "key": { "$in": keys.to_vec() } },
+                doc! { "$set": { "last_referenced_at": now } },
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Deletes media records that nothing references and that were neither uploaded nor last seen
+/// referenced within the grace period, then the stored bytes (and rendered reveal stages) of
+/// every image left without a record. With `dry_run` nothing is deleted and the report says what
+/// would have been.
+pub async fn sweep(db: &Database, grace_millis: i64, dry_run: bool) -> mongodb::error::Result<SweepReport> {
+    let now = DateTime::now();
+    let referenced = referenced_keys(db).await?;
+    let cutoff = DateTime::from_millis(now.timestamp_millis() - grace_millis);
+
+    mark_referenced(db, &referenced, now).await?;
+
+    let orphans: Vec<Media> = Media::collection(db)
+        .find(doc! {
+            "created_at": { "$lt": cutoff },
+            "last_referenced_at": { "$not": { "$gte": cutoff } },
+        })
+        .await?
+        .try_filter(|media| futures::future::ready(!referenced.contains(&media.key)))
+        .try_collect()
+        .await?;
+
+    let mut report = SweepReport {
+        id: ObjectId::new(),
+        dry_run,
+        records: orphans.len() as u64,
+        keys: Vec::new(),
+        stages: 0,
+        bytes: 0,
+        created_at: now,
+    };
+
+    if orphans.is_empty() {
+        return Ok(report);
+    }
+
+    let orphan_ids: Vec<MediaId> = orphans.iter().map(|media| media.id).collect();
+
+    if !dry_run {
+        Media::collection(db)
+            .delete_many(doc! { "_id": { "$in": orphan_ids.clone() } })
+            .await?;
+    }
+
+    let mut seen = HashSet::new();
+
+    for media in &orphans {
+        if !seen.insert(media.key.clone()) {
+            continue;
+        }
+
+        // A newer upload of the same picture, by anyone, keeps the bytes alive.
+        let remaining = Media::collection(db)
+            .count_documents(doc! { "key": &media.key, "_id": { "$nin": orphan_ids.clone() } })
+            .await?;
+
+        if remaining > 0 {
+            continue;
+        }
+
+        let variants: Vec<RevealVariants> = RevealVariants::collection(db)
+            .find(doc! {
+                "_id": Regex {
+                    pattern: format!("^{}:", media.key.replace('.', "\\.")),
+                    options: String::new(),
+                }
+            })
+            .await?
+            .try_collect()
+            .await?;
+
+        report.keys.push(media.key.clone());
+        report.bytes += media.size;
+        report.stages += variants.iter().map(|variants| variants.keys.len() as u64).sum::<u64>();
+
+        if dry_run {
+            continue;
+        }
+
+        let storage = get_storage();
+        let storage_error = |e: std::io::Error| mongodb::error::Error::custom(e.to_string());
+
+        for variants in &variants {
+            for key in &variants.keys {
+                storage.delete(key).await.map_err(storage_error)?;
+            }
+
+            RevealVariants::collection(db).delete_one(doc! { "_id": &variants.id }).await?;
+        }
+
+        storage.delete(&media.key).await.map_err(storage_error)?;
+    }
+
+    Ok(report)
+}
+
+/// Runs `sweep` every few hours for the lifetime of the server, honouring `MEDIA_GC_DRY_RUN`, and
+/// stores each report.
+pub fn spawn_sweep_job(db: Database) {
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        let grace_millis = *MEDIA_GC_GRACE_HOURS * 60 * 60 * 1000;
+        let dry_run = *MEDIA_GC_DRY_RUN;
+
+        loop {
+            interval.tick().await;
+
+            let report = match sweep(&db, grace_millis, dry_run).await {
+                Ok(report) => report,
+                Err(e) => {
+                    error!("Failed to sweep media: {}", e);
+                    continue;
+                }
+            };
+
+            if report.records > 0 {
+                info!(
+                    "{} {} unreferenced media records, {} images ({} bytes) and {} reveal stages; see {} {}",
+                    if dry_run { "Media sweep would delete" } else { "Media sweep deleted" },
+                    report.records,
+                    report.keys.len(),
+                    report.bytes,
+                    report.stages,
+                    SweepReport::COLLECTION,
+                    report.id,
+                );
+            }
+
+            if let Err(e) = SweepReport::collection(&db).insert_one(&report).await {
+                error!("Failed to store media sweep report: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::id::QuizId;
+
+    #[test]
+    fn finds_image_and_clip_paths_anywhere() {
+        let document = doc! {
+            "slides": [
+                { "image_path": "/api/media/one.webp", "clip": { "path": "/api/media/two.mp3" } },
+                { "image_path": "https://example.com/elsewhere.png" },
+                { "clip": { "path": "local:intro.mp3" } },
+            ],
+        };
+        let mut keys = HashSet::new();
+
+        collect_paths(&Bson::Document(document), &mut keys);
+
+        assert_eq!(keys, HashSet::from(["one.webp".to_string(), "two.mp3".to_string()]));
+    }
+
+    #[test]
+    fn keeps_what_running_rooms_show() {
+        let slide = serde_json::from_value(json!({
+            "question_type": "open_ended", "theme": "", "time_limit": 20, "image_reveal": "blur",
+            "image_path": "/api/media/photo.webp", "question": "What is it?", "max_length": null,
+        })).unwrap();
+
+        let snapshot = QuizSnapshot {
+            quiz_id: QuizId::new(),
+            revision: 3,
+            title: String::new(),
+            slides: vec![slide],
+            reveals: HashMap::from([(0, vec!["/api/media/stage-0.webp".to_string(), "/api/media/stage-1.webp".to_string()])]),
+        };
+        let mut keys = HashSet::new();
+
+        collect_snapshot_keys(&snapshot, &mut keys);
+
+        assert_eq!(keys, HashSet::from(["photo.webp".to_string(), "stage-0.webp".to_string(), "stage-1.webp".to_string()]));
+    }
+}
@@ -4,6 +4,7 @@ use mongodb::{
     Client, Collection, Database, IndexModel,
 };
 use mongodb::results::{CreateIndexResult};
+use futures::TryStreamExt;
 use tokio::sync::OnceCell;
 
 use crate::env::MONGODB_URI;
@@ -42,4 +43,61 @@ pub async fn create_index(
         .build();
 
     collection.create_index(index_model).await
-}
\ No newline at end of file
+}
+
+/// Creates the collection's text index on `keys`, reading each document's language from
+/// `language_override`. Without an override MongoDB reads it from a `language` field and rejects
+/// writes where that holds null or a language it doesn't know. A collection has at most one text
+/// index, so an existing one with another override is dropped first.
+pub async fn create_text_index(
+    collection_name: &str,
+    keys: Document,
+    language_override: &str,
+) -> mongodb::error::Result<CreateIndexResult> {
+    let collection = get_collection(collection_name);
+    let mut indexes = collection.list_indexes().await?;
+
+    while let Some(index) = indexes.try_next().await? {
+        let is_text = index.keys.values().any(|value| value.as_str() == Some("text"));
+        let options = index.options.unwrap_or_default();
+
+        if is_text && options.language_override.as_deref() != Some(language_override) {
+            if let Some(name) = options.name {
+                drop(indexes);
+                collection.drop_index(name).await?;
+                break;
+            }
+        }
+    }
+
+    let index_options = IndexOptions::builder().language_override(language_override.to_string()).build();
+    let index_model = IndexModel::builder()
+        .keys(keys)
+        .options(index_options)
+        .build();
+
+    collection.create_index(index_model).await
+}
+
+/// Drops the index on exactly `keys` if it is unique. `create_index` can't relax an existing
+/// index in place: asking for the same keys with other options fails, so the old one has to go
+/// first. Returns whether anything was dropped.
+pub async fn drop_unique_index(collection_name: &str, keys: Document) -> mongodb::error::Result<bool> {
+    let collection = get_collection(collection_name);
+    let mut indexes = collection.list_indexes().await?;
+
+    while let Some(index) = indexes.try_next().await? {
+        let options = match index.options {
+            Some(options) if index.keys == keys && options.unique == Some(true) => options,
+            _ => continue,
+        };
+
+        if let Some(name) = options.name {
+            collection.drop_index(name).await?;
+
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,344 @@
+//! Conversion between slides and Moodle's GIFT and XML question formats. Both formats go through
+//! `MoodleQuestion`, which holds the parts of a question the two have in common with slides.
+//!
+//! | slide           | Moodle        |
+//! |-----------------|---------------|
+//! | `question`      | `multichoice` |
+//! | `true_or_false` | `truefalse`   |
+//! | `short_answer`  | `shortanswer` |
+//! | `numerical`     | `numerical`   |
+//! | `open_ended`    | `essay`       |
+//! | `content`       | `description` |
+//!
+//! Moodle has no per-question time limit, and GIFT has no grades or images, so those don't
+//! survive a round trip through it. Anything else that can't be carried over is reported as a
+//! `Warning`.
+
+pub mod gift;
+
+pub mod xml;
+
+use crate::libraries::interchange::{Image, Imported, Warning};
+use crate::libraries::rich_text::replace_math;
+use crate::models::slide::{Slide, SlideContent, DEFAULT_POINTS, SlideNumerical, SlideOpenEnded, SlideQuizQuestion, SlideQuizTrueOrFalse, SlideShortAnswer};
+
+/// Longest question name written to an export; Moodle only shows names in its question bank.
+const NAME_MAX_LENGTH: usize = 60;
+
+pub enum Kind {
+    MultipleChoice { answers: Vec<String>, correct: Vec<bool> },
+    TrueFalse(bool),
+    ShortAnswer { answers: Vec<String>, case_sensitive: bool },
+    Numerical { value: f64, tolerance: f64 },
+    Essay,
+    Description,
+}
+
+pub struct MoodleQuestion {
+    pub name: String,
+    /// Question text in the rich text subset; the body of a description.
+    pub text: String,
+    pub kind: Kind,
+    pub points: Option<u32>,
+    pub image: Option<Image>,
+}
+
+/// Moodle grades a question out of its `defaultgrade`, 1 unless changed, where slides score
+/// points out of `DEFAULT_POINTS`.
+fn points_from_grade(grade: f64) -> Option<u32> {
+    if !grade.is_finite() || grade <= 0.0 || grade == 1.0 {
+        return None;
+    }
+
+    Some((grade * DEFAULT_POINTS as f64).round() as u32)
+}
+
+fn grade_from_points(points: Option<u32>) -> f64 {
+    points.map_or(1.0, |points| points as f64 / DEFAULT_POINTS as f64)
+}
+
+/// Moodle's MathJax filter reads `\(...\)` for inline math, not `$...$`.
+fn to_moodle_math(text: &str) -> String {
+    replace_math(text, |tex, display| {
+        if display { format!("$${}$$", tex) } else { format!("\\({}\\)", tex) }
+    })
+}
+
+/// Turns `\(...\)` and `\[...\]` back into `$...$` and `$$...$$`.
+fn from_moodle_math(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("\\(").into_iter().chain(rest.find("\\[")).min() {
+        let display = rest[start..].starts_with("\\[");
+        let close = if display { "\\]" } else { "\\)" };
+
+        let end = match rest[start + 2..].find(close) {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+
+        let delimiter = if display { "$$" } else { "$" };
+
+        out.push_str(&rest[..start]);
+        out.push_str(delimiter);
+        out.push_str(rest[start + 2..end].trim());
+        out.push_str(delimiter);
+        rest = &rest[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Plain text from Moodle's HTML question text: tags dropped, breaks kept and entities decoded.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+
+        let tag = rest[start + 1..end].trim_start_matches('/').to_ascii_lowercase();
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+
+        if matches!(name, "br" | "p" | "div" | "li" | "tr") && !text.ends_with('\n') && !text.is_empty() {
+            text.push('\n');
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    text.push_str(rest);
+    decode_entities(&text).trim().to_string()
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';').filter(|end| *end <= 10) {
+            Some(end) => end,
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let decoded = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            entity => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Question text as read from a file in `format` (`html`, `markdown`, `plain` or `moodle`).
+fn import_text(text: &str, format: Option<&str>) -> String {
+    let text = match format {
+        Some("html") => html_to_text(text),
+        _ => text.trim().to_string(),
+    };
+
+    from_moodle_math(&text)
+}
+
+impl MoodleQuestion {
+    /// A name for the question from the start of its text.
+    fn name_from(text: &str) -> String {
+        let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if line.chars().count() <= NAME_MAX_LENGTH {
+            return line;
+        }
+
+        let mut name: String = line.chars().take(NAME_MAX_LENGTH - 3).collect();
+        name.push_str("...");
+        name
+    }
+
+    /// The question for a slide, or why the slide has no Moodle equivalent.
+    pub fn from_slide(slide: &Slide) -> Result<MoodleQuestion, &'static str> {
+        let question = |text: &str, kind: Kind, points: Option<u32>| MoodleQuestion {
+            name: MoodleQuestion::name_from(text),
+            text: text.to_string(),
+            kind,
+            points,
+            image: None,
+        };
+
+        Ok(match slide {
+            Slide::Question(slide) => question(&slide.question, Kind::MultipleChoice {
+                answers: slide.answers.clone().unwrap_or_default(),
+                correct: slide.correct_answers.clone().unwrap_or_default(),
+            }, slide.points),
+            Slide::TrueOrFalse(slide) => {
+                let answers = slide.answers.as_deref().unwrap_or_default();
+                let correct = slide.correct_answers.as_deref().unwrap_or_default();
+                let correct_index = correct.iter().position(|correct| *correct).unwrap_or(0);
+
+                // The answers are only labels: a correct "True" or "False" says which it is, and
+                // otherwise the first answer stands for true.
+                let truth = match answers.get(correct_index).map(|answer| answer.trim().to_lowercase()).as_deref() {
+                    Some("true") => true,
+                    Some("false") => false,
+                    _ => correct_index == 0,
+                };
+
+                question(&slide.question, Kind::TrueFalse(truth), slide.points)
+            }
+            Slide::ShortAnswer(slide) => question(&slide.question, Kind::ShortAnswer {
+                answers: slide.accepted_answers.clone(),
+                case_sensitive: slide.case_sensitive,
+            }, slide.points),
+            Slide::Numerical(slide) => question(&slide.question, Kind::Numerical {
+                value: slide.correct_value,
+                tolerance: slide.tolerance,
+            }, slide.points),
+            Slide::OpenEnded(slide) => question(&slide.question, Kind::Essay, None),
+            Slide::Content(slide) => MoodleQuestion {
+                name: if slide.title.trim().is_empty() { MoodleQuestion::name_from(&slide.body) } else { slide.title.clone() },
+                text: slide.body.clone(),
+                kind: Kind::Description,
+                points: None,
+                image: None,
+            },
+            Slide::BankItem(_) => return Err("bank questions that no longer exist can't be exported"),
+            Slide::RandomDraw(_) => return Err("random draws from the question bank can't be exported"),
+        })
+    }
+
+    pub fn into_slide(self) -> Slide {
+        let theme = "default".to_string();
+        let image_reveal = "none".to_string();
+
+        match self.kind {
+            Kind::MultipleChoice { answers, correct } => Slide::Question(SlideQuizQuestion {
+                theme,
+                time_limit: None,
+                points: self.points,
+                answer_options: if correct.iter().filter(|correct| **correct).count() > 1 { "multiple" } else { "single" }.to_string(),
+                image_reveal,
+                image_path: String::new(),
+                clip: None,
+                question: self.text,
+                answers: Some(answers),
+                correct_answers: Some(correct),
+            }),
+            Kind::TrueFalse(truth) => Slide::TrueOrFalse(SlideQuizTrueOrFalse {
+                theme,
+                time_limit: None,
+                points: self.points,
+                image_reveal,
+                image_path: String::new(),
+                clip: None,
+                question: self.text,
+                answers: Some(vec!["True".to_string(), "False".to_string()]),
+                correct_answers: Some(vec![truth, !truth]),
+            }),
+            Kind::ShortAnswer { answers, case_sensitive } => Slide::ShortAnswer(SlideShortAnswer {
+                theme,
+                time_limit: None,
+                points: self.points,
+                image_reveal,
+                image_path: String::new(),
+                clip: None,
+                question: self.text,
+                accepted_answers: answers,
+                case_sensitive,
+            }),
+            Kind::Numerical { value, tolerance } => Slide::Numerical(SlideNumerical {
+                theme,
+                time_limit: None,
+                points: self.points,
+                image_reveal,
+                image_path: String::new(),
+                clip: None,
+                question: self.text,
+                correct_value: value,
+                tolerance,
+            }),
+            Kind::Essay => Slide::OpenEnded(SlideOpenEnded {
+                theme,
+                time_limit: None,
+                image_reveal,
+                image_path: String::new(),
+                clip: None,
+                question: self.text,
+                max_length: None,
+            }),
+            Kind::Description => Slide::Content(SlideContent {
+                theme,
+                title: self.name,
+                body: self.text,
+                image_path: None,
+            }),
+        }
+    }
+}
+
+/// Collects `questions`, by their position among the file's `count`, into slides, setting
+/// embedded images aside.
+fn imported(count: usize, questions: Vec<(usize, MoodleQuestion)>, warnings: Vec<Warning>) -> Imported {
+    let mut slides = Vec::with_capacity(questions.len());
+    let mut positions = Vec::with_capacity(questions.len());
+    let mut images = Vec::new();
+
+    for (position, mut question) in questions {
+        if let Some(image) = question.image.take() {
+            images.push((slides.len(), image));
+        }
+
+        slides.push(question.into_slide());
+        positions.push(position);
+    }
+
+    Imported { count, slides, positions, images, warnings }
+}
+
+/// Warnings about a slide's parts that no Moodle format keeps.
+fn slide_warnings(index: usize, slide: &Slide, warnings: &mut Vec<Warning>) {
+    if slide.clip().is_some() {
+        warnings.push(Warning { index, message: "the audio or video clip is not exported".to_string() });
+    }
+
+    if slide.reveal_mode().is_some() {
+        warnings.push(Warning { index, message: "the image reveal is not exported".to_string() });
+    }
+}
@@ -0,0 +1,571 @@
+//! GIFT, Moodle's plain text format: one question per paragraph, answers in braces.
+//!
+//! ```text
+//! ::Capital::What is the capital of France? {=Paris ~Lyon ~Marseille}
+//! ```
+
+use super::{import_text, imported, slide_warnings, to_moodle_math, Kind, MoodleQuestion};
+use crate::libraries::interchange::{Imported, Warning};
+use crate::models::slide::Slide;
+
+/// Characters with a meaning in GIFT, written with a backslash when they are part of the text.
+const SPECIAL: &[char] = &['~', '=', '#', '{', '}', ':'];
+
+const FORMATS: &[&str] = &["html", "markdown", "plain", "moodle"];
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if SPECIAL.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.peek().copied() {
+            Some('n') => {
+                out.push('\n');
+                chars.next();
+            }
+            Some(next) if next == '\\' || SPECIAL.contains(&next) => {
+                out.push(next);
+                chars.next();
+            }
+            _ => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// Byte offsets of the unescaped occurrences of `targets` in `text`.
+fn unescaped(text: &str, targets: &[char]) -> Vec<(usize, char)> {
+    let mut found = Vec::new();
+    let mut escaped = false;
+
+    for (index, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if targets.contains(&c) {
+            found.push((index, c));
+        }
+    }
+
+    found
+}
+
+fn find(text: &str, target: char) -> Option<usize> {
+    unescaped(text, &[target]).first().map(|(index, _)| *index)
+}
+
+/// Where an unescaped run of `pattern` (e.g. `::` or `####`) starts.
+fn find_run(text: &str, target: char, pattern: &str) -> Option<usize> {
+    unescaped(text, &[target])
+        .into_iter()
+        .map(|(index, _)| index)
+        .find(|index| text[*index..].starts_with(pattern))
+}
+
+/// Splits on blank lines, dropping comment lines and category markers.
+fn blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("//") || trimmed.starts_with("$CATEGORY:") {
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            if !current.trim().is_empty() {
+                blocks.push(std::mem::take(&mut current));
+            }
+
+            current.clear();
+            continue;
+        }
+
+        current.push_str(line);
+        current.push('\n');
+    }
+
+    if !current.trim().is_empty() {
+        blocks.push(current);
+    }
+
+    blocks
+}
+
+/// A value and tolerance from `3.14`, `3.14:0.01` or `3..4`.
+fn parse_number(value: &str) -> Option<(f64, f64)> {
+    let value = value.trim();
+
+    if let Some((low, high)) = value.split_once("..") {
+        let (low, high): (f64, f64) = (low.trim().parse().ok()?, high.trim().parse().ok()?);
+
+        return Some(((low + high) / 2.0, (high - low).abs() / 2.0));
+    }
+
+    match value.split_once(':') {
+        Some((value, tolerance)) => Some((value.trim().parse().ok()?, tolerance.trim().parse::<f64>().ok()?.abs())),
+        None => Some((value.parse().ok()?, 0.0)),
+    }
+}
+
+/// One `=` or `~` answer: its marker, `%weight%` if given, text and whether it had feedback.
+struct Answer {
+    marker: char,
+    weight: Option<f64>,
+    text: String,
+    has_feedback: bool,
+}
+
+fn parse_answer(marker: char, content: &str) -> Answer {
+    let mut content = content.trim();
+    let mut weight = None;
+
+    if let Some(rest) = content.strip_prefix('%') {
+        if let Some((value, rest)) = rest.split_once('%') {
+            weight = value.trim().parse().ok();
+            content = rest;
+        }
+    }
+
+    let (text, has_feedback) = match find(content, '#') {
+        Some(index) => (&content[..index], !content[index + 1..].trim().is_empty()),
+        None => (content, false),
+    };
+
+    Answer {
+        marker,
+        weight,
+        text: text.trim().to_string(),
+        has_feedback,
+    }
+}
+
+/// `{T}`, `{FALSE}` and so on: the truth and whether there was feedback.
+fn parse_true_false(body: &str) -> Option<(bool, bool)> {
+    let answer = parse_answer('=', body);
+
+    match answer.text.to_uppercase().as_str() {
+        "T" | "TRUE" => Some((true, answer.has_feedback)),
+        "F" | "FALSE" => Some((false, answer.has_feedback)),
+        _ => None,
+    }
+}
+
+/// Reads the part between the braces. Pushes a warning and returns `None` for question kinds
+/// without a slide equivalent.
+fn parse_kind(body: &str, format: Option<&str>, index: usize, warnings: &mut Vec<Warning>) -> Option<Kind> {
+    let mut body = body.trim();
+    let mut has_feedback = false;
+
+    if let Some(general) = find_run(body, '#', "####") {
+        has_feedback |= !body[general + 4..].trim().is_empty();
+        body = body[..general].trim();
+    }
+
+    let warn = |warnings: &mut Vec<Warning>, message: &str| warnings.push(Warning { index, message: message.to_string() });
+
+    let kind = if body.is_empty() {
+        Kind::Essay
+    } else if let Some(numbers) = body.strip_prefix('#') {
+        let markers = unescaped(numbers, &['=']);
+
+        let answers: Vec<Answer> = if markers.is_empty() {
+            vec![parse_answer('=', numbers)]
+        } else {
+            markers
+                .iter()
+                .enumerate()
+                .map(|(position, (start, marker))| {
+                    let end = markers.get(position + 1).map_or(numbers.len(), |(end, _)| *end);
+
+                    parse_answer(*marker, &numbers[start + 1..end])
+                })
+                .collect()
+        };
+
+        has_feedback |= answers.iter().any(|answer| answer.has_feedback);
+
+        if answers.len() > 1 {
+            warn(warnings, "only the first fully correct numerical answer is imported");
+        }
+
+        let number = answers
+            .iter()
+            .filter(|answer| answer.weight.is_none_or(|weight| weight >= 100.0))
+            .find_map(|answer| parse_number(&answer.text));
+
+        match number {
+            Some((value, tolerance)) => Kind::Numerical { value, tolerance },
+            None => {
+                warn(warnings, "has no numerical answer that can be read");
+                return None;
+            }
+        }
+    } else if let Some((truth, feedback)) = parse_true_false(body) {
+        has_feedback |= feedback;
+
+        Kind::TrueFalse(truth)
+    } else {
+        let markers = unescaped(body, &['=', '~']);
+
+        if markers.first().is_none_or(|(start, _)| !body[..*start].trim().is_empty()) {
+            warn(warnings, "has answers in a format that isn't supported");
+            return None;
+        }
+
+        let answers: Vec<Answer> = markers
+            .iter()
+            .enumerate()
+            .map(|(position, (start, marker))| {
+                let end = markers.get(position + 1).map_or(body.len(), |(end, _)| *end);
+
+                parse_answer(*marker, &body[start + 1..end])
+            })
+            .collect();
+
+        if answers.iter().any(|answer| answer.text.contains("->")) {
+            warn(warnings, "matching questions aren't supported");
+            return None;
+        }
+
+        has_feedback |= answers.iter().any(|answer| answer.has_feedback);
+
+        let text = |answer: &Answer| import_text(&unescape(&answer.text), format);
+
+        if answers.iter().any(|answer| answer.marker == '~') {
+            let correct: Vec<bool> = answers
+                .iter()
+                .map(|answer| answer.weight.map_or(answer.marker == '=', |weight| weight > 0.0))
+                .collect();
+
+            let credit: f64 = answers.iter().filter_map(|answer| answer.weight).filter(|weight| *weight > 0.0).sum();
+
+            if answers.iter().any(|answer| answer.weight.is_some()) && (credit - 100.0).abs() > 0.1 {
+                warn(warnings, "partial credit isn't supported; every answer with credit counts as correct");
+            }
+
+            Kind::MultipleChoice {
+                answers: answers.iter().map(text).collect(),
+                correct,
+            }
+        } else {
+            let (accepted, partial): (Vec<&Answer>, Vec<&Answer>) = answers
+                .iter()
+                .partition(|answer| answer.weight.is_none_or(|weight| weight >= 100.0));
+
+            if !partial.is_empty() {
+                warn(warnings, "short answers with partial credit are left out");
+            }
+
+            Kind::ShortAnswer {
+                answers: accepted.into_iter().map(text).collect(),
+                case_sensitive: false,
+            }
+        }
+    };
+
+    if has_feedback {
+        warn(warnings, "feedback isn't imported");
+    }
+
+    Some(kind)
+}
+
+fn parse_question(block: &str, index: usize, warnings: &mut Vec<Warning>) -> Option<MoodleQuestion> {
+    let mut text = block.trim();
+    let mut name = String::new();
+
+    if let Some(rest) = text.strip_prefix("::") {
+        if let Some(end) = find_run(rest, ':', "::") {
+            name = unescape(rest[..end].trim());
+            text = rest[end + 2..].trim_start();
+        }
+    }
+
+    let mut format = None;
+
+    if let Some(rest) = text.strip_prefix('[') {
+        if let Some((marker, rest)) = rest.split_once(']') {
+            if FORMATS.contains(&marker) {
+                format = Some(marker);
+                text = rest;
+            }
+        }
+    }
+
+    let open = match find(text, '{') {
+        Some(open) => open,
+        None => {
+            return Some(MoodleQuestion {
+                name,
+                text: import_text(&unescape(text), format),
+                kind: Kind::Description,
+                points: None,
+                image: None,
+            });
+        }
+    };
+
+    let close = match unescaped(&text[open..], &['}']).last() {
+        Some((close, _)) => open + close,
+        None => {
+            warnings.push(Warning { index, message: "has an answer list that is never closed".to_string() });
+            return None;
+        }
+    };
+
+    // Text on both sides of the answers is a fill-in-the-blank question.
+    let (before, after) = (text[..open].trim(), text[close + 1..].trim());
+    let question = if after.is_empty() { before.to_string() } else { format!("{} _____ {}", before, after) };
+    let question = import_text(&unescape(&question), format);
+
+    let kind = parse_kind(&text[open + 1..close], format, index, warnings)?;
+
+    Some(MoodleQuestion {
+        name: if name.is_empty() { MoodleQuestion::name_from(&question) } else { name },
+        text: question,
+        kind,
+        points: None,
+        image: None,
+    })
+}
+
+pub fn import(text: &str) -> Imported {
+    let mut warnings = Vec::new();
+
+    let blocks = blocks(text);
+
+    let questions = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, block)| parse_question(block, index, &mut warnings).map(|question| (index, question)))
+        .collect();
+
+    imported(blocks.len(), questions, warnings)
+}
+
+fn weight(weight: f64) -> String {
+    let weight = format!("{:.5}", weight);
+
+    weight.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn write_question(out: &mut String, question: &MoodleQuestion) {
+    let text = |text: &str| escape(&to_moodle_math(text));
+
+    if !question.name.is_empty() {
+        out.push_str(&format!("::{}::", escape(&question.name)));
+    }
+
+    out.push_str("[markdown]");
+    out.push_str(&text(&question.text));
+
+    let answers = match &question.kind {
+        Kind::Description => {
+            out.push('\n');
+            return;
+        }
+        Kind::MultipleChoice { answers, correct } => {
+            let correct_count = correct.iter().filter(|correct| **correct).count();
+            let wrong_count = answers.len() - correct_count.min(answers.len());
+
+            answers
+                .iter()
+                .zip(correct.iter().chain(std::iter::repeat(&false)))
+                .map(|(answer, correct)| match (correct_count, *correct) {
+                    (1, true) => format!("\n\t={}", text(answer)),
+                    (1, false) => format!("\n\t~{}", text(answer)),
+                    // Several correct answers split the credit, and wrong ones take it away.
+                    (_, true) => format!("\n\t~%{}%{}", weight(100.0 / correct_count as f64), text(answer)),
+                    (_, false) => format!("\n\t~%{}%{}", weight(-100.0 / wrong_count.max(1) as f64), text(answer)),
+                })
+                .collect::<String>()
+                + "\n"
+        }
+        Kind::TrueFalse(truth) => if *truth { "T" } else { "F" }.to_string(),
+        Kind::ShortAnswer { answers, .. } => answers.iter().map(|answer| format!("={}", text(answer))).collect::<Vec<_>>().join(" "),
+        Kind::Numerical { value, tolerance } => format!("#{}:{}", value, tolerance),
+        Kind::Essay => String::new(),
+    };
+
+    out.push_str(&format!(" {{{}}}\n", answers));
+}
+
+/// The slides as GIFT, with warnings for what GIFT can't hold.
+pub fn export(slides: &[Slide]) -> (String, Vec<Warning>) {
+    let mut out = String::new();
+    let mut warnings = Vec::new();
+
+    for (index, slide) in slides.iter().enumerate() {
+        let question = match MoodleQuestion::from_slide(slide) {
+            Ok(question) => question,
+            Err(reason) => {
+                warnings.push(Warning { index, message: reason.to_string() });
+                continue;
+            }
+        };
+
+        slide_warnings(index, slide, &mut warnings);
+
+        let mut warn = |message: &str| warnings.push(Warning { index, message: message.to_string() });
+
+        if slide.image_path().is_some() {
+            warn("GIFT can't hold images; export Moodle XML to keep them");
+        }
+
+        if question.points.is_some() {
+            warn("GIFT has no grades, so the points are not exported");
+        }
+
+        if matches!(question.kind, Kind::ShortAnswer { case_sensitive: true, .. }) {
+            warn("GIFT short answers are never case sensitive");
+        }
+
+        write_question(&mut out, &question);
+        out.push('\n');
+    }
+
+    (out, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(block: &str) -> (Option<MoodleQuestion>, Vec<Warning>) {
+        let mut warnings = Vec::new();
+        let question = parse_question(block, 0, &mut warnings);
+
+        (question, warnings)
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let text = "a = b ~ {c} #1: \\ done\nnext";
+
+        assert_eq!(escape("{=}"), "\\{\\=\\}");
+        assert_eq!(unescape(&escape(text)), text);
+        assert_eq!(unescaped("\\= = \\\\~", &['=', '~']), vec![(3, '='), (7, '~')]);
+    }
+
+    #[test]
+    fn splits_questions_on_blank_lines() {
+        let text = "// comment\n$CATEGORY: $course$/Quiz\n::A:: One {T}\n\n\n::B:: Two\n{F}\n";
+
+        assert_eq!(blocks(text), vec!["::A:: One {T}\n".to_string(), "::B:: Two\n{F}\n".to_string()]);
+    }
+
+    #[test]
+    fn reads_multiple_choice() {
+        let (question, warnings) = parse("::Capital::What is the capital of \\{France\\}? {=Paris ~Lyon#No ~Marseille}");
+        let question = question.unwrap();
+
+        assert_eq!(question.name, "Capital");
+        assert_eq!(question.text, "What is the capital of {France}?");
+        assert!(matches!(&question.kind, Kind::MultipleChoice { answers, correct }
+            if answers == &["Paris", "Lyon", "Marseille"] && correct == &[true, false, false]));
+        assert_eq!(warnings.iter().map(|warning| warning.message.as_str()).collect::<Vec<_>>(), ["feedback isn't imported"]);
+
+        let (question, warnings) = parse("Pick two {~%50%A ~%50%B ~%-100%C}");
+
+        assert!(matches!(question.unwrap().kind, Kind::MultipleChoice { correct, .. } if correct == [true, true, false]));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn reads_true_false_numerical_and_short_answers() {
+        assert!(matches!(parse("The sky is blue. {TRUE}").0.unwrap().kind, Kind::TrueFalse(true)));
+        assert!(matches!(parse("Two is odd. {F}").0.unwrap().kind, Kind::TrueFalse(false)));
+
+        let numerical = |block: &str| match parse(block).0.unwrap().kind {
+            Kind::Numerical { value, tolerance } => (value, tolerance),
+            _ => panic!("{} is not numerical", block),
+        };
+
+        assert_eq!(numerical("Litres? {#1.5:0.01}"), (1.5, 0.01));
+        assert_eq!(numerical("Between? {#1..3}"), (2.0, 1.0));
+        assert_eq!(numerical("Exactly? {#=%50%4 =%100%5}"), (5.0, 0.0));
+
+        let (question, warnings) = parse("Who's buried in Grant's tomb? {=Grant =Ulysses S. Grant =%50%Ulysses}");
+
+        assert!(matches!(question.unwrap().kind, Kind::ShortAnswer { answers, case_sensitive: false }
+            if answers == ["Grant", "Ulysses S. Grant"]));
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn reads_blanks_essays_and_descriptions() {
+        let (blank, _) = parse("Moodle costs {=nothing} to download.");
+
+        assert_eq!(blank.unwrap().text, "Moodle costs _____ to download.");
+        assert!(matches!(parse("Describe your day. {}").0.unwrap().kind, Kind::Essay));
+        assert!(matches!(parse("Just some text.").0.unwrap().kind, Kind::Description));
+    }
+
+    #[test]
+    fn warns_about_what_it_leaves_out() {
+        for block in [
+            "Match these. {=a -> 1 =b -> 2}",
+            "Never closed {=a ~b",
+            "Unreadable {#abc}",
+            "Loose text {before =a ~b}",
+        ] {
+            let (question, warnings) = parse(block);
+
+            assert!(question.is_none(), "{} should be left out", block);
+            assert_eq!(warnings.len(), 1, "{} should be warned about once", block);
+        }
+
+        let imported = import("Kept {T}\n\nMatch these. {=a -> 1 =b -> 2}\n");
+
+        assert_eq!((imported.count, imported.slides.len(), imported.positions), (2, 1, vec![0]));
+        assert_eq!(imported.warnings[0].index, 1);
+    }
+
+    #[test]
+    fn exports_what_it_imports() {
+        let text = "::Capital::What is the capital of France? {=Paris ~Lyon}\n\n\
+                    Pick two {~%50%A ~%50%B ~%-100%C}\n\n\
+                    Is \\{this\\} true? {T}\n\n\
+                    Litres? {#1.5:0.01}\n\n\
+                    Name it {=Grant =Ulysses S. Grant}\n";
+
+        let first = import(text);
+        let (exported, warnings) = export(&first.slides);
+        let second = import(&exported);
+
+        assert!(first.warnings.is_empty() && warnings.is_empty() && second.warnings.is_empty());
+        assert_eq!(second.slides.len(), 5);
+
+        for (first, second) in first.slides.iter().zip(&second.slides) {
+            assert_eq!(first.to_json(), second.to_json());
+        }
+    }
+}
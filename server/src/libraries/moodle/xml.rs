@@ -0,0 +1,367 @@
+//! Moodle XML, the format of Moodle's question bank exports. Unlike GIFT it carries grades and
+//! embeds images, which are written as `@@PLUGINFILE@@` files next to the question text.
+
+use std::collections::HashMap;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+
+use super::{grade_from_points, import_text, imported, points_from_grade, slide_warnings, to_moodle_math, Kind, MoodleQuestion};
+use crate::libraries::interchange::{Image, Imported, Warning};
+use crate::models::slide::Slide;
+
+const PLUGINFILE: &str = "@@PLUGINFILE@@/";
+
+#[derive(Deserialize)]
+struct XmlQuiz {
+    #[serde(default)]
+    question: Vec<XmlQuestion>,
+}
+
+#[derive(Deserialize, Default)]
+struct XmlText {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize, Default)]
+struct XmlFormattedText {
+    #[serde(rename = "@format")]
+    format: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    file: Vec<XmlFile>,
+}
+
+#[derive(Deserialize)]
+struct XmlFile {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@encoding")]
+    encoding: Option<String>,
+    #[serde(rename = "$text", default)]
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct XmlAnswer {
+    #[serde(rename = "@fraction")]
+    fraction: Option<String>,
+    #[serde(rename = "@format")]
+    format: Option<String>,
+    #[serde(default)]
+    text: String,
+    tolerance: Option<String>,
+    feedback: Option<XmlFormattedText>,
+}
+
+impl XmlAnswer {
+    fn fraction(&self) -> f64 {
+        self.fraction.as_deref().and_then(|fraction| fraction.trim().parse().ok()).unwrap_or(0.0)
+    }
+}
+
+#[derive(Deserialize)]
+struct XmlQuestion {
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(default)]
+    name: XmlText,
+    #[serde(default)]
+    questiontext: XmlFormattedText,
+    generalfeedback: Option<XmlFormattedText>,
+    defaultgrade: Option<String>,
+    single: Option<String>,
+    usecase: Option<String>,
+    #[serde(default)]
+    answer: Vec<XmlAnswer>,
+}
+
+fn is_true(value: Option<&str>) -> bool {
+    matches!(value.map(str::trim), Some("true" | "1"))
+}
+
+/// Removes references to embedded files from question text, since the image moves to the slide's
+/// `image_path`: Markdown `![...](@@PLUGINFILE@@/...)` here, HTML `<img>` tags go with the rest
+/// of the HTML.
+fn strip_file_references(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("![") {
+        let reference = rest[start..]
+            .find("](")
+            .map(|middle| start + middle + 2)
+            .filter(|target| rest[*target..].starts_with(PLUGINFILE))
+            .and_then(|target| rest[target..].find(')').map(|end| target + end + 1));
+
+        match reference {
+            Some(end) => {
+                out.push_str(&rest[..start]);
+                rest = &rest[end..];
+            }
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn parse_question(question: XmlQuestion, index: usize, warnings: &mut Vec<Warning>) -> Option<MoodleQuestion> {
+    let mut warn = |message: String| warnings.push(Warning { index, message });
+
+    let format = question.questiontext.format.as_deref();
+    let text = import_text(&strip_file_references(&question.questiontext.text), format);
+    let answer_text = |answer: &XmlAnswer| import_text(&answer.text, answer.format.as_deref().or(Some("plain")));
+
+    let kind = match question.kind.as_str() {
+        "multichoice" => {
+            let credit: f64 = question.answer.iter().map(XmlAnswer::fraction).filter(|fraction| *fraction > 0.0).sum();
+
+            if (credit - 100.0).abs() > 0.1 && is_true(question.single.as_deref()) == (credit > 100.0) {
+                warn("partial credit isn't supported; every answer with credit counts as correct".to_string());
+            }
+
+            Kind::MultipleChoice {
+                answers: question.answer.iter().map(answer_text).collect(),
+                correct: question.answer.iter().map(|answer| answer.fraction() > 0.0).collect(),
+            }
+        }
+        "truefalse" => {
+            let correct = question.answer.iter().max_by(|a, b| a.fraction().total_cmp(&b.fraction()));
+
+            match correct.map(|answer| answer.text.trim().to_lowercase()).as_deref() {
+                Some("true") => Kind::TrueFalse(true),
+                Some("false") => Kind::TrueFalse(false),
+                _ => {
+                    warn("has no true or false answer".to_string());
+                    return None;
+                }
+            }
+        }
+        "shortanswer" => {
+            let (accepted, partial): (Vec<&XmlAnswer>, Vec<&XmlAnswer>) =
+                question.answer.iter().partition(|answer| answer.fraction() >= 100.0);
+
+            if !partial.is_empty() {
+                warn("short answers with partial credit are left out".to_string());
+            }
+
+            Kind::ShortAnswer {
+                answers: accepted.into_iter().map(answer_text).collect(),
+                case_sensitive: is_true(question.usecase.as_deref()),
+            }
+        }
+        "numerical" => {
+            if question.answer.len() > 1 {
+                warn("only the first fully correct numerical answer is imported".to_string());
+            }
+
+            let number = question
+                .answer
+                .iter()
+                .filter(|answer| answer.fraction() >= 100.0)
+                .find_map(|answer| {
+                    let value = answer.text.trim().parse::<f64>().ok()?;
+                    let tolerance = answer.tolerance.as_deref().map_or(Some(0.0), |tolerance| tolerance.trim().parse::<f64>().ok())?;
+
+                    Some((value, tolerance.abs()))
+                });
+
+            match number {
+                Some((value, tolerance)) => Kind::Numerical { value, tolerance },
+                None => {
+                    warn("has no numerical answer that can be read".to_string());
+                    return None;
+                }
+            }
+        }
+        "essay" => Kind::Essay,
+        "description" => Kind::Description,
+        kind => {
+            warn(format!("{} questions aren't supported", kind));
+            return None;
+        }
+    };
+
+    let has_feedback = question.generalfeedback.iter().chain(question.answer.iter().filter_map(|answer| answer.feedback.as_ref()))
+        .any(|feedback| !feedback.text.trim().is_empty());
+
+    if has_feedback {
+        warn("feedback isn't imported".to_string());
+    }
+
+    let images: Vec<Image> = question
+        .questiontext
+        .file
+        .iter()
+        .filter(|file| file.encoding.as_deref().is_none_or(|encoding| encoding == "base64"))
+        .filter_map(|file| {
+            let data: String = file.data.split_whitespace().collect();
+
+            BASE64.decode(data).ok().map(|bytes| Image { name: file.name.clone(), bytes })
+        })
+        .collect();
+
+    if images.len() > 1 {
+        warn("only the first embedded image is imported".to_string());
+    }
+
+    Some(MoodleQuestion {
+        name: question.name.text.trim().to_string(),
+        text,
+        kind,
+        points: question.defaultgrade.as_deref().and_then(|grade| grade.trim().parse().ok()).and_then(points_from_grade),
+        image: images.into_iter().next(),
+    })
+}
+
+pub fn import(text: &str) -> Result<Imported, &'static str> {
+    let quiz: XmlQuiz = quick_xml::de::from_str(text).map_err(|_| "is not a Moodle XML question file")?;
+    let mut warnings = Vec::new();
+
+    // Category entries only say where the questions go in Moodle's bank.
+    let entries: Vec<XmlQuestion> = quiz.question.into_iter().filter(|question| question.kind != "category").collect();
+    let count = entries.len();
+
+    let questions = entries
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, question)| parse_question(question, index, &mut warnings).map(|question| (index, question)))
+        .collect();
+
+    Ok(imported(count, questions, warnings))
+}
+
+fn escape(text: &str) -> String {
+    quick_xml::escape::escape(text).into_owned()
+}
+
+/// Text as CDATA, splitting any `]]>` it contains across two sections.
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+fn fraction(fraction: f64) -> String {
+    let fraction = format!("{:.5}", fraction);
+
+    fraction.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn write_answer(out: &mut String, fraction_value: f64, text: &str, extra: &str) {
+    out.push_str(&format!(
+        "    <answer fraction=\"{}\" format=\"markdown\">\n      <text>{}</text>\n{}    </answer>\n",
+        fraction(fraction_value),
+        cdata(text),
+        extra,
+    ));
+}
+
+fn write_question(out: &mut String, question: &MoodleQuestion) {
+    let kind = match question.kind {
+        Kind::MultipleChoice { .. } => "multichoice",
+        Kind::TrueFalse(_) => "truefalse",
+        Kind::ShortAnswer { .. } => "shortanswer",
+        Kind::Numerical { .. } => "numerical",
+        Kind::Essay => "essay",
+        Kind::Description => "description",
+    };
+
+    let mut text = to_moodle_math(&question.text);
+
+    if let Some(image) = &question.image {
+        text.push_str(&format!("\n\n![]({}{})", PLUGINFILE, image.name));
+    }
+
+    out.push_str(&format!("  <question type=\"{}\">\n", kind));
+    out.push_str(&format!("    <name>\n      <text>{}</text>\n    </name>\n", escape(&question.name)));
+    out.push_str(&format!("    <questiontext format=\"markdown\">\n      <text>{}</text>\n", cdata(&text)));
+
+    if let Some(image) = &question.image {
+        out.push_str(&format!(
+            "      <file name=\"{}\" path=\"/\" encoding=\"base64\">{}</file>\n",
+            escape(&image.name),
+            BASE64.encode(&image.bytes),
+        ));
+    }
+
+    out.push_str("    </questiontext>\n");
+    out.push_str(&format!("    <defaultgrade>{}</defaultgrade>\n", fraction(grade_from_points(question.points))));
+
+    let answer = |text: &str| to_moodle_math(text);
+
+    match &question.kind {
+        Kind::MultipleChoice { answers, correct } => {
+            let correct_count = correct.iter().filter(|correct| **correct).count();
+            let wrong_count = answers.len() - correct_count.min(answers.len());
+
+            out.push_str(&format!("    <single>{}</single>\n", correct_count <= 1));
+            out.push_str("    <shuffleanswers>true</shuffleanswers>\n");
+            out.push_str("    <answernumbering>abc</answernumbering>\n");
+
+            for (text, correct) in answers.iter().zip(correct.iter().chain(std::iter::repeat(&false))) {
+                // Several correct answers split the credit, and wrong ones take it away.
+                let fraction = match (correct_count, *correct) {
+                    (_, true) => 100.0 / correct_count as f64,
+                    (0 | 1, false) => 0.0,
+                    (_, false) => -100.0 / wrong_count.max(1) as f64,
+                };
+
+                write_answer(out, fraction, &answer(text), "");
+            }
+        }
+        Kind::TrueFalse(truth) => {
+            write_answer(out, if *truth { 100.0 } else { 0.0 }, "true", "");
+            write_answer(out, if *truth { 0.0 } else { 100.0 }, "false", "");
+        }
+        Kind::ShortAnswer { answers, case_sensitive } => {
+            out.push_str(&format!("    <usecase>{}</usecase>\n", u8::from(*case_sensitive)));
+
+            for text in answers {
+                write_answer(out, 100.0, &answer(text), "");
+            }
+        }
+        Kind::Numerical { value, tolerance } => {
+            write_answer(out, 100.0, &value.to_string(), &format!("      <tolerance>{}</tolerance>\n", tolerance));
+        }
+        Kind::Essay | Kind::Description => {}
+    }
+
+    out.push_str("  </question>\n");
+}
+
+/// The slides as Moodle XML, embedding `images` (by slide index) as files, with warnings for what
+/// Moodle can't hold.
+pub fn export(slides: &[Slide], mut images: HashMap<usize, Image>) -> (String, Vec<Warning>) {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n");
+    let mut warnings = Vec::new();
+
+    for (index, slide) in slides.iter().enumerate() {
+        let mut question = match MoodleQuestion::from_slide(slide) {
+            Ok(question) => question,
+            Err(reason) => {
+                warnings.push(Warning { index, message: reason.to_string() });
+                continue;
+            }
+        };
+
+        slide_warnings(index, slide, &mut warnings);
+
+        question.image = images.remove(&index);
+
+        if slide.image_path().is_some() && question.image.is_none() {
+            warnings.push(Warning { index, message: "the image could not be included".to_string() });
+        }
+
+        write_question(&mut out, &question);
+    }
+
+    out.push_str("</quiz>\n");
+
+    (out, warnings)
+}
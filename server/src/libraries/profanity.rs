@@ -0,0 +1,118 @@
+const BLOCKED_WORDS: &[&str] = &[
+    "fuck", "fucking", "fucker", "shit", "bitch", "bastard", "asshole", "dick", "cunt", "pussy",
+    "slut", "whore", "nigger", "nigga", "faggot", "retard", "motherfucker", "bullshit",
+    "dm", "dcm", "dmm", "vcl", "vkl", "vl", "clgt", "cmm", "dkm", "dmcs",
+];
+
+fn normalize_char(c: char) -> Option<char> {
+    match c {
+        '0' => Some('o'),
+        '1' | '!' | '|' => Some('i'),
+        '3' => Some('e'),
+        '4' | '@' => Some('a'),
+        '5' | '$' => Some('s'),
+        '7' => Some('t'),
+        'à' | 'á' | 'ả' | 'ã' | 'ạ' | 'ă' | 'ằ' | 'ắ' | 'ẳ' | 'ẵ' | 'ặ' | 'â' | 'ầ' | 'ấ' | 'ẩ' | 'ẫ' | 'ậ' => Some('a'),
+        'è' | 'é' | 'ẻ' | 'ẽ' | 'ẹ' | 'ê' | 'ề' | 'ế' | 'ể' | 'ễ' | 'ệ' => Some('e'),
+        'ì' | 'í' | 'ỉ' | 'ĩ' | 'ị' => Some('i'),
+        'ò' | 'ó' | 'ỏ' | 'õ' | 'ọ' | 'ô' | 'ồ' | 'ố' | 'ổ' | 'ỗ' | 'ộ' | 'ơ' | 'ờ' | 'ớ' | 'ở' | 'ỡ' | 'ợ' => Some('o'),
+        'ù' | 'ú' | 'ủ' | 'ũ' | 'ụ' | 'ư' | 'ừ' | 'ứ' | 'ử' | 'ữ' | 'ự' => Some('u'),
+        'ỳ' | 'ý' | 'ỷ' | 'ỹ' | 'ỵ' => Some('y'),
+        'đ' => Some('d'),
+        c if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+fn collapse_repeats(word: &str) -> String {
+    let mut collapsed = String::with_capacity(word.len());
+
+    for c in word.chars() {
+        if !collapsed.ends_with(c) {
+            collapsed.push(c);
+        }
+    }
+
+    collapsed
+}
+
+/// Whether `word` is a blocked word, or its plural, once repeated letters are collapsed.
+fn is_blocked(word: &str) -> bool {
+    let collapsed = collapse_repeats(word);
+
+    BLOCKED_WORDS.iter().any(|blocked| {
+        let blocked = collapse_repeats(blocked);
+
+        [word, collapsed.as_str()]
+            .iter()
+            .any(|candidate| {
+                *candidate == blocked || ["s", "es"].iter().any(|suffix| candidate.strip_suffix(suffix) == Some(blocked.as_str()))
+            })
+    })
+}
+
+/// Returns true when the text contains a blocked word, after undoing common obfuscations
+/// (leetspeak, diacritics, repeated letters, separators between single letters). Only whole
+/// words count, so words that merely contain a blocked one are let through.
+pub fn is_suspicious(text: &str) -> bool {
+    let lowered = text.to_lowercase();
+
+    let words: Vec<String> = lowered
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.' || c == '?' || c == '-' || c == '_' || c == '*')
+        // A trailing "!" ends a sentence rather than standing in for an "i".
+        .map(|word| word.trim_end_matches(['!', '|']).chars().filter_map(normalize_char).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    // "f u c k" spells a word out one letter at a time; join each run of single letters back
+    // together, but never across a longer word.
+    let spelled_out: Vec<String> = words
+        .split(|word| word.chars().count() != 1)
+        .filter(|run| run.len() > 1)
+        .map(|run| run.concat())
+        .collect();
+
+    words.iter().chain(&spelled_out).any(|word| is_blocked(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_suspicious;
+
+    #[test]
+    fn flags_blocked_words() {
+        assert!(is_suspicious("what the fuck"));
+        assert!(is_suspicious("Shit!"));
+        assert!(is_suspicious("bitches"));
+    }
+
+    #[test]
+    fn flags_obfuscated_words() {
+        assert!(is_suspicious("sh1t"));
+        assert!(is_suspicious("fuuuuck this"));
+        assert!(is_suspicious("b.i.t.c.h"));
+        assert!(is_suspicious("you f u c k"));
+        assert!(is_suspicious("đm"));
+    }
+
+    #[test]
+    fn ignores_words_containing_blocked_ones() {
+        assert!(!is_suspicious("Scunthorpe is in Lincolnshire"));
+        assert!(!is_suspicious("a classic assessment"));
+        assert!(!is_suspicious("Dickens wrote Bleak House"));
+        assert!(!is_suspicious("shitake mushrooms"));
+    }
+
+    #[test]
+    fn joins_only_adjacent_single_letters() {
+        // The letters of "dm" are in the message, but never next to each other.
+        assert!(!is_suspicious("d is for dog and m is for moon"));
+        assert!(!is_suspicious("plan a or b, then c u later"));
+    }
+
+    #[test]
+    fn lets_clean_text_through() {
+        assert!(!is_suspicious("The mitochondria is the powerhouse of the cell"));
+        assert!(!is_suspicious(""));
+    }
+}
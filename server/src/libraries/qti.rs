@@ -0,0 +1,492 @@
+//! IMS QTI content packages: a zip of assessment items, an assessment test putting them in order
+//! and an `imsmanifest.xml` listing both. QTI 2.1 and 3.0 are read and written.
+//!
+//! | slide           | QTI item                                            |
+//! |-----------------|-----------------------------------------------------|
+//! | `question`      | `choiceInteraction`                                 |
+//! | `true_or_false` | `choiceInteraction` with choices "True" and "False" |
+//! | `short_answer`  | `textEntryInteraction` with a string response       |
+//! | `numerical`     | `textEntryInteraction` with a float response        |
+//! | `open_ended`    | `extendedTextInteraction`                           |
+//! | `content`       | an item without interactions                        |
+//!
+//! `time_limit` goes on the test's `assessmentItemRef` as `timeLimits maxTime`. `points` is the
+//! item's `MAXSCORE`, counted in marks of `DEFAULT_POINTS` since other tools score questions out
+//! of 1. Slide images are packaged under `media/`.
+//!
+//! QTI 3.0 renamed every element to kebab case with a `qti-` prefix (`qti-choice-interaction`)
+//! and every attribute to kebab case. Files of either version are read into an `Element` tree
+//! with the 2.1 names, and written through `Writer`, which spells names for its version.
+
+pub mod item;
+
+pub mod package;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::libraries::rich_text;
+use crate::models::slide::DEFAULT_POINTS;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Version {
+    V2p1,
+    V3p0,
+}
+
+impl Version {
+    fn namespace(self) -> &'static str {
+        match self {
+            Version::V2p1 => "http://www.imsglobal.org/xsd/imsqti_v2p1",
+            Version::V3p0 => "http://www.imsglobal.org/xsd/imsqtiasi_v3p0",
+        }
+    }
+
+    fn schema_location(self) -> &'static str {
+        match self {
+            Version::V2p1 => "http://www.imsglobal.org/xsd/imsqti_v2p1 http://www.imsglobal.org/xsd/qti/qtiv2p1/imsqti_v2p1p2.xsd",
+            Version::V3p0 => "http://www.imsglobal.org/xsd/imsqtiasi_v3p0 https://purl.imsglobal.org/spec/qti/v3p0/schema/xsd/imsqti_asiv3p0_v1p0.xsd",
+        }
+    }
+
+    /// A QTI element name, given as in 2.1.
+    fn element(self, name: &str) -> String {
+        match self {
+            Version::V2p1 => name.to_string(),
+            Version::V3p0 => format!("qti-{}", kebab_case(name)),
+        }
+    }
+
+    /// A QTI attribute name, given as in 2.1.
+    fn attribute(self, name: &str) -> String {
+        match self {
+            Version::V2p1 => name.to_string(),
+            Version::V3p0 => kebab_case(name),
+        }
+    }
+}
+
+fn kebab_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('-');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+
+    for c in name.chars() {
+        match c {
+            '-' => upper = true,
+            c if upper => {
+                out.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// A name as read from either version: 3.0's `qti-map-entry` and `map-key` become 2.1's
+/// `mapEntry` and `mapKey`. Namespace prefixes are dropped.
+fn normalize_name(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+
+    match name.strip_prefix("qti-") {
+        Some(name) => camel_case(name),
+        None if name.contains('-') => camel_case(&name),
+        None => name.into_owned(),
+    }
+}
+
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |element| element.name == name)
+    }
+
+    /// Every element below this one, depth first.
+    pub fn descendants(&self) -> Vec<&Element> {
+        let mut found = Vec::new();
+
+        for element in self.elements() {
+            found.push(element);
+            found.extend(element.descendants());
+        }
+
+        found
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.descendants().into_iter().find(|element| element.name == name)
+    }
+
+    /// All the text inside, tags dropped.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        for node in &self.children {
+            match node {
+                Node::Element(element) => text.push_str(&element.text()),
+                Node::Text(value) => text.push_str(value),
+            }
+        }
+
+        text
+    }
+}
+
+/// XHTML in question text often uses HTML entities XML doesn't define.
+fn html_entity(entity: &str) -> Option<&'static str> {
+    match entity {
+        "nbsp" => Some("\u{a0}"),
+        "ndash" => Some("\u{2013}"),
+        "mdash" => Some("\u{2014}"),
+        "hellip" => Some("\u{2026}"),
+        "times" => Some("\u{d7}"),
+        "divide" => Some("\u{f7}"),
+        "deg" => Some("\u{b0}"),
+        _ => None,
+    }
+}
+
+/// Parses an XML document into its root element.
+pub fn parse(xml: &str) -> Option<Element> {
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Element> = Vec::new();
+
+    let start = |tag: &quick_xml::events::BytesStart| Element {
+        name: normalize_name(tag.local_name().as_ref()),
+        attributes: tag
+            .attributes()
+            .flatten()
+            .filter(|attribute| attribute.key.as_ref() != b"xmlns" && attribute.key.prefix().is_none_or(|prefix| prefix.as_ref() != b"xmlns"))
+            .map(|attribute| {
+                let raw = String::from_utf8_lossy(&attribute.value);
+                let value = quick_xml::escape::unescape_with(&raw, html_entity).map(|value| value.into_owned()).unwrap_or_default();
+
+                (normalize_name(attribute.key.local_name().as_ref()), value)
+            })
+            .collect(),
+        children: Vec::new(),
+    };
+
+    loop {
+        let node = match reader.read_event().ok()? {
+            Event::Start(tag) => {
+                stack.push(start(&tag));
+                continue;
+            }
+            Event::End(_) => {
+                let element = stack.pop()?;
+
+                if stack.is_empty() {
+                    return Some(element);
+                }
+
+                Node::Element(element)
+            }
+            Event::Empty(tag) => Node::Element(start(&tag)),
+            Event::Text(text) => Node::Text(text.unescape_with(html_entity).ok()?.into_owned()),
+            Event::CData(data) => Node::Text(String::from_utf8_lossy(&data.into_inner()).into_owned()),
+            Event::Eof => return None,
+            _ => continue,
+        };
+
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => {
+                if let Node::Element(element) = node {
+                    return Some(element);
+                }
+            }
+        }
+    }
+}
+
+/// Writes XML with QTI element and attribute names spelled for `version`.
+struct Writer {
+    version: Version,
+    out: String,
+}
+
+impl Writer {
+    fn new(version: Version) -> Writer {
+        Writer { version, out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n") }
+    }
+
+    fn tag(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.out.push('<');
+        self.out.push_str(&self.version.element(name));
+
+        for (key, value) in attributes {
+            let key = if key.contains(':') { key.to_string() } else { self.version.attribute(key) };
+
+            self.out.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+        }
+    }
+
+    fn open(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.tag(name, attributes);
+        self.out.push('>');
+    }
+
+    fn close(&mut self, name: &str) {
+        self.out.push_str(&format!("</{}>", self.version.element(name)));
+    }
+
+    fn empty(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.tag(name, attributes);
+        self.out.push_str("/>");
+    }
+
+    fn text(&mut self, name: &str, attributes: &[(&str, &str)], text: &str) {
+        self.open(name, attributes);
+        self.out.push_str(&escape(text));
+        self.close(name);
+    }
+
+    /// XHTML content, written as is.
+    fn xhtml(&mut self, xhtml: &str) {
+        self.out.push_str(xhtml);
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(text: &str) -> String {
+    quick_xml::escape::escape(text).into_owned()
+}
+
+/// Rich text as XHTML for an item body. QTI's XHTML subset has no `del`, and void elements must
+/// be closed.
+fn to_xhtml(text: &str) -> String {
+    rich_text::render(text)
+        .replace("<br>", "<br/>")
+        .replace("<del>", "<span class=\"strikethrough\">")
+        .replace("</del>", "</span>")
+}
+
+/// Markdown-escapes text so it reads back as itself. Underscores inside words and brackets that
+/// can't start a link are left alone, since those read back as themselves anyway.
+fn escape_markdown(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+
+    for (index, c) in chars.iter().enumerate() {
+        let escape = match c {
+            '\\' | '*' | '`' | '~' | '$' | '<' => true,
+            '_' => {
+                let word = |c: Option<&char>| c.is_some_and(|c| c.is_alphanumeric());
+
+                !(word(index.checked_sub(1).and_then(|before| chars.get(before))) && word(chars.get(index + 1)))
+            }
+            '[' => text[text.char_indices().nth(index).map_or(0, |(offset, _)| offset)..].contains("]("),
+            _ => false,
+        };
+
+        if escape {
+            out.push('\\');
+        }
+
+        out.push(*c);
+    }
+
+    out
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+
+    for c in text.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+                space = false;
+            }
+
+            out.push(c);
+        }
+    }
+
+    if space {
+        out.push(' ');
+    }
+
+    out
+}
+
+/// `marker` around `inner`, keeping surrounding spaces outside so the emphasis still parses.
+fn wrap(out: &mut String, marker: &str, inner: &str) {
+    let core = inner.trim();
+
+    if core.is_empty() {
+        out.push_str(inner);
+        return;
+    }
+
+    if inner.starts_with(' ') {
+        out.push(' ');
+    }
+
+    out.push_str(marker);
+    out.push_str(core);
+    out.push_str(marker);
+
+    if inner.ends_with(' ') {
+        out.push(' ');
+    }
+}
+
+fn has_class(element: &Element, class: &str) -> bool {
+    element.attribute("class").is_some_and(|classes| classes.split_whitespace().any(|name| name == class))
+}
+
+const BLOCKS: &[&str] = &[
+    "p", "div", "blockquote", "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li", "table", "tr",
+    "dl", "dt", "dd", "hr", "prompt", "simpleChoice",
+];
+
+/// The blank an inline interaction leaves in rich text. It is escaped the way `escape_markdown`
+/// would escape it, so an exported blank reads back the same.
+const BLANK: &str = "\\_\\_\\_\\_\\_";
+
+/// Converts item body content back to the rich text subset. Interactions are left to the caller:
+/// inline ones become a `BLANK`, others are skipped. Images are dropped here and picked up
+/// separately.
+fn markdown(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        let element = match node {
+            Node::Text(text) => {
+                out.push_str(&escape_markdown(&collapse_whitespace(text)));
+                continue;
+            }
+            Node::Element(element) => element,
+        };
+
+        let inner = || {
+            let mut inner = String::new();
+            markdown(&element.children, &mut inner);
+            inner
+        };
+
+        match element.name.as_str() {
+            "em" | "i" => wrap(out, "*", &inner()),
+            "strong" | "b" => wrap(out, "**", &inner()),
+            "del" | "s" | "strike" => wrap(out, "~~", &inner()),
+            "span" if has_class(element, "strikethrough") => wrap(out, "~~", &inner()),
+            "span" if has_class(element, "math-display") => out.push_str(&format!("$${}$$", element.text().trim())),
+            "span" if has_class(element, "math") => out.push_str(&format!("${}$", element.text().trim())),
+            "code" => {
+                let code = collapse_whitespace(&element.text());
+                let fence = if code.contains('`') { "``" } else { "`" };
+
+                out.push_str(&format!("{}{}{}", fence, code.trim(), fence));
+            }
+            "pre" => {
+                let language = element
+                    .child("code")
+                    .and_then(|code| code.attribute("class"))
+                    .and_then(|class| class.split_whitespace().find_map(|name| name.strip_prefix("language-")))
+                    .unwrap_or("");
+
+                out.push_str(&format!("\n\n```{}\n{}\n```\n\n", language, element.text().trim_end_matches('\n')));
+            }
+            "br" => out.push_str("\\\n"),
+            "img" | "object" | "feedbackInline" | "feedbackBlock" | "rubricBlock" => {}
+            name if name.ends_with("Interaction") => {
+                if name == "textEntryInteraction" || name == "inlineChoiceInteraction" {
+                    out.push_str(BLANK);
+                }
+            }
+            name if BLOCKS.contains(&name) => {
+                out.push_str("\n\n");
+                markdown(&element.children, out);
+                out.push_str("\n\n");
+            }
+            _ => markdown(&element.children, out),
+        }
+    }
+}
+
+/// Rich text from XHTML nodes: paragraphs trimmed and separated by blank lines. A paragraph that
+/// is only an interaction's blank is dropped, since the slide shows its own answer field.
+fn to_markdown(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    markdown(nodes, &mut out);
+
+    out.split("\n\n")
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter(|paragraph| !paragraph.is_empty() && paragraph != BLANK)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// QTI scores a question in marks, 1 unless changed, where slides score points out of
+/// `DEFAULT_POINTS`.
+fn points_from_marks(marks: f64) -> Option<u32> {
+    if !marks.is_finite() || marks <= 0.0 || marks == 1.0 {
+        return None;
+    }
+
+    Some((marks * DEFAULT_POINTS as f64).round() as u32)
+}
+
+fn marks_from_points(points: Option<u32>) -> f64 {
+    points.map_or(1.0, |points| points as f64 / DEFAULT_POINTS as f64)
+}
+
+/// A number as QTI writes it: no trailing zeros.
+fn number(value: f64) -> String {
+    let value = format!("{:.6}", value);
+
+    value.trim_end_matches('0').trim_end_matches('.').to_string()
+}
@@ -0,0 +1,511 @@
+//! One assessment item per slide.
+
+use super::{escape, marks_from_points, number, parse, points_from_marks, to_markdown, to_xhtml, Element, Node, Version, Writer};
+use crate::models::slide::{Slide, SlideContent, SlideNumerical, SlideOpenEnded, SlideQuizQuestion, SlideQuizTrueOrFalse, SlideShortAnswer};
+
+const RESPONSE: &str = "RESPONSE";
+
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Longest item title written; titles only label items in other tools' banks.
+const TITLE_MAX_LENGTH: usize = 60;
+
+/// How an item is answered and scored.
+enum Response<'a> {
+    Choice { answers: &'a [String], correct: &'a [bool], multiple: bool },
+    Text { accepted: &'a [String], case_sensitive: bool },
+    Number { value: f64, tolerance: f64 },
+    Extended { max_length: Option<u32> },
+    None,
+}
+
+/// A plain text title from the start of rich text.
+fn title_from(text: &str) -> String {
+    let plain = parse(&format!("<title>{}</title>", to_xhtml(text))).map_or_else(|| text.to_string(), |title| title.text());
+    let line = plain.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if line.chars().count() <= TITLE_MAX_LENGTH {
+        return line;
+    }
+
+    let mut title: String = line.chars().take(TITLE_MAX_LENGTH - 3).collect();
+    title.push_str("...");
+    title
+}
+
+fn choice_identifier(index: usize) -> String {
+    format!("CHOICE_{}", index + 1)
+}
+
+/// The item for `slide`, with its image at `image` in the package, or why it has none.
+pub fn write(version: Version, identifier: &str, slide: &Slide, image: Option<&str>) -> Result<String, &'static str> {
+    let empty: &[String] = &[];
+
+    let (title, question, points, response) = match slide {
+        Slide::Question(slide) => (title_from(&slide.question), slide.question.as_str(), slide.points, Response::Choice {
+            answers: slide.answers.as_deref().unwrap_or(empty),
+            correct: slide.correct_answers.as_deref().unwrap_or_default(),
+            multiple: slide.answer_options == "multiple",
+        }),
+        Slide::TrueOrFalse(slide) => (title_from(&slide.question), slide.question.as_str(), slide.points, Response::Choice {
+            answers: slide.answers.as_deref().unwrap_or(empty),
+            correct: slide.correct_answers.as_deref().unwrap_or_default(),
+            multiple: false,
+        }),
+        Slide::ShortAnswer(slide) => (title_from(&slide.question), slide.question.as_str(), slide.points, Response::Text {
+            accepted: &slide.accepted_answers,
+            case_sensitive: slide.case_sensitive,
+        }),
+        Slide::Numerical(slide) => (title_from(&slide.question), slide.question.as_str(), slide.points, Response::Number {
+            value: slide.correct_value,
+            tolerance: slide.tolerance,
+        }),
+        Slide::OpenEnded(slide) => (title_from(&slide.question), slide.question.as_str(), None, Response::Extended {
+            max_length: slide.max_length,
+        }),
+        Slide::Content(slide) => {
+            let title = if slide.title.trim().is_empty() { title_from(&slide.body) } else { slide.title.clone() };
+
+            (title, slide.body.as_str(), None, Response::None)
+        }
+        Slide::BankItem(_) => return Err("bank questions that no longer exist can't be exported"),
+        Slide::RandomDraw(_) => return Err("random draws from the question bank can't be exported"),
+    };
+
+    let marks = number(marks_from_points(points));
+    let mut w = Writer::new(version);
+
+    w.open("assessmentItem", &[
+        ("xmlns", version.namespace()),
+        ("xmlns:xsi", XSI_NAMESPACE),
+        ("xsi:schemaLocation", version.schema_location()),
+        ("identifier", identifier),
+        ("title", &title),
+        ("adaptive", "false"),
+        ("timeDependent", "false"),
+    ]);
+
+    match &response {
+        Response::Choice { correct, multiple, .. } => {
+            w.open("responseDeclaration", &[
+                ("identifier", RESPONSE),
+                ("cardinality", if *multiple { "multiple" } else { "single" }),
+                ("baseType", "identifier"),
+            ]);
+            w.open("correctResponse", &[]);
+
+            for (index, _) in correct.iter().enumerate().filter(|(_, correct)| **correct) {
+                w.text("value", &[], &choice_identifier(index));
+            }
+
+            w.close("correctResponse");
+            w.close("responseDeclaration");
+        }
+        Response::Text { accepted, case_sensitive } => {
+            w.open("responseDeclaration", &[("identifier", RESPONSE), ("cardinality", "single"), ("baseType", "string")]);
+            w.open("correctResponse", &[]);
+            w.text("value", &[], accepted.first().map_or("", String::as_str));
+            w.close("correctResponse");
+
+            // Every accepted answer scores in full; the mapping is what lets more than one match.
+            w.open("mapping", &[("defaultValue", "0")]);
+
+            for answer in accepted.iter() {
+                w.empty("mapEntry", &[
+                    ("mapKey", answer),
+                    ("mappedValue", &marks),
+                    ("caseSensitive", if *case_sensitive { "true" } else { "false" }),
+                ]);
+            }
+
+            w.close("mapping");
+            w.close("responseDeclaration");
+        }
+        Response::Number { value, .. } => {
+            w.open("responseDeclaration", &[("identifier", RESPONSE), ("cardinality", "single"), ("baseType", "float")]);
+            w.open("correctResponse", &[]);
+            w.text("value", &[], &number(*value));
+            w.close("correctResponse");
+            w.close("responseDeclaration");
+        }
+        Response::Extended { .. } => {
+            w.empty("responseDeclaration", &[("identifier", RESPONSE), ("cardinality", "single"), ("baseType", "string")]);
+        }
+        Response::None => {}
+    }
+
+    if !matches!(response, Response::None) {
+        w.open("outcomeDeclaration", &[("identifier", "SCORE"), ("cardinality", "single"), ("baseType", "float")]);
+        w.open("defaultValue", &[]);
+        w.text("value", &[], "0");
+        w.close("defaultValue");
+        w.close("outcomeDeclaration");
+    }
+
+    if !matches!(response, Response::None | Response::Extended { .. }) {
+        w.open("outcomeDeclaration", &[("identifier", "MAXSCORE"), ("cardinality", "single"), ("baseType", "float")]);
+        w.open("defaultValue", &[]);
+        w.text("value", &[], &marks);
+        w.close("defaultValue");
+        w.close("outcomeDeclaration");
+    }
+
+    w.open("itemBody", &[]);
+
+    if let Slide::Content(slide) = slide {
+        if !slide.title.trim().is_empty() {
+            w.xhtml(&format!("<h2>{}</h2>", escape(&slide.title)));
+        }
+    }
+
+    w.xhtml(&to_xhtml(question));
+
+    if let Some(image) = image {
+        w.xhtml(&format!("<p><img src=\"{}\" alt=\"\"/></p>", escape(image)));
+    }
+
+    match &response {
+        Response::Choice { answers, multiple, .. } => {
+            let max_choices = if *multiple { answers.len().to_string() } else { "1".to_string() };
+
+            w.open("choiceInteraction", &[("responseIdentifier", RESPONSE), ("shuffle", "false"), ("maxChoices", &max_choices)]);
+
+            for (index, answer) in answers.iter().enumerate() {
+                w.open("simpleChoice", &[("identifier", &choice_identifier(index))]);
+                w.xhtml(&to_xhtml(answer));
+                w.close("simpleChoice");
+            }
+
+            w.close("choiceInteraction");
+        }
+        Response::Text { .. } | Response::Number { .. } => {
+            // Text entry is inline, so it needs a paragraph of its own.
+            w.xhtml("<p>");
+            w.empty("textEntryInteraction", &[("responseIdentifier", RESPONSE)]);
+            w.xhtml("</p>");
+        }
+        Response::Extended { max_length } => match max_length {
+            Some(max_length) => w.empty("extendedTextInteraction", &[("responseIdentifier", RESPONSE), ("expectedLength", &max_length.to_string())]),
+            None => w.empty("extendedTextInteraction", &[("responseIdentifier", RESPONSE)]),
+        },
+        Response::None => {}
+    }
+
+    w.close("itemBody");
+
+    let score = |w: &mut Writer| {
+        w.open("setOutcomeValue", &[("identifier", "SCORE")]);
+        w.empty("variable", &[("identifier", "MAXSCORE")]);
+        w.close("setOutcomeValue");
+    };
+
+    match &response {
+        Response::Choice { .. } => {
+            w.open("responseProcessing", &[]);
+            w.open("responseCondition", &[]);
+            w.open("responseIf", &[]);
+            w.open("match", &[]);
+            w.empty("variable", &[("identifier", RESPONSE)]);
+            w.empty("correct", &[("identifier", RESPONSE)]);
+            w.close("match");
+            score(&mut w);
+            w.close("responseIf");
+            w.close("responseCondition");
+            w.close("responseProcessing");
+        }
+        Response::Number { tolerance, .. } => {
+            let tolerance = number(*tolerance);
+
+            w.open("responseProcessing", &[]);
+            w.open("responseCondition", &[]);
+            w.open("responseIf", &[]);
+
+            if tolerance == "0" {
+                w.open("equal", &[("toleranceMode", "exact")]);
+            } else {
+                w.open("equal", &[("toleranceMode", "absolute"), ("tolerance", &tolerance)]);
+            }
+
+            w.empty("variable", &[("identifier", RESPONSE)]);
+            w.empty("correct", &[("identifier", RESPONSE)]);
+            w.close("equal");
+            score(&mut w);
+            w.close("responseIf");
+            w.close("responseCondition");
+            w.close("responseProcessing");
+        }
+        Response::Text { .. } => {
+            w.open("responseProcessing", &[]);
+            w.open("setOutcomeValue", &[("identifier", "SCORE")]);
+            w.empty("mapResponse", &[("identifier", RESPONSE)]);
+            w.close("setOutcomeValue");
+            w.close("responseProcessing");
+        }
+        Response::Extended { .. } | Response::None => {}
+    }
+
+    w.close("assessmentItem");
+
+    Ok(w.finish())
+}
+
+/// An item read back as a slide, with the `src` of its image relative to the item file.
+pub struct ReadItem {
+    pub slide: Slide,
+    pub image: Option<String>,
+}
+
+/// Image sources in `element`, outside any interaction.
+fn image_sources<'a>(element: &'a Element, sources: &mut Vec<&'a str>) {
+    for child in element.elements() {
+        match child.name.as_str() {
+            "img" => sources.extend(child.attribute("src")),
+            "object" if child.attribute("type").is_some_and(|kind| kind.starts_with("image/")) => sources.extend(child.attribute("data")),
+            name if name.ends_with("Interaction") => {}
+            _ => image_sources(child, sources),
+        }
+    }
+}
+
+fn values(element: Option<&Element>) -> Vec<String> {
+    element.map_or_else(Vec::new, |element| element.children_named("value").map(|value| value.text().trim().to_string()).collect())
+}
+
+/// Reads an assessment item as a slide taking `time_limit`, or says why it can't be one.
+pub fn read(item: &Element, time_limit: Option<u32>, warnings: &mut Vec<String>) -> Result<ReadItem, String> {
+    if item.name != "assessmentItem" {
+        return Err("is not an assessment item".to_string());
+    }
+
+    let body = item.child("itemBody").ok_or("has no item body")?;
+    let interactions: Vec<&Element> = body.descendants().into_iter().filter(|element| element.name.ends_with("Interaction")).collect();
+
+    if interactions.len() > 1 {
+        return Err("items with more than one interaction aren't supported".to_string());
+    }
+
+    let mut sources = Vec::new();
+    image_sources(body, &mut sources);
+
+    if sources.len() > 1 {
+        warnings.push("only the first image is imported".to_string());
+    }
+
+    if interactions.iter().any(|interaction| interaction.find("img").is_some()) {
+        warnings.push("images in answers aren't imported".to_string());
+    }
+
+    let has_feedback = item.child("modalFeedback").is_some()
+        || body.descendants().iter().any(|element| matches!(element.name.as_str(), "feedbackInline" | "feedbackBlock"));
+
+    if has_feedback {
+        warnings.push("feedback isn't imported".to_string());
+    }
+
+    let image = sources.first().map(|source| source.to_string());
+
+    let interaction = match interactions.first() {
+        Some(interaction) => *interaction,
+        None => {
+            // An item without interactions is informational, like a content slide. A leading
+            // heading is its title.
+            let first = body.children.iter().position(|node| matches!(node, Node::Element(_)));
+            let heading = first.and_then(|position| match &body.children[position] {
+                Node::Element(element) if matches!(element.name.as_str(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6") => Some((position, element)),
+                _ => None,
+            });
+
+            let (title, body_text) = match heading {
+                Some((position, heading)) => (heading.text().trim().to_string(), to_markdown(&body.children[position + 1..])),
+                None => match to_markdown(&body.children) {
+                    // The item title is only a label, so it stands in for an empty body.
+                    body_text if body_text.is_empty() => (item.attribute("title").unwrap_or("").to_string(), body_text),
+                    body_text => (String::new(), body_text),
+                },
+            };
+
+            return Ok(ReadItem {
+                slide: Slide::Content(SlideContent {
+                    theme: "default".to_string(),
+                    title,
+                    body: body_text,
+                    image_path: None,
+                }),
+                image,
+            });
+        }
+    };
+
+    let mut question = to_markdown(&body.children);
+
+    if let Some(prompt) = interaction.child("prompt") {
+        let prompt = to_markdown(&prompt.children);
+
+        if !prompt.is_empty() {
+            if !question.is_empty() {
+                question.push_str("\n\n");
+            }
+
+            question.push_str(&prompt);
+        }
+    }
+
+    let declaration = item
+        .children_named("responseDeclaration")
+        .find(|declaration| declaration.attribute("identifier") == interaction.attribute("responseIdentifier"));
+
+    let correct = values(declaration.and_then(|declaration| declaration.child("correctResponse")));
+    let mapping: Vec<(String, f64, bool)> = declaration
+        .and_then(|declaration| declaration.child("mapping"))
+        .map_or_else(Vec::new, |mapping| {
+            mapping
+                .children_named("mapEntry")
+                .map(|entry| (
+                    entry.attribute("mapKey").unwrap_or("").trim().to_string(),
+                    entry.attribute("mappedValue").and_then(|value| value.trim().parse().ok()).unwrap_or(0.0),
+                    entry.attribute("caseSensitive") == Some("true"),
+                ))
+                .collect()
+        });
+
+    let points = item
+        .children_named("outcomeDeclaration")
+        .find(|declaration| declaration.attribute("identifier") == Some("MAXSCORE"))
+        .and_then(|declaration| values(declaration.child("defaultValue")).first().and_then(|value| value.parse().ok()))
+        .and_then(points_from_marks);
+
+    let processing = item.child("responseProcessing");
+    let theme = "default".to_string();
+    let image_reveal = "none".to_string();
+
+    let slide = match interaction.name.as_str() {
+        "choiceInteraction" => {
+            let choices: Vec<&Element> = interaction.children_named("simpleChoice").collect();
+            let credited: Vec<String> = if correct.is_empty() {
+                mapping.iter().filter(|(_, value, _)| *value > 0.0).map(|(key, _, _)| key.clone()).collect()
+            } else {
+                correct
+            };
+
+            if credited.is_empty() {
+                return Err("has no correct answer".to_string());
+            }
+
+            let answers: Vec<String> = choices.iter().map(|choice| to_markdown(&choice.children)).collect();
+            let correct_answers: Vec<bool> = choices
+                .iter()
+                .map(|choice| choice.attribute("identifier").is_some_and(|identifier| credited.iter().any(|credited| credited == identifier)))
+                .collect();
+
+            let is_true_or_false = answers.len() == 2
+                && answers.iter().any(|answer| answer.eq_ignore_ascii_case("true"))
+                && answers.iter().any(|answer| answer.eq_ignore_ascii_case("false"));
+
+            let multiple = declaration.and_then(|declaration| declaration.attribute("cardinality")) == Some("multiple")
+                || correct_answers.iter().filter(|correct| **correct).count() > 1;
+
+            if is_true_or_false && !multiple {
+                Slide::TrueOrFalse(SlideQuizTrueOrFalse {
+                    theme,
+                    time_limit,
+                    points,
+                    image_reveal,
+                    image_path: String::new(),
+                    clip: None,
+                    question,
+                    answers: Some(answers),
+                    correct_answers: Some(correct_answers),
+                })
+            } else {
+                Slide::Question(SlideQuizQuestion {
+                    theme,
+                    time_limit,
+                    points,
+                    answer_options: if multiple { "multiple" } else { "single" }.to_string(),
+                    image_reveal,
+                    image_path: String::new(),
+                    clip: None,
+                    question,
+                    answers: Some(answers),
+                    correct_answers: Some(correct_answers),
+                })
+            }
+        }
+        "textEntryInteraction" => match declaration.and_then(|declaration| declaration.attribute("baseType")) {
+            Some("float" | "integer") => {
+                let correct_value = correct
+                    .first()
+                    .and_then(|value| value.replace(',', ".").parse::<f64>().ok())
+                    .ok_or("has no numerical answer that can be read")?;
+
+                let equal = processing.and_then(|processing| processing.find("equal"));
+                let tolerance = equal.and_then(|equal| equal.attribute("tolerance")).and_then(|tolerance| tolerance.split_whitespace().next()?.parse::<f64>().ok());
+
+                let tolerance = match (equal.and_then(|equal| equal.attribute("toleranceMode")), tolerance) {
+                    (Some("absolute"), Some(tolerance)) => tolerance.abs(),
+                    (Some("relative"), Some(percent)) => (correct_value * percent / 100.0).abs(),
+                    _ => 0.0,
+                };
+
+                Slide::Numerical(SlideNumerical {
+                    theme,
+                    time_limit,
+                    points,
+                    image_reveal,
+                    image_path: String::new(),
+                    clip: None,
+                    question,
+                    correct_value,
+                    tolerance,
+                })
+            }
+            _ => {
+                let mut accepted: Vec<String> = correct.into_iter().filter(|answer| !answer.is_empty()).collect();
+
+                for (key, value, _) in &mapping {
+                    if *value > 0.0 && !key.is_empty() && !accepted.contains(key) {
+                        accepted.push(key.clone());
+                    }
+                }
+
+                if accepted.is_empty() {
+                    return Err("has no correct answer".to_string());
+                }
+
+                // Without a mapping, a response has to match the correct one exactly.
+                let case_sensitive = if mapping.is_empty() {
+                    processing
+                        .and_then(|processing| processing.find("stringMatch"))
+                        .and_then(|string_match| string_match.attribute("caseSensitive"))
+                        .is_none_or(|case_sensitive| case_sensitive == "true")
+                } else {
+                    mapping.iter().any(|(_, _, case_sensitive)| *case_sensitive)
+                };
+
+                Slide::ShortAnswer(SlideShortAnswer {
+                    theme,
+                    time_limit,
+                    points,
+                    image_reveal,
+                    image_path: String::new(),
+                    clip: None,
+                    question,
+                    accepted_answers: accepted,
+                    case_sensitive,
+                })
+            }
+        },
+        "extendedTextInteraction" => Slide::OpenEnded(SlideOpenEnded {
+            theme,
+            time_limit,
+            image_reveal,
+            image_path: String::new(),
+            clip: None,
+            question,
+            max_length: None,
+        }),
+        name => return Err(format!("{} items aren't supported", name)),
+    };
+
+    Ok(ReadItem { slide, image })
+}
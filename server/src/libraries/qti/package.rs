@@ -0,0 +1,530 @@
+//! The zip around the items: `imsmanifest.xml`, an assessment test giving their order and time
+//! limits, and the media they show.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Cursor, Read, Write};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use super::{escape, item, number, parse, Element, Version, Writer};
+use crate::libraries::interchange::{Image, Imported, Warning};
+use crate::models::slide::Slide;
+
+const MANIFEST: &str = "imsmanifest.xml";
+
+const TEST: &str = "assessment-test.xml";
+
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Largest file read out of a package, and the most read in total, so a small upload can't
+/// unpack into a huge one.
+const ENTRY_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+const PACKAGE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+fn manifest_namespace(version: Version) -> &'static str {
+    match version {
+        Version::V2p1 => "http://www.imsglobal.org/xsd/imscp_v1p1",
+        Version::V3p0 => "http://www.imsglobal.org/xsd/qti/qtiv3p0/imscp_v1p1",
+    }
+}
+
+fn manifest_schema_location(version: Version) -> &'static str {
+    match version {
+        Version::V2p1 => "http://www.imsglobal.org/xsd/imscp_v1p1 http://www.imsglobal.org/xsd/qti/qtiv2p1/qtiv2p1_imscpv1p2_v1p0.xsd",
+        Version::V3p0 => "http://www.imsglobal.org/xsd/qti/qtiv3p0/imscp_v1p1 https://purl.imsglobal.org/spec/qti/v3p0/schema/xsd/imsqtiv3p0_imscpv1p2_v1p0.xsd",
+    }
+}
+
+/// Resource types for a test and an item in the manifest.
+fn resource_types(version: Version) -> (&'static str, &'static str) {
+    match version {
+        Version::V2p1 => ("imsqti_test_xmlv2p1", "imsqti_item_xmlv2p1"),
+        Version::V3p0 => ("imsqti_test_xmlv3p0", "imsqti_item_xmlv3p0"),
+    }
+}
+
+fn schema_version(version: Version) -> (&'static str, &'static str) {
+    match version {
+        Version::V2p1 => ("QTIv2.1 Package", "1.0.0"),
+        Version::V3p0 => ("QTI Package", "3.0.0"),
+    }
+}
+
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let decoded = (bytes[index] == b'%')
+            .then(|| href.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                index += 3;
+            }
+            None => {
+                out.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The path in the package of `href`, as written in the file at `base`.
+fn resolve(base: &str, href: &str) -> String {
+    let href = percent_decode(href.split(['?', '#']).next().unwrap_or(""));
+    let mut parts: Vec<&str> = match base.rsplit_once('/') {
+        Some((directory, _)) if !href.starts_with('/') => directory.split('/').collect(),
+        _ => Vec::new(),
+    };
+
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+struct Package<'a> {
+    archive: ZipArchive<Cursor<&'a [u8]>>,
+    budget: u64,
+}
+
+impl Package<'_> {
+    fn read(&mut self, path: &str) -> Option<Vec<u8>> {
+        let file = self.archive.by_name(path).ok()?;
+        let limit = ENTRY_MAX_BYTES.min(self.budget);
+
+        if file.size() > limit {
+            return None;
+        }
+
+        // The size in the header can lie, so the read is capped as well.
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.take(limit + 1).read_to_end(&mut bytes).ok()?;
+
+        if bytes.len() as u64 > limit {
+            return None;
+        }
+
+        self.budget -= bytes.len() as u64;
+
+        Some(bytes)
+    }
+
+    fn read_xml(&mut self, path: &str) -> Option<Element> {
+        let bytes = self.read(path)?;
+        let text = std::str::from_utf8(&bytes).ok()?;
+
+        parse(text.trim_start_matches('\u{feff}'))
+    }
+}
+
+/// The items to read, as package paths with their time limits: in test order when the package has
+/// a test, in manifest order otherwise.
+fn item_refs(package: &mut Package, manifest: &Element) -> Vec<(String, Option<u32>)> {
+    let resources: Vec<&Element> = manifest.child("resources").map_or_else(Vec::new, |resources| resources.children_named("resource").collect());
+    let of_type = |prefix: &str| -> Vec<&str> {
+        resources
+            .iter()
+            .filter(|resource| resource.attribute("type").is_some_and(|kind| kind.starts_with(prefix)))
+            .filter_map(|resource| resource.attribute("href"))
+            .collect()
+    };
+
+    let mut refs = Vec::new();
+
+    for test_path in of_type("imsqti_test").into_iter().map(|href| resolve(MANIFEST, href)) {
+        let test = match package.read_xml(&test_path) {
+            Some(test) => test,
+            None => continue,
+        };
+
+        for item_ref in test.descendants().into_iter().filter(|element| element.name == "assessmentItemRef") {
+            let time_limit = item_ref
+                .child("timeLimits")
+                .and_then(|limits| limits.attribute("maxTime"))
+                .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+                .filter(|seconds| seconds.is_finite() && *seconds >= 1.0)
+                .map(|seconds| seconds.round() as u32);
+
+            if let Some(href) = item_ref.attribute("href") {
+                refs.push((resolve(&test_path, href), time_limit));
+            }
+        }
+    }
+
+    if refs.is_empty() {
+        refs = of_type("imsqti_item").into_iter().map(|href| (resolve(MANIFEST, href), None)).collect();
+    }
+
+    refs
+}
+
+/// Reads a QTI 2.1 or 3.0 content package, or says why it isn't one.
+pub fn import(bytes: &[u8]) -> Result<Imported, &'static str> {
+    let archive = ZipArchive::new(Cursor::new(bytes)).map_err(|_| "is not a zip file")?;
+    let mut package = Package { archive, budget: PACKAGE_MAX_BYTES };
+
+    let manifest = package.read_xml(MANIFEST).ok_or("must be a QTI content package with an imsmanifest.xml")?;
+    let refs = item_refs(&mut package, &manifest);
+
+    if refs.is_empty() {
+        return Err("has no assessment items");
+    }
+
+    let count = refs.len();
+    let mut slides = Vec::new();
+    let mut positions = Vec::new();
+    let mut images = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (index, (path, time_limit)) in refs.into_iter().enumerate() {
+        let mut messages = Vec::new();
+
+        let read = match package.read_xml(&path) {
+            Some(element) => item::read(&element, time_limit, &mut messages),
+            None => Err("could not be read from the package".to_string()),
+        };
+
+        warnings.extend(messages.into_iter().map(|message| Warning { index, message }));
+
+        let read = match read {
+            Ok(read) => read,
+            Err(message) => {
+                warnings.push(Warning { index, message });
+                continue;
+            }
+        };
+
+        if let Some(source) = read.image {
+            let image_path = resolve(&path, &source);
+
+            match package.read(&image_path) {
+                Some(bytes) => {
+                    let name = image_path.rsplit('/').next().unwrap_or_default().to_string();
+
+                    images.push((slides.len(), Image { name, bytes }));
+                }
+                None => warnings.push(Warning { index, message: "the image could not be read from the package".to_string() }),
+            }
+        }
+
+        slides.push(read.slide);
+        positions.push(index);
+    }
+
+    Ok(Imported { count, slides, positions, images, warnings })
+}
+
+fn test_xml(version: Version, title: &str, items: &[(String, String, Option<u32>)]) -> String {
+    let mut w = Writer::new(version);
+
+    w.open("assessmentTest", &[
+        ("xmlns", version.namespace()),
+        ("xmlns:xsi", XSI_NAMESPACE),
+        ("xsi:schemaLocation", version.schema_location()),
+        ("identifier", "TEST"),
+        ("title", title),
+    ]);
+    w.open("testPart", &[("identifier", "PART-1"), ("navigationMode", "linear"), ("submissionMode", "individual")]);
+    w.open("assessmentSection", &[("identifier", "SECTION-1"), ("title", title), ("visible", "true")]);
+
+    for (identifier, href, time_limit) in items {
+        match time_limit {
+            Some(time_limit) => {
+                w.open("assessmentItemRef", &[("identifier", identifier), ("href", href)]);
+                w.empty("timeLimits", &[("maxTime", &number(*time_limit as f64))]);
+                w.close("assessmentItemRef");
+            }
+            None => w.empty("assessmentItemRef", &[("identifier", identifier), ("href", href)]),
+        }
+    }
+
+    w.close("assessmentSection");
+    w.close("testPart");
+    w.close("assessmentTest");
+
+    w.finish()
+}
+
+/// `items` as `(identifier, href, media identifier)` and `media` as `(identifier, href)`.
+fn manifest_xml(version: Version, items: &[(String, String, Option<String>)], media: &[(String, String)]) -> String {
+    let (test_type, item_type) = resource_types(version);
+    let (schema, schema_version) = schema_version(version);
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+    out.push_str(&format!(
+        "<manifest xmlns=\"{}\" xmlns:xsi=\"{}\" xsi:schemaLocation=\"{}\" identifier=\"MANIFEST\">",
+        manifest_namespace(version),
+        XSI_NAMESPACE,
+        manifest_schema_location(version),
+    ));
+    out.push_str(&format!("<metadata><schema>{}</schema><schemaversion>{}</schemaversion></metadata>", schema, schema_version));
+    out.push_str("<organizations/><resources>");
+
+    out.push_str(&format!("<resource identifier=\"TEST\" type=\"{}\" href=\"{}\"><file href=\"{}\"/>", test_type, TEST, TEST));
+
+    for (identifier, _, _) in items {
+        out.push_str(&format!("<dependency identifierref=\"{}\"/>", identifier));
+    }
+
+    out.push_str("</resource>");
+
+    for (identifier, href, media) in items {
+        out.push_str(&format!("<resource identifier=\"{}\" type=\"{}\" href=\"{}\"><file href=\"{}\"/>", identifier, item_type, href, href));
+
+        if let Some(media) = media {
+            out.push_str(&format!("<dependency identifierref=\"{}\"/>", media));
+        }
+
+        out.push_str("</resource>");
+    }
+
+    for (identifier, href) in media {
+        let href = escape(href);
+
+        out.push_str(&format!("<resource identifier=\"{}\" type=\"webcontent\" href=\"{}\"><file href=\"{}\"/></resource>", identifier, href, href));
+    }
+
+    out.push_str("</resources></manifest>\n");
+    out
+}
+
+/// The slides as a content package, embedding `images` (by slide index), with warnings for what
+/// QTI can't hold.
+pub fn export(version: Version, title: &str, slides: &[Slide], mut images: HashMap<usize, Image>) -> io::Result<(Vec<u8>, Vec<Warning>)> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    let mut warnings = Vec::new();
+
+    // `(identifier, href, media identifier)` for the manifest, and what the test needs.
+    let mut items = Vec::new();
+    let mut test_items = Vec::new();
+    let mut media: Vec<(String, String)> = Vec::new();
+    let mut written: HashSet<String> = HashSet::new();
+
+    for (index, slide) in slides.iter().enumerate() {
+        let identifier = format!("ITEM-{}", index + 1);
+        let href = format!("items/item-{}.xml", index + 1);
+        let image = images.remove(&index);
+        let source = image.as_ref().map(|image| format!("../media/{}", image.name));
+
+        let xml = match item::write(version, &identifier, slide, source.as_deref()) {
+            Ok(xml) => xml,
+            Err(reason) => {
+                warnings.push(Warning { index, message: reason.to_string() });
+                continue;
+            }
+        };
+
+        if slide.clip().is_some() {
+            warnings.push(Warning { index, message: "the audio or video clip is not exported".to_string() });
+        }
+
+        if slide.reveal_mode().is_some() {
+            warnings.push(Warning { index, message: "the image reveal is not exported".to_string() });
+        }
+
+        if slide.image_path().is_some() && image.is_none() {
+            warnings.push(Warning { index, message: "the image could not be included".to_string() });
+        }
+
+        zip.start_file(href.as_str(), options)?;
+        zip.write_all(xml.as_bytes())?;
+
+        let media_identifier = match image {
+            Some(image) => {
+                let media_href = format!("media/{}", image.name);
+
+                if written.insert(media_href.clone()) {
+                    zip.start_file(media_href.as_str(), options)?;
+                    zip.write_all(&image.bytes)?;
+                    media.push((format!("MEDIA-{}", media.len() + 1), media_href.clone()));
+                }
+
+                media.iter().find(|(_, href)| *href == media_href).map(|(identifier, _)| identifier.clone())
+            }
+            None => None,
+        };
+
+        test_items.push((identifier.clone(), href.clone(), slide.time_limit()));
+        items.push((identifier, href, media_identifier));
+    }
+
+    zip.start_file(TEST, options)?;
+    zip.write_all(test_xml(version, title, &test_items).as_bytes())?;
+
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(manifest_xml(version, &items, &media).as_bytes())?;
+
+    let bytes = zip.finish()?.into_inner();
+
+    Ok((bytes, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Stdio};
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::models::slide::DEFAULT_POINTS;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/qti");
+
+    const VERSIONS: [Version; 2] = [Version::V2p1, Version::V3p0];
+
+    fn sample(version: Version) -> Vec<u8> {
+        let name = match version {
+            Version::V2p1 => "europe-qti21.zip",
+            Version::V3p0 => "europe-qti30.zip",
+        };
+
+        std::fs::read(format!("{}/samples/{}", FIXTURES, name)).unwrap()
+    }
+
+    /// Where the official schema at `url` is vendored: under `schemas/`, mirrored by host and
+    /// path, which is also where `schemas/catalog.xml` points the schemas' own imports.
+    fn official_schema(url: &str) -> String {
+        let path = url.split_once("://").map_or(url, |(_, path)| path);
+
+        format!("{}/schemas/{}", FIXTURES, path)
+    }
+
+    /// Validates every XML file in `package` with xmllint, against the official schemas its
+    /// `xsi:schemaLocation` names. Fails when xmllint or the schemas aren't there.
+    fn validate(version: Version, package: &[u8]) {
+        let location = |schema_location: &'static str| official_schema(schema_location.split_whitespace().last().unwrap());
+        let (manifest_schema, qti_schema) = (location(manifest_schema_location(version)), location(version.schema_location()));
+
+        for schema in [&manifest_schema, &qti_schema] {
+            assert!(std::path::Path::new(schema).exists(), "{} is missing; see tests/fixtures/qti/README.md", schema);
+        }
+
+        let mut archive = ZipArchive::new(Cursor::new(package)).unwrap();
+
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).unwrap();
+            let name = file.name().to_string();
+
+            if !name.ends_with(".xml") {
+                continue;
+            }
+
+            let mut xml = Vec::new();
+            file.read_to_end(&mut xml).unwrap();
+
+            let schema = if name == MANIFEST { &manifest_schema } else { &qti_schema };
+            let mut xmllint = Command::new("xmllint")
+                .args(["--noout", "--nonet", "--schema", schema, "-"])
+                .env("XML_CATALOG_FILES", format!("{}/schemas/catalog.xml", FIXTURES))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .expect("xmllint is needed to validate QTI packages");
+
+            xmllint.stdin.take().unwrap().write_all(&xml).unwrap();
+            let output = xmllint.wait_with_output().unwrap();
+
+            assert!(output.status.success(), "{} is not valid: {}", name, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+
+    #[test]
+    fn reads_the_sample_packages() {
+        for version in VERSIONS {
+            let imported = import(&sample(version)).unwrap();
+
+            assert_eq!((imported.count, imported.slides.len()), (7, 7));
+            assert!(imported.warnings.is_empty());
+
+            let slides: Vec<Value> = imported.slides.iter().map(Slide::to_json).collect();
+
+            assert_eq!(slides[0]["question_type"], "question");
+            assert_eq!(slides[0]["time_limit"], 20);
+            assert_eq!(slides[0]["points"], 2 * DEFAULT_POINTS);
+            assert_eq!(slides[0]["answers"], json!(["Lyon", "Paris", "Marseille"]));
+            assert_eq!(slides[0]["correct_answers"], json!([false, true, false]));
+            assert_eq!(slides[1]["answer_options"], "multiple");
+            assert_eq!(slides[1]["question"], "Which of these are *prime*?");
+            assert_eq!(slides[2]["question_type"], "true_or_false");
+            assert_eq!(slides[2]["correct_answers"], json!([false, true]));
+            assert_eq!(slides[3]["question_html"], "<p>Who wrote <em>Les Misérables</em>? _____</p>");
+            assert_eq!(slides[3]["accepted_answers"], json!(["Hugo", "Victor Hugo"]));
+            assert_eq!(slides[3]["case_sensitive"], false);
+            assert_eq!((&slides[4]["correct_value"], &slides[4]["tolerance"], &slides[4]["time_limit"]), (&json!(330.0), &json!(5.0), &json!(45)));
+            assert_eq!(slides[5]["question_type"], "open_ended");
+            assert_eq!((&slides[6]["title"], &slides[6]["body"]), (&json!("Halfway there"), &json!("Take a **short** break.")));
+
+            assert_eq!(imported.images.len(), 1);
+            assert_eq!((imported.images[0].0, imported.images[0].1.name.as_str()), (0, "flag.png"));
+        }
+    }
+
+    #[test]
+    fn exports_valid_packages_that_read_back() {
+        for from in VERSIONS {
+            let imported = import(&sample(from)).unwrap();
+
+            for version in VERSIONS {
+                let images = imported
+                    .images
+                    .iter()
+                    .map(|(index, image)| (*index, Image { name: image.name.clone(), bytes: image.bytes.clone() }))
+                    .collect();
+
+                let (package, warnings) = export(version, "Europe", &imported.slides, images).unwrap();
+
+                assert!(warnings.is_empty());
+
+                let exported = import(&package).unwrap();
+
+                assert!(exported.warnings.is_empty());
+                assert_eq!(
+                    exported.slides.iter().map(Slide::to_json).collect::<Vec<_>>(),
+                    imported.slides.iter().map(Slide::to_json).collect::<Vec<_>>(),
+                );
+                assert_eq!(exported.images.len(), 1);
+                assert_eq!(exported.images[0].1.bytes, imported.images[0].1.bytes);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs xmllint and the official IMS schemas vendored as tests/fixtures/qti/README.md describes"]
+    fn packages_match_the_official_schemas() {
+        for from in VERSIONS {
+            let package = sample(from);
+            validate(from, &package);
+
+            let imported = import(&package).unwrap();
+
+            for version in VERSIONS {
+                let images = imported
+                    .images
+                    .iter()
+                    .map(|(index, image)| (*index, Image { name: image.name.clone(), bytes: image.bytes.clone() }))
+                    .collect();
+
+                let (package, _) = export(version, "Europe", &imported.slides, images).unwrap();
+
+                validate(version, &package);
+            }
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use std::convert::Infallible;
 use std::fmt;
-use redis::{Client, RedisResult, Connection, cmd, Pipeline, PubSub};
+use std::thread;
+use std::time::Duration;
+use redis::{Client, RedisResult, Connection, cmd, Msg, Pipeline, PubSub};
 use redis::cluster::{ClusterClient, ClusterConnection};
 use tokio::sync::OnceCell;
 use crate::env::REDIS_URI;
@@ -57,70 +60,110 @@ impl RedisConn {
         }
     }
 
-    pub fn set(&mut self, key: &str, value: &str) -> RedisResult<()> {
+    pub fn get(&mut self, key: &str) -> RedisResult<Option<String>> {
         match self {
-            RedisConn::Single(conn) => cmd("SET").arg(key).arg(value).query(conn),
-            RedisConn::Cluster(conn) => cmd("SET").arg(key).arg(value).query(conn),
+            RedisConn::Single(conn) => cmd("GET").arg(key).query(conn),
+            RedisConn::Cluster(conn) => cmd("GET").arg(key).query(conn),
         }
     }
 
-    pub fn set_ex(&mut self, key: &str, value: &str, seconds: u64) -> RedisResult<()> {
+    pub fn exists(&mut self, key: &str) -> RedisResult<bool> {
         match self {
-            RedisConn::Single(conn) => cmd("SETEX").arg(key).arg(seconds).arg(value).query(conn),
-            RedisConn::Cluster(conn) => cmd("SETEX").arg(key).arg(seconds).arg(value).query(conn),
+            RedisConn::Single(conn) => cmd("EXISTS").arg(key).query(conn),
+            RedisConn::Cluster(conn) => cmd("EXISTS").arg(key).query(conn),
         }
     }
 
-    pub fn get(&mut self, key: &str) -> RedisResult<Option<String>> {
+    pub fn expire(&mut self, key: &str, seconds: i64) -> RedisResult<()> {
         match self {
-            RedisConn::Single(conn) => cmd("GET").arg(key).query(conn),
-            RedisConn::Cluster(conn) => cmd("GET").arg(key).query(conn),
+            RedisConn::Single(conn) => cmd("EXPIRE").arg(key).arg(seconds).query(conn),
+            RedisConn::Cluster(conn) => cmd("EXPIRE").arg(key).arg(seconds).query(conn),
         }
     }
 
-    pub fn del(&mut self, key: &str) -> RedisResult<i64> {
+    pub fn hset(&mut self, key: &str, field: &str, value: &str) -> RedisResult<()> {
         match self {
-            RedisConn::Single(conn) => cmd("DEL").arg(key).query(conn),
-            RedisConn::Cluster(conn) => cmd("DEL").arg(key).query(conn),
+            RedisConn::Single(conn) => cmd("HSET").arg(key).arg(field).arg(value).query(conn),
+            RedisConn::Cluster(conn) => cmd("HSET").arg(key).arg(field).arg(value).query(conn),
         }
     }
 
-    pub fn exists(&mut self, key: &str) -> RedisResult<bool> {
+    pub fn hdel(&mut self, key: &str, field: &str) -> RedisResult<i64> {
         match self {
-            RedisConn::Single(conn) => cmd("EXISTS").arg(key).query(conn),
-            RedisConn::Cluster(conn) => cmd("EXISTS").arg(key).query(conn),
+            RedisConn::Single(conn) => cmd("HDEL").arg(key).arg(field).query(conn),
+            RedisConn::Cluster(conn) => cmd("HDEL").arg(key).arg(field).query(conn),
         }
     }
 
-    pub fn set_nx(&mut self, key: &str, value: &str) -> RedisResult<bool> {
+    pub fn hgetall(&mut self, key: &str) -> RedisResult<Vec<(String, String)>> {
         match self {
-            RedisConn::Single(conn) => cmd("SETNX").arg(key).arg(value).query(conn),
-            RedisConn::Cluster(conn) => cmd("SETNX").arg(key).arg(value).query(conn),
+            RedisConn::Single(conn) => cmd("HGETALL").arg(key).query(conn),
+            RedisConn::Cluster(conn) => cmd("HGETALL").arg(key).query(conn),
         }
     }
 
-    pub fn expire(&mut self, key: &str, seconds: i64) -> RedisResult<()> {
+    pub fn publish(&mut self, channel: &str, message: &str) -> RedisResult<i32> {
         match self {
-            RedisConn::Single(conn) => cmd("EXPIRE").arg(key).arg(seconds).query(conn),
-            RedisConn::Cluster(conn) => cmd("EXPIRE").arg(key).arg(seconds).query(conn),
+            RedisConn::Single(conn) => cmd("PUBLISH").arg(channel).arg(message).query(conn),
+            RedisConn::Cluster(conn) => cmd("PUBLISH").arg(channel).arg(message).query(conn),
+        }
+    }
+
+    /// Every key matching `pattern`, found with SCAN so Redis isn't blocked the way KEYS would.
+    /// Cluster mode would need a scan per node, so it is refused there rather than answered from
+    /// whichever node the command lands on.
+    pub fn scan_match(&mut self, pattern: &str) -> RedisResult<Vec<String>> {
+        let conn = match self {
+            RedisConn::Single(conn) => conn,
+            RedisConn::Cluster(_) => return Err(redis::RedisError::from((
+                redis::ErrorKind::InvalidClientConfig,
+                "SCAN not supported in cluster mode with this implementation",
+            ))),
+        };
+
+        let mut keys = Vec::new();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = cmd("SCAN").arg(cursor).arg("MATCH").arg(pattern).arg("COUNT").arg(500).query(conn)?;
+
+            keys.extend(batch);
+
+            if next == 0 {
+                return Ok(keys);
+            }
+
+            cursor = next;
         }
     }
 
-    pub fn incr(&mut self, key: &str, increment: i64) -> RedisResult<i64> {
+    pub fn watch(&mut self, key: &str) -> RedisResult<()> {
         match self {
-            RedisConn::Single(conn) => cmd("INCRBY").arg(key).arg(increment).query(conn),
-            RedisConn::Cluster(conn) => cmd("INCRBY").arg(key).arg(increment).query(conn),
+            RedisConn::Single(conn) => cmd("WATCH").arg(key).query(conn),
+            RedisConn::Cluster(conn) => cmd("WATCH").arg(key).query(conn),
         }
     }
 
-    pub fn publish(&mut self, channel: &str, message: &str) -> RedisResult<i32> {
+    pub fn unwatch(&mut self) -> RedisResult<()> {
         match self {
-            RedisConn::Single(conn) => cmd("PUBLISH").arg(channel).arg(message).query(conn),
-            RedisConn::Cluster(conn) => cmd("PUBLISH").arg(channel).arg(message).query(conn),
+            RedisConn::Single(conn) => cmd("UNWATCH").query(conn),
+            RedisConn::Cluster(conn) => cmd("UNWATCH").query(conn),
         }
     }
 
-    pub fn pubsub(&mut self) -> RedisResult<PubSub> {
+    /// Runs `pipe` as MULTI/EXEC on this connection, so it honours an earlier `watch`. `None`
+    /// means a watched key changed and nothing was applied.
+    pub fn exec<T: redis::FromRedisValue>(&mut self, pipe: &Pipeline) -> RedisResult<Option<T>> {
+        let mut pipe = pipe.clone();
+        pipe.atomic();
+
+        match self {
+            RedisConn::Single(conn) => pipe.query(conn),
+            RedisConn::Cluster(conn) => pipe.query(conn),
+        }
+    }
+
+    pub fn pubsub(&mut self) -> RedisResult<PubSub<'_>> {
         match self {
             RedisConn::Single(conn) => Ok(conn.as_pubsub()),
             RedisConn::Cluster(_) => Err(redis::RedisError::from((
@@ -144,4 +187,32 @@ where
         RedisConn::Single(ref mut c) => pipe.query(c),
         RedisConn::Cluster(ref mut c) => pipe.query(c),
     }
-}
\ No newline at end of file
+}
+/// Longest wait between attempts to resubscribe after Redis goes away.
+const SUBSCRIBE_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Subscribes to `channels` and hands every message to `on_message`, forever. A dropped or failed
+/// connection is logged and retried with backoff, so the caller's thread never dies with it.
+/// Blocks the calling thread.
+pub fn subscribe_forever(channels: &[&str], mut on_message: impl FnMut(Msg)) -> ! {
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        let result: RedisResult<Infallible> = RedisConn::get_connection().and_then(|mut redis_connect| {
+            let mut pubsub = redis_connect.pubsub()?;
+
+            pubsub.subscribe(channels)?;
+            backoff = Duration::from_secs(1);
+
+            loop {
+                on_message(pubsub.get_message()?);
+            }
+        });
+
+        let Err(e) = result;
+        tracing::error!("Redis subscription to {:?} failed, retrying in {:?}: {}", channels, backoff, e);
+
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(SUBSCRIBE_MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,188 @@
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+/// TeX commands that pull in URLs, raw HTML or files. KaTeX refuses most of them unless trusted,
+/// but a shared quiz shouldn't rely on every client's settings.
+const BLOCKED_TEX_COMMANDS: &[&str] = &[
+    "href", "url", "includegraphics", "htmlClass", "htmlId", "htmlStyle", "htmlData", "html",
+];
+
+/// Question and answer text is a Markdown subset: paragraphs and line breaks, emphasis, strong,
+/// strikethrough, inline code, code blocks and `$...$` / `$$...$$` math. Anything else (headings,
+/// lists, links, images, raw HTML, ...) is rejected on save.
+fn parser(source: &str) -> Parser<'_> {
+    Parser::new_ext(source, Options::ENABLE_STRIKETHROUGH | Options::ENABLE_MATH)
+}
+
+fn escape(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// The language of a fenced code block, if it is a plain name safe to put in a class.
+fn code_language(info: &str) -> Option<&str> {
+    let language = info.split_whitespace().next()?;
+
+    let valid = language.len() <= 20
+        && language.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '#' | '-' | '_'));
+
+    Some(language).filter(|_| valid)
+}
+
+fn check_tex(tex: &str) -> Result<(), &'static str> {
+    let mut depth = 0i32;
+    let mut chars = tex.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth < 0 {
+                    return Err("has unbalanced braces in math");
+                }
+            }
+            '\\' => {
+                let start = index + 1;
+                let mut end = start;
+
+                while let Some((index, c)) = chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+
+                    end = index + 1;
+                    chars.next();
+                }
+
+                if end == start {
+                    // An escaped symbol like `\{`; skip it so it doesn't count as a brace.
+                    chars.next();
+                } else if BLOCKED_TEX_COMMANDS.contains(&&tex[start..end]) {
+                    return Err("uses a math command that isn't allowed");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if depth != 0 {
+        return Err("has unbalanced braces in math");
+    }
+
+    Ok(())
+}
+
+/// Checks that `source` only uses the supported subset, returning why it doesn't otherwise.
+pub fn validate(source: &str) -> Result<(), &'static str> {
+    for event in parser(source) {
+        match event {
+            Event::Start(Tag::Paragraph | Tag::CodeBlock(_) | Tag::Emphasis | Tag::Strong | Tag::Strikethrough)
+            | Event::End(_)
+            | Event::Text(_)
+            | Event::Code(_)
+            | Event::SoftBreak
+            | Event::HardBreak => {}
+            Event::InlineMath(tex) | Event::DisplayMath(tex) => check_tex(&tex)?,
+            Event::Html(_) | Event::InlineHtml(_) => return Err("must not contain HTML"),
+            Event::Start(Tag::Link { .. }) => return Err("must not contain links"),
+            Event::Start(Tag::Image { .. }) => return Err("must not contain images"),
+            _ => return Err("may only use emphasis, code and math formatting"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders `source` to HTML built only from escaped text and a fixed set of tags, so it is safe
+/// to insert as is. Math is left as escaped TeX for the client to typeset. Constructs outside the
+/// subset (which `validate` keeps out of saved quizzes) degrade to their text.
+pub fn render(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() + source.len() / 4);
+    let mut in_code_block = false;
+
+    for event in parser(source) {
+        match event {
+            Event::Start(Tag::Paragraph) => out.push_str("<p>"),
+            Event::End(TagEnd::Paragraph) => out.push_str("</p>"),
+            Event::Start(Tag::Emphasis) => out.push_str("<em>"),
+            Event::End(TagEnd::Emphasis) => out.push_str("</em>"),
+            Event::Start(Tag::Strong) => out.push_str("<strong>"),
+            Event::End(TagEnd::Strong) => out.push_str("</strong>"),
+            Event::Start(Tag::Strikethrough) => out.push_str("<del>"),
+            Event::End(TagEnd::Strikethrough) => out.push_str("</del>"),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code_block = true;
+
+                match kind {
+                    CodeBlockKind::Fenced(info) => match code_language(&info) {
+                        Some(language) => {
+                            out.push_str("<pre><code class=\"language-");
+                            escape(&mut out, language);
+                            out.push_str("\">");
+                        }
+                        None => out.push_str("<pre><code>"),
+                    },
+                    CodeBlockKind::Indented => out.push_str("<pre><code>"),
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                out.push_str("</code></pre>");
+            }
+            Event::Text(text) => escape(&mut out, &text),
+            Event::Code(code) => {
+                out.push_str("<code>");
+                escape(&mut out, &code);
+                out.push_str("</code>");
+            }
+            Event::InlineMath(tex) => {
+                out.push_str("<span class=\"math math-inline\">");
+                escape(&mut out, &tex);
+                out.push_str("</span>");
+            }
+            Event::DisplayMath(tex) => {
+                out.push_str("<span class=\"math math-display\">");
+                escape(&mut out, &tex);
+                out.push_str("</span>");
+            }
+            Event::Html(html) | Event::InlineHtml(html) => escape(&mut out, &html),
+            Event::SoftBreak if in_code_block => out.push('\n'),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => out.push_str("<br>"),
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// `source` with each math span rewritten by `write(tex, display)`, for exports to formats that
+/// delimit math differently. Everything else is left byte for byte.
+pub fn replace_math(source: &str, write: impl Fn(&str, bool) -> String) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut copied = 0;
+
+    for (event, range) in parser(source).into_offset_iter() {
+        let (tex, display) = match event {
+            Event::InlineMath(tex) => (tex, false),
+            Event::DisplayMath(tex) => (tex, true),
+            _ => continue,
+        };
+
+        out.push_str(&source[copied..range.start]);
+        out.push_str(&write(&tex, display));
+        copied = range.end;
+    }
+
+    out.push_str(&source[copied..]);
+    out
+}
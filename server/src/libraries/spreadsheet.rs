@@ -0,0 +1,97 @@
+use std::io::Cursor;
+use calamine::{open_workbook_from_rs, Reader, Xlsx};
+
+/// The cells of a spreadsheet's first sheet as text, row by row.
+pub struct Sheet {
+    /// 1-based number of the first row in `rows`, as the user's spreadsheet app shows it.
+    pub first_row: usize,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// XLSX files are zip archives; anything else is treated as CSV.
+fn is_xlsx(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+/// Spreadsheet apps set to a locale with decimal commas export CSV with semicolons, and some
+/// people paste tab separated text, so the separator is whichever appears most on the first line.
+fn csv_delimiter(text: &str) -> u8 {
+    let first_line = text.lines().next().unwrap_or("");
+
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|delimiter| first_line.bytes().filter(|byte| byte == delimiter).count())
+        .unwrap_or(b',')
+}
+
+fn read_csv(bytes: &[u8]) -> Result<Sheet, &'static str> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "must be a UTF-8 CSV or an XLSX file")?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(csv_delimiter(text))
+        .from_reader(text.as_bytes());
+
+    let rows = reader
+        .records()
+        .map(|record| record.map(|record| record.iter().map(str::to_string).collect()))
+        .collect::<Result<Vec<Vec<String>>, _>>()
+        .map_err(|_| "is not a readable CSV file")?;
+
+    Ok(Sheet { first_row: 1, rows })
+}
+
+fn read_xlsx(bytes: &[u8]) -> Result<Sheet, &'static str> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(bytes)).map_err(|_| "is not a readable XLSX file")?;
+
+    let range = match workbook.worksheet_range_at(0) {
+        Some(Ok(range)) => range,
+        Some(Err(_)) => return Err("is not a readable XLSX file"),
+        None => return Err("has no sheets"),
+    };
+
+    // The range starts at the first non-empty cell, which need not be A1.
+    let first_row = range.start().map_or(1, |(row, _)| row as usize + 1);
+    let rows = range.rows().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect();
+
+    Ok(Sheet { first_row, rows })
+}
+
+/// Reads a CSV or XLSX upload, telling them apart by content.
+pub fn read(bytes: &[u8]) -> Result<Sheet, &'static str> {
+    if is_xlsx(bytes) {
+        read_xlsx(bytes)
+    } else {
+        read_csv(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_csv_with_quotes_and_a_byte_order_mark() {
+        let sheet = read("\u{feff}question,answer_1,answer_2\n\"Is 1,5 > 1?\",\"Yes, \"\"really\"\"\",No\n".as_bytes()).unwrap();
+
+        assert_eq!(sheet.first_row, 1);
+        assert_eq!(sheet.rows, [
+            vec!["question", "answer_1", "answer_2"],
+            vec!["Is 1,5 > 1?", "Yes, \"really\"", "No"],
+        ]);
+    }
+
+    #[test]
+    fn picks_the_delimiter_from_the_first_line() {
+        assert_eq!(read(b"question;answer_1;answer_2\nA, B or C?;A;B\n").unwrap().rows[1], ["A, B or C?", "A", "B"]);
+        assert_eq!(read(b"question\tcorrect\nWhich?\t1,2\n").unwrap().rows[1], ["Which?", "1,2"]);
+    }
+
+    #[test]
+    fn keeps_short_rows_and_refuses_other_encodings() {
+        assert_eq!(read(b"a,b,c\nonly one\n").unwrap().rows[1], ["only one"]);
+        assert!(read(b"question\n\xff\xfe\n").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{self, SeekFrom};
+use std::path::PathBuf;
+use std::pin::Pin;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use futures::Stream;
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::OnceCell;
+use crate::env::MEDIA_DIR;
+
+/// How much of an object is read at a time when streaming it.
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Where uploaded media bytes live. Keys are content hashes, so an object never changes once
+/// written and backends are free to cache or deduplicate by key.
+#[async_trait]
+pub trait MediaStorage: Send + Sync {
+    /// Stores `bytes` under `key`. Writing a key that already exists is a no-op.
+    async fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()>;
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// The size of `key` in bytes, or `None` if it isn't stored.
+    async fn size(&self, key: &str) -> io::Result<Option<u64>>;
+
+    /// Bytes `start..=end` of `key`, read as they are consumed rather than all up front, so
+    /// serving a clip doesn't hold the whole file in memory.
+    async fn stream(&self, key: &str, start: u64, end: u64) -> io::Result<Option<ByteStream>>;
+
+    /// Removes `key`. Deleting a key that doesn't exist is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Stores media as files under a directory, sharded by the first two characters of the key.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(&key[..2.min(key.len())]).join(key)
+    }
+}
+
+#[async_trait]
+impl MediaStorage for LocalStorage {
+    async fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.path(key);
+
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Written aside and renamed into place so a reader never sees a half-written file.
+        let temporary = path.with_extension(format!("{:08x}.tmp", rand::rng().random::<u32>()));
+
+        tokio::fs::write(&temporary, bytes).await?;
+        tokio::fs::rename(&temporary, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn size(&self, key: &str) -> io::Result<Option<u64>> {
+        match tokio::fs::metadata(self.path(key)).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn stream(&self, key: &str, start: u64, end: u64) -> io::Result<Option<ByteStream>> {
+        let mut file = match tokio::fs::File::open(self.path(key)).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        file.seek(SeekFrom::Start(start)).await?;
+
+        let remaining = (end + 1).saturating_sub(start);
+
+        let chunks = futures::stream::try_unfold((file.take(remaining), remaining), |(mut file, remaining)| async move {
+            if remaining == 0 {
+                return Ok(None);
+            }
+
+            let mut chunk = vec![0; STREAM_CHUNK_BYTES.min(remaining as usize)];
+            let read = file.read(&mut chunk).await?;
+
+            if read == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "media file is shorter than expected"));
+            }
+
+            chunk.truncate(read);
+
+            Ok(Some((Bytes::from(chunk), (file, remaining - read as u64))))
+        });
+
+        Ok(Some(Box::pin(chunks)))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub static STORAGE: OnceCell<Box<dyn MediaStorage>> = OnceCell::const_new();
+
+pub fn init_storage() {
+    let storage = LocalStorage::new(MEDIA_DIR.clone());
+
+    if STORAGE.set(Box::new(storage)).is_err() {
+        panic!("Storage already initialized");
+    }
+}
+
+pub fn get_storage() -> &'static dyn MediaStorage {
+    STORAGE.get().expect("Storage not initialized").as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn streams_just_the_requested_range() {
+        let root = std::env::temp_dir().join(format!("media-test-{:016x}", rand::rng().random::<u64>()));
+        let storage = LocalStorage::new(&root);
+        let bytes: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+
+        storage.put("abcdef", &bytes).await.unwrap();
+
+        let storage = &storage;
+        let read = |start, end| async move {
+            let chunks: Vec<Bytes> = storage.stream("abcdef", start, end).await.unwrap().unwrap().try_collect().await.unwrap();
+
+            chunks.concat()
+        };
+
+        assert_eq!(storage.size("abcdef").await.unwrap(), Some(200_000));
+        assert_eq!(read(0, 199_999).await, bytes);
+        assert_eq!(read(70_000, 140_000).await, bytes[70_000..=140_000]);
+        assert!(storage.stream("missing", 0, 1).await.unwrap().is_none());
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+}
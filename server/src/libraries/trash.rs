@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::Database;
+use futures::TryStreamExt;
+use tracing::{error, info};
+
+use crate::env::TRASH_RETENTION_DAYS;
+use crate::models::id::QuizId;
+use crate::models::quiz::Quiz;
+use crate::models::revision::QuizRevision;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Milliseconds a quiz spends in the trash before the purge job removes it.
+pub fn retention_millis() -> i64 {
+    *TRASH_RETENTION_DAYS * 24 * 60 * 60 * 1000
+}
+
+/// Hard-deletes every trashed quiz matching `filter` together with its revision history.
+/// Returns how many quizzes were removed.
+///
+/// The quizzes are looked up inside the same transaction that deletes them, so one restored in
+/// the meantime makes the transaction conflict instead of losing its history.
+pub async fn purge(db: &Database, filter: Document) -> mongodb::error::Result<u64> {
+    let mut filter = filter;
+    filter.insert("is_deleted", true);
+
+    let mut session = db.client().start_session().await?;
+
+    let result: mongodb::error::Result<u64> = async {
+        session.start_transaction().await?;
+
+        let quiz_ids: Vec<QuizId> = db.collection::<Document>(Quiz::COLLECTION)
+            .find(filter)
+            .projection(doc! { "_id": 1 })
+            .session(&mut session)
+            .await?
+            .stream(&mut session)
+            .try_collect::<Vec<Document>>()
+            .await?
+            .iter()
+            .filter_map(|quiz| quiz.get_object_id("_id").ok().map(QuizId))
+            .collect();
+
+        if quiz_ids.is_empty() {
+            session.abort_transaction().await?;
+
+            return Ok(0);
+        }
+
+        let deleted = Quiz::collection(db)
+            .delete_many(doc! { "_id": { "$in": quiz_ids.clone() }, "is_deleted": true })
+            .session(&mut session)
+            .await?;
+
+        QuizRevision::collection(db)
+            .delete_many(doc! { "quiz_id": { "$in": quiz_ids } })
+            .session(&mut session)
+            .await?;
+
+        session.commit_transaction().await?;
+
+        Ok(deleted.deleted_count)
+    }.await;
+
+    if result.is_err() {
+        let _ = session.abort_transaction().await;
+    }
+
+    result
+}
+
+/// Runs `purge` for quizzes past the retention period once an hour for the lifetime of the server.
+pub fn spawn_purge_job(db: Database) {
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - retention_millis());
+
+            match purge(&db, doc! { "deleted_at": { "$lt": cutoff } }).await {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} quizzes from the trash", count),
+                Err(e) => error!("Failed to purge trash: {}", e),
+            }
+        }
+    });
+}
@@ -3,14 +3,16 @@ use actix_cors::Cors;
 use actix_web::http::header::HeaderName;
 use mongodb::bson::doc;
 use tracing::{info, Level};
-use tracing_subscriber;
-use crate::libraries::mongodb::{get_db, init_mongodb, create_index};
+use crate::libraries::mongodb::{get_db, init_mongodb, create_index, create_text_index, drop_unique_index};
+use crate::models::quiz::Quiz;
 use crate::libraries::redis::init_redis;
+use crate::libraries::storage::init_storage;
 
 mod env;
 mod routes;
 mod libraries;
 mod middlewares;
+mod models;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -24,6 +26,8 @@ async fn main() -> std::io::Result<()> {
 
     init_redis().await;
 
+    init_storage();
+
     // key: TELEGRAM_ONLY
     // match create_index("users", doc! { "tele_id": 1 }, true, true).await {
     //     Ok(index_name) => println!("Index created: {}", index_name.index_name),
@@ -40,11 +44,69 @@ async fn main() -> std::io::Result<()> {
         Err(e) => eprintln!("Failed to create index: {}", e),
     }
 
-    match create_index("quizzes", doc! { "owner_id": 1 }, true, false).await {
+    // Deployments from before visibility have this index as unique, which allows only one quiz
+    // per owner.
+    match drop_unique_index("quizzes", doc! { "owner_id": 1 }).await {
+        Ok(true) => println!("Index dropped: quizzes.owner_id (unique)"),
+        Ok(false) => {}
+        Err(e) => eprintln!("Failed to drop index: {}", e),
+    }
+
+    match create_index("quizzes", doc! { "owner_id": 1 }, false, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_index("quizzes", doc! { "collaborators.user_id": 1 }, false, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_text_index("quizzes", doc! {
+        "title": "text",
+        "description": "text",
+        "slides.question": "text",
+        "slides.title": "text",
+        "slides.body": "text",
+    }, Quiz::TEXT_LANGUAGE_FIELD).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_index("quizzes", doc! { "visibility": 1, "play_count": -1 }, false, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_index("quizzes", doc! { "owner_id": 1, "folder_id": 1 }, false, false).await {
         Ok(index_name) => println!("Index created: {}", index_name.index_name),
         Err(e) => eprintln!("Failed to create index: {}", e),
     }
 
+    match create_index("folders", doc! { "owner_id": 1, "parent_id": 1 }, false, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_index("media", doc! { "owner_id": 1, "key": 1 }, true, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_index("question_bank", doc! { "owner_id": 1, "tags": 1 }, false, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    match create_index("quiz_revisions", doc! { "quiz_id": 1, "revision": -1 }, true, false).await {
+        Ok(index_name) => println!("Index created: {}", index_name.index_name),
+        Err(e) => eprintln!("Failed to create index: {}", e),
+    }
+
+    libraries::trash::spawn_purge_job(get_db().clone());
+
+    libraries::media_gc::spawn_sweep_job(get_db().clone());
+
     info!(
         "Starting server at {}:{} with {} workers",
         config.app_host, config.app_port, config.worker_count
@@ -55,12 +117,14 @@ async fn main() -> std::io::Result<()> {
             .wrap(
                 Cors::default()
                     .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+                    .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
                     .allowed_headers(vec![
                         actix_web::http::header::AUTHORIZATION,
                         actix_web::http::header::ACCEPT,
                         actix_web::http::header::CONTENT_TYPE,
+                        actix_web::http::header::IF_MATCH,
                     ])
+                    .expose_headers(vec![actix_web::http::header::ETAG])
                     // key: TELEGRAM_ONLY
                     // .allowed_header(HeaderName::from_static("--webapp-hash"))
                     // .allowed_header(HeaderName::from_static("--webapp-init"))
@@ -2,14 +2,16 @@ use actix_web::{HttpRequest, HttpResponse, HttpMessage};
 use actix_web::web::Data;
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
-use mongodb::bson::{doc, DateTime, Document};
+use mongodb::bson::{doc, DateTime};
 use mongodb::Database;
 use crate::env::JWT_SECRET;
 use crate::libraries::{response_bad_request, response_forbidden, response_internal_server_error};
+use crate::models::id::UserId;
+use crate::models::user::User;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JwtUser {
-    pub user_id: String,
+    pub user_id: UserId,
     pub method: String,
 }
 
@@ -25,6 +27,10 @@ struct Claims {
     exp: usize,
 }
 
+pub fn user_id(req: &HttpRequest) -> Option<UserId> {
+    req.extensions().get::<RequestUser>().map(|data| data.user.user_id)
+}
+
 pub async fn middleware(req: &HttpRequest, db: &Data<Database>) -> Option<HttpResponse> {
     let token = match req.cookie("--auth-token") {
         Some(cookie) => cookie.value().to_string(),
@@ -40,13 +46,18 @@ pub async fn middleware(req: &HttpRequest, db: &Data<Database>) -> Option<HttpRe
         Err(_) => return Some(response_forbidden()),
     };
 
+    let user_id = match UserId::parse(&token_data.claims.sub) {
+        Some(user_id) => user_id,
+        None => return Some(response_forbidden()),
+    };
+
     let jwt_user = JwtUser {
-        user_id: (&token_data.claims.sub[2..]).to_string(),
+        user_id,
         method: token_data.claims.method,
     };
 
-    match db.collection::<Document>("users").update_one(
-        doc! { "google_id": jwt_user.user_id.clone() },
+    match User::collection(db).update_one(
+        doc! { "_id": jwt_user.user_id },
         doc! {
             "$set": {
                 "last_active": DateTime::now()
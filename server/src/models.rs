@@ -0,0 +1,19 @@
+pub mod id;
+
+pub mod slide;
+
+pub mod bank;
+
+pub mod folder;
+
+pub mod media;
+
+pub mod quiz;
+
+pub mod revision;
+
+pub mod room;
+
+pub mod edit_session;
+
+pub mod user;
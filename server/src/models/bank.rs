@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::models::id::{BankItemId, UserId};
+use crate::models::slide::Slide;
+
+/// A reusable question in a user's question bank, in the same format as a quiz slide.
+///
+/// Banks are per user only. Organization banks were asked for as well, but there are no
+/// organizations here to scope them to: users belong to nothing but themselves, and quizzes are
+/// shared one collaborator at a time. They need an organization model with membership first,
+/// after which an item would be owned by either a user or an organization.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BankItem {
+    #[serde(rename = "_id")]
+    pub id: BankItemId,
+    pub owner_id: UserId,
+    pub slide: Slide,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub updated_at: DateTime,
+    pub created_at: DateTime,
+}
+
+impl BankItem {
+    pub const COLLECTION: &'static str = "question_bank";
+
+    pub fn collection(db: &Database) -> Collection<BankItem> {
+        db.collection(Self::COLLECTION)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "item_id": self.id.to_string(),
+            "slide": self.slide.to_json(),
+            "tags": self.tags,
+            "updated_at": self.updated_at,
+            "created_at": self.created_at,
+        })
+    }
+
+    /// Replaces references to the owner's bank with the referenced slides. References to items
+    /// that have since been deleted are dropped; random draws are left in place.
+    pub async fn inline_items(db: &Database, owner_id: UserId, slides: &[Slide]) -> mongodb::error::Result<Vec<Slide>> {
+        let item_ids: Vec<BankItemId> = slides
+            .iter()
+            .filter_map(|slide| match slide {
+                Slide::BankItem(slide) => BankItemId::parse(&slide.item_id),
+                _ => None,
+            })
+            .collect();
+
+        if item_ids.is_empty() {
+            return Ok(slides.to_vec());
+        }
+
+        let items: HashMap<BankItemId, Slide> = BankItem::collection(db)
+            .find(doc! {
+                "_id": { "$in": item_ids },
+                "owner_id": owner_id,
+            })
+            .await?
+            .try_collect::<Vec<BankItem>>()
+            .await?
+            .into_iter()
+            .map(|item| (item.id, item.slide))
+            .collect();
+
+        Ok(slides
+            .iter()
+            .filter_map(|slide| match slide {
+                Slide::BankItem(slide) => BankItemId::parse(&slide.item_id).and_then(|id| items.get(&id).cloned()),
+                slide => Some(slide.clone()),
+            })
+            .collect())
+    }
+
+    /// Turns every placeholder into real slides for a room: bank references are inlined and each
+    /// random draw is filled by sampling the owner's bank, never repeating a question that is
+    /// already in the room.
+    ///
+    /// With a `draw_seed` the draws come out the same every time for that seed. Rooms hosted by
+    /// someone without a role on the quiz use one, so starting room after room can't be used to
+    /// read through the owner's bank.
+    pub async fn resolve_slides(db: &Database, owner_id: UserId, slides: &[Slide], draw_seed: Option<u64>) -> mongodb::error::Result<Vec<Slide>> {
+        let mut used: Vec<BankItemId> = slides
+            .iter()
+            .filter_map(|slide| match slide {
+                Slide::BankItem(slide) => BankItemId::parse(&slide.item_id),
+                _ => None,
+            })
+            .collect();
+
+        let slides = BankItem::inline_items(db, owner_id, slides).await?;
+        let mut resolved = Vec::with_capacity(slides.len());
+
+        for slide in slides {
+            let draw = match slide {
+                Slide::RandomDraw(draw) => draw,
+                slide => {
+                    resolved.push(slide);
+                    continue;
+                }
+            };
+
+            let mut filter = doc! {
+                "owner_id": owner_id,
+                "_id": { "$nin": used.clone() },
+            };
+
+            // Bank tags are stored normalized, the draw's tags are as the editor typed them.
+            let tags: Vec<String> = draw.tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+
+            if !tags.is_empty() {
+                filter.insert("tags", doc! { "$all": tags });
+            }
+
+            let drawn = match draw_seed {
+                Some(seed) => BankItem::seeded_draw(db, filter, draw.count as usize, seed).await?,
+                None => BankItem::collection(db)
+                    .aggregate(vec![
+                        doc! { "$match": filter },
+                        doc! { "$sample": { "size": draw.count as i64 } },
+                    ])
+                    .with_type::<BankItem>()
+                    .await?
+                    .try_collect()
+                    .await?,
+            };
+
+            for item in drawn {
+                used.push(item.id);
+                resolved.push(item.slide);
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// `count` items matching `filter`, picked by `seed` from the matches in id order.
+    async fn seeded_draw(db: &Database, filter: Document, count: usize, seed: u64) -> mongodb::error::Result<Vec<BankItem>> {
+        let mut ids: Vec<BankItemId> = db
+            .collection::<Document>(Self::COLLECTION)
+            .find(filter)
+            .projection(doc! { "_id": 1 })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .iter()
+            .filter_map(|item| item.get_object_id("_id").ok().map(BankItemId))
+            .collect();
+
+        ids.shuffle(&mut StdRng::seed_from_u64(seed));
+        ids.truncate(count);
+
+        let mut items: HashMap<BankItemId, BankItem> = BankItem::collection(db)
+            .find(doc! { "_id": { "$in": ids.clone() } })
+            .await?
+            .try_collect::<Vec<BankItem>>()
+            .await?
+            .into_iter()
+            .map(|item| (item.id, item))
+            .collect();
+
+        Ok(ids.iter().filter_map(|id| items.remove(id)).collect())
+    }
+}
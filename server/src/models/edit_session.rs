@@ -0,0 +1,167 @@
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::Database;
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::libraries::redis::RedisConn;
+use crate::models::id::{QuizId, UserId};
+use crate::models::revision::QuizRevision;
+use crate::models::slide::SlideEdit;
+
+pub const EDIT_SESSION_TTL: i64 = 3600;
+
+/// How many revisions an edit can be rebased over. A client further behind than this reloads the quiz.
+pub const EDIT_LOG_LENGTH: i64 = 200;
+
+/// Someone connected to the collaborative editor, and the slide they have open.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Editor {
+    pub connection_id: String,
+    pub user_id: UserId,
+    pub name: String,
+    pub slide: Option<usize>,
+}
+
+impl Editor {
+    /// The editor as presence messages show it, with ids spelled as everywhere else in the API.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "connection_id": self.connection_id,
+            "user_id": self.user_id.to_string(),
+            "name": self.name,
+            "slide": self.slide,
+        })
+    }
+
+    pub fn key(quiz_id: QuizId) -> String {
+        format!("quiz_editors:{}", quiz_id)
+    }
+
+    pub fn save(&self, redis_connect: &mut RedisConn, quiz_id: QuizId) -> RedisResult<()> {
+        let value = serde_json::to_string(self).unwrap_or_default();
+
+        redis_connect.hset(&Editor::key(quiz_id), &self.connection_id, &value)?;
+        redis_connect.expire(&Editor::key(quiz_id), EDIT_SESSION_TTL)
+    }
+
+    pub fn remove(redis_connect: &mut RedisConn, quiz_id: QuizId, connection_id: &str) -> RedisResult<()> {
+        redis_connect.hdel(&Editor::key(quiz_id), connection_id).map(|_| ())
+    }
+
+    pub fn list(redis_connect: &mut RedisConn, quiz_id: QuizId) -> Vec<Editor> {
+        redis_connect
+            .hgetall(&Editor::key(quiz_id))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str(&value).ok())
+            .collect()
+    }
+}
+
+/// An edit made through the collaborative editor, read back from the revision it produced so
+/// later edits based on older revisions can be rebased over it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoggedEdit {
+    pub revision: i64,
+    pub author_id: UserId,
+    /// Missing when the revision was saved some other way, e.g. through the REST API.
+    #[serde(default)]
+    pub edit: Option<SlideEdit>,
+}
+
+impl LoggedEdit {
+    /// The edits that took the quiz from `base_revision` to `current_revision`, in order. `None`
+    /// if the client is too far behind or any of those revisions wasn't a slide edit.
+    pub async fn since(db: &Database, quiz_id: QuizId, base_revision: i64, current_revision: i64) -> mongodb::error::Result<Option<Vec<SlideEdit>>> {
+        if current_revision - base_revision > EDIT_LOG_LENGTH {
+            return Ok(None);
+        }
+
+        let logged: Vec<LoggedEdit> = QuizRevision::collection(db)
+            .clone_with_type::<LoggedEdit>()
+            .find(doc! {
+                "quiz_id": quiz_id,
+                "revision": { "$gt": base_revision, "$lte": current_revision },
+            })
+            .projection(doc! { "revision": 1, "author_id": 1, "edit": 1 })
+            .sort(doc! { "revision": 1 })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(LoggedEdit::contiguous(logged, base_revision, current_revision))
+    }
+
+    /// The edits in `logged`, provided there is exactly one for every revision after
+    /// `base_revision` up to `current_revision`.
+    fn contiguous(logged: Vec<LoggedEdit>, base_revision: i64, current_revision: i64) -> Option<Vec<SlideEdit>> {
+        if logged.len() as i64 != current_revision - base_revision {
+            return None;
+        }
+
+        logged
+            .into_iter()
+            .zip(base_revision + 1..=current_revision)
+            .map(|(logged, revision)| logged.edit.filter(|_| logged.revision == revision))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logged(revision: i64, edit: Option<SlideEdit>) -> LoggedEdit {
+        LoggedEdit {
+            revision,
+            author_id: UserId::new(),
+            edit,
+        }
+    }
+
+    #[test]
+    fn rebases_over_an_unbroken_run_of_edits() {
+        let edits = vec![
+            logged(4, Some(SlideEdit::Delete { index: 0 })),
+            logged(5, Some(SlideEdit::Duplicate { index: 1 })),
+        ];
+
+        let applied = LoggedEdit::contiguous(edits, 3, 5).unwrap();
+
+        assert!(matches!(applied[..], [SlideEdit::Delete { index: 0 }, SlideEdit::Duplicate { index: 1 }]));
+        assert!(LoggedEdit::contiguous(Vec::new(), 5, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn resyncs_on_gaps_and_other_saves() {
+        let gap = vec![
+            logged(4, Some(SlideEdit::Delete { index: 0 })),
+            logged(6, Some(SlideEdit::Delete { index: 0 })),
+        ];
+        let short = vec![logged(4, Some(SlideEdit::Delete { index: 0 }))];
+        let rest_save = vec![
+            logged(4, Some(SlideEdit::Delete { index: 0 })),
+            logged(5, None),
+        ];
+
+        assert!(LoggedEdit::contiguous(gap, 3, 5).is_none());
+        assert!(LoggedEdit::contiguous(short, 3, 5).is_none());
+        assert!(LoggedEdit::contiguous(rest_save, 3, 5).is_none());
+    }
+
+    #[test]
+    fn presence_spells_ids_like_the_api() {
+        let user_id = UserId::new();
+        let editor = Editor {
+            connection_id: "abc".to_string(),
+            user_id,
+            name: "Ada".to_string(),
+            slide: Some(2),
+        };
+
+        assert_eq!(editor.to_json()["user_id"], user_id.to_string());
+        assert!(editor.to_json()["user_id"].as_str().unwrap().starts_with("0x"));
+    }
+}
@@ -0,0 +1,44 @@
+use mongodb::bson::{doc, DateTime};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::models::id::{FolderId, UserId};
+
+/// Folders are private to their owner and nest through `parent_id`; `None` is the root.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Folder {
+    #[serde(rename = "_id")]
+    pub id: FolderId,
+    pub owner_id: UserId,
+    pub name: String,
+    #[serde(default)]
+    pub parent_id: Option<FolderId>,
+    pub updated_at: DateTime,
+    pub created_at: DateTime,
+}
+
+impl Folder {
+    pub const COLLECTION: &'static str = "folders";
+
+    pub fn collection(db: &Database) -> Collection<Folder> {
+        db.collection(Self::COLLECTION)
+    }
+
+    pub async fn find_owned(db: &Database, folder_id: FolderId, owner_id: UserId) -> mongodb::error::Result<Option<Folder>> {
+        Self::collection(db).find_one(doc! {
+            "_id": folder_id,
+            "owner_id": owner_id,
+        }).await
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "folder_id": self.id.to_string(),
+            "name": self.name,
+            "parent_id": self.parent_id.map(|parent_id| parent_id.to_string()),
+            "updated_at": self.updated_at,
+            "created_at": self.created_at,
+        })
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+use mongodb::bson::{oid::ObjectId, Bson};
+use serde::{Deserialize, Serialize};
+
+/// Ids are stored as plain `ObjectId`s and exposed through the API as `0x`-prefixed hex.
+macro_rules! object_id {
+    ($name:ident) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub ObjectId);
+
+        impl $name {
+            pub fn new() -> Self {
+                $name(ObjectId::new())
+            }
+
+            pub fn parse(value: &str) -> Option<Self> {
+                let hex = value.strip_prefix("0x").unwrap_or(value);
+
+                ObjectId::parse_str(hex).ok().map($name)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "0x{}", self.0.to_hex())
+            }
+        }
+
+        impl From<$name> for Bson {
+            fn from(id: $name) -> Self {
+                Bson::ObjectId(id.0)
+            }
+        }
+    };
+}
+
+object_id!(QuizId);
+
+object_id!(FolderId);
+
+object_id!(BankItemId);
+
+object_id!(MediaId);
+
+object_id!(UserId);
@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::ReturnDocument;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::libraries::media::{render_reveal, MediaKind, ProcessedImage, RevealMode};
+use crate::libraries::storage::get_storage;
+use crate::models::id::{MediaId, UserId};
+use crate::models::slide::Slide;
+
+/// Prefix of the URL uploaded media is served from; slides store `image_path` and clip paths as
+/// this plus the key.
+pub const MEDIA_PATH_PREFIX: &str = "/api/media/";
+
+/// One user's claim on a stored image or clip. The bytes are content addressed by `key`, so
+/// several users uploading the same file share the stored object but each has their own record.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Media {
+    #[serde(rename = "_id")]
+    pub id: MediaId,
+    pub owner_id: UserId,
+    pub key: String,
+    #[serde(default)]
+    pub kind: MediaKind,
+    pub content_type: String,
+    pub size: i64,
+    /// Images only.
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// Clips only: length in seconds and audio codec.
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub codec: Option<String>,
+    pub created_at: DateTime,
+    /// The last media sweep that found the key in use. The sweep leaves records alone for a
+    /// grace period after this as well as after `created_at`.
+    #[serde(default)]
+    pub last_referenced_at: Option<DateTime>,
+}
+
+/// The rendered reveal stages of one image in one mode, kept so each image is only rendered once
+/// per mode no matter how many rooms show it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RevealVariants {
+    /// `{key}:{mode}`.
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// Stage keys, most obscured first.
+    pub keys: Vec<String>,
+    pub created_at: DateTime,
+}
+
+impl RevealVariants {
+    pub const COLLECTION: &'static str = "media_reveals";
+
+    pub fn collection(db: &Database) -> Collection<RevealVariants> {
+        db.collection(Self::COLLECTION)
+    }
+
+    /// Looks up the stages of `key` in `mode`, rendering and storing them the first time.
+    pub async fn find_or_render(db: &Database, key: &str, mode: RevealMode) -> mongodb::error::Result<Vec<String>> {
+        let id = format!("{}:{}", key, mode.as_str());
+
+        if let Some(variants) = RevealVariants::collection(db).find_one(doc! { "_id": &id }).await? {
+            return Ok(variants.keys);
+        }
+
+        let storage_error = |e: std::io::Error| mongodb::error::Error::custom(e.to_string());
+
+        let bytes = get_storage()
+            .get(key)
+            .await
+            .map_err(storage_error)?
+            .ok_or_else(|| mongodb::error::Error::custom("Media not found"))?;
+
+        let source_key = key.to_string();
+
+        let stages = tokio::task::spawn_blocking(move || render_reveal(&bytes, &source_key, mode))
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| mongodb::error::Error::custom("Failed to render reveal stages"))?;
+
+        for stage in &stages {
+            get_storage().put(&stage.key, &stage.bytes).await.map_err(storage_error)?;
+        }
+
+        let variants = RevealVariants {
+            id,
+            keys: stages.into_iter().map(|stage| stage.key).collect(),
+            created_at: DateTime::now(),
+        };
+
+        // Two rooms rendering the same image at once produce identical stages, so losing the
+        // race to insert is fine.
+        let _ = RevealVariants::collection(db).insert_one(&variants).await;
+
+        Ok(variants.keys)
+    }
+
+    /// Stage paths for every slide that reveals an uploaded image, by slide index.
+    pub async fn for_slides(db: &Database, slides: &[Slide]) -> mongodb::error::Result<HashMap<usize, Vec<String>>> {
+        let mut reveals = HashMap::new();
+
+        for (index, slide) in slides.iter().enumerate() {
+            let (mode, key) = match (slide.reveal_mode(), slide.image_path().and_then(Media::key_from_path)) {
+                (Some(mode), Some(key)) => (mode, key),
+                _ => continue,
+            };
+
+            let keys = RevealVariants::find_or_render(db, key, mode).await?;
+
+            reveals.insert(index, keys.iter().map(|key| format!("{}{}", MEDIA_PATH_PREFIX, key)).collect());
+        }
+
+        Ok(reveals)
+    }
+}
+
+impl Media {
+    pub const COLLECTION: &'static str = "media";
+
+    pub fn collection(db: &Database) -> Collection<Media> {
+        db.collection(Self::COLLECTION)
+    }
+
+    pub fn path(&self) -> String {
+        format!("{}{}", MEDIA_PATH_PREFIX, self.key)
+    }
+
+    /// The media key an `image_path` points at, if it points at uploaded media at all.
+    pub fn key_from_path(path: &str) -> Option<&str> {
+        path.strip_prefix(MEDIA_PATH_PREFIX)
+    }
+
+    /// Keys of every uploaded image or clip the slides reference.
+    pub fn keys_in(slides: &[Slide]) -> Vec<String> {
+        let mut keys = Vec::new();
+
+        for path in slides.iter().flat_map(Slide::media_paths) {
+            if let Some(key) = Media::key_from_path(path) {
+                if !keys.iter().any(|existing| existing == key) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+
+        keys
+    }
+
+    /// The records of `keys` belonging to any of `owner_ids`, by key.
+    pub async fn find_owned(db: &Database, owner_ids: &[UserId], keys: Vec<String>) -> mongodb::error::Result<HashMap<String, Media>> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let media: Vec<Media> = Media::collection(db)
+            .find(doc! {
+                "owner_id": { "$in": owner_ids.to_vec() },
+                "key": { "$in": keys },
+            })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(media.into_iter().map(|media| (media.key.clone(), media)).collect())
+    }
+
+    /// Stores an uploaded file and gives `owner_id` a record of it with `fields` (kind, content type
+    /// and the kind's metadata). Storing the same file twice returns the existing record.
+    pub async fn store(db: &Database, owner_id: UserId, key: &str, bytes: &[u8], fields: Document) -> mongodb::error::Result<Option<Media>> {
+        get_storage()
+            .put(key, bytes)
+            .await
+            .map_err(|e| mongodb::error::Error::custom(e.to_string()))?;
+
+        let mut insert = doc! {
+            "_id": MediaId::new(),
+            "size": bytes.len() as i64,
+            "created_at": DateTime::now(),
+        };
+        insert.extend(fields);
+
+        Media::collection(db)
+            .find_one_and_update(
+                doc! { "owner_id": owner_id, "key": key },
+                doc! { "$setOnInsert": insert },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+    }
+
+    pub async fn store_image(db: &Database, owner_id: UserId, image: &ProcessedImage) -> mongodb::error::Result<Option<Media>> {
+        Media::store(db, owner_id, &image.key, &image.bytes, doc! {
+            "kind": MediaKind::Image.as_str(),
+            "content_type": image.content_type,
+            "width": image.width,
+            "height": image.height,
+        }).await
+    }
+
+    /// Gives `owner_id` their own record for each existing image in `keys`, e.g. when they copy
+    /// someone else's quiz, so the copy keeps passing the ownership check on save.
+    pub async fn grant(db: &Database, owner_id: UserId, keys: Vec<String>) -> mongodb::error::Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let media: Vec<Media> = Media::collection(db)
+            .find(doc! { "key": { "$in": keys } })
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut granted = HashSet::new();
+
+        for media in media {
+            if !granted.insert(media.key.clone()) {
+                continue;
+            }
+
+            Media::collection(db)
+                .update_one(
+                    doc! { "owner_id": owner_id, "key": &media.key },
+                    doc! {
+                        "$setOnInsert": {
+                            "_id": MediaId::new(),
+                            "kind": media.kind.as_str(),
+                            "content_type": &media.content_type,
+                            "size": media.size,
+                            "width": media.width,
+                            "height": media.height,
+                            "duration": media.duration,
+                            "codec": &media.codec,
+                            "created_at": DateTime::now(),
+                        }
+                    },
+                )
+                .upsert(true)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "media_id": self.id.to_string(),
+            "path": self.path(),
+            "kind": self.kind,
+            "content_type": self.content_type,
+            "size": self.size,
+            "width": self.width,
+            "height": self.height,
+            "duration": self.duration,
+            "codec": self.codec,
+            "created_at": self.created_at,
+        })
+    }
+}
@@ -0,0 +1,352 @@
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::models::id::{FolderId, QuizId, UserId};
+use crate::models::slide::Slide;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Owner,
+    Editor,
+    CoHost,
+    Viewer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Permission {
+    View,
+    Collaborate,
+    Edit,
+    Host,
+    Manage,
+}
+
+impl Role {
+    pub fn allows(self, permission: Permission) -> bool {
+        match permission {
+            Permission::View | Permission::Collaborate => true,
+            Permission::Edit => matches!(self, Role::Owner | Role::Editor),
+            Permission::Host => matches!(self, Role::Owner | Role::CoHost),
+            Permission::Manage => self == Role::Owner,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    #[default]
+    Private,
+    Unlisted,
+    Public,
+}
+
+impl Visibility {
+    /// What anyone holding the link gets, on top of whatever role they have on the quiz.
+    pub fn allows(self, permission: Permission) -> bool {
+        match self {
+            Visibility::Private => false,
+            Visibility::Unlisted | Visibility::Public => {
+                matches!(permission, Permission::View | Permission::Host)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Collaborator {
+    pub user_id: UserId,
+    pub role: Role,
+    pub invited_by: UserId,
+    pub invited_at: DateTime,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ForkedFrom {
+    pub quiz_id: QuizId,
+    pub revision: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Quiz {
+    #[serde(rename = "_id")]
+    pub id: QuizId,
+    pub owner_id: UserId,
+    pub title: String,
+    pub description: Option<String>,
+    pub slides: Vec<Slide>,
+    #[serde(default)]
+    pub revision: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<ForkedFrom>,
+    #[serde(default)]
+    pub collaborators: Vec<Collaborator>,
+    /// Folder in the owner's tree; collaborators see shared quizzes outside of any folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_id: Option<FolderId>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub grade_level: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub play_count: i64,
+    pub updated_at: DateTime,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub is_deleted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+}
+
+impl Quiz {
+    pub const COLLECTION: &'static str = "quizzes";
+
+    /// The text index's `language_override`. Quizzes never have this field, so their `language`,
+    /// which is free text or null, is not taken as the language to index in.
+    pub const TEXT_LANGUAGE_FIELD: &'static str = "text_language";
+
+    pub fn collection(db: &Database) -> Collection<Quiz> {
+        db.collection(Self::COLLECTION)
+    }
+
+    /// Matches every quiz the user owns or collaborates on.
+    pub fn access_filter(user_id: UserId) -> Document {
+        doc! {
+            "$or": [
+                { "owner_id": user_id },
+                { "collaborators.user_id": user_id },
+            ]
+        }
+    }
+
+    /// Matches quizzes the user can reach either through a role or through the quiz's visibility.
+    pub fn reachable_filter(user_id: UserId) -> Document {
+        doc! {
+            "$or": [
+                { "owner_id": user_id },
+                { "collaborators.user_id": user_id },
+                { "visibility": { "$in": ["unlisted", "public"] } },
+            ]
+        }
+    }
+
+    pub fn allows(&self, user_id: UserId, permission: Permission) -> bool {
+        self.role_of(user_id).is_some_and(|role| role.allows(permission))
+            || self.visibility.allows(permission)
+    }
+
+    pub fn role_of(&self, user_id: UserId) -> Option<Role> {
+        if self.owner_id == user_id {
+            return Some(Role::Owner);
+        }
+
+        self.collaborators
+            .iter()
+            .find(|collaborator| collaborator.user_id == user_id)
+            .map(|collaborator| collaborator.role)
+    }
+
+    /// The quiz as `user_id` may see it. Anyone reaching it only through its visibility gets the
+    /// slides without their answers.
+    pub fn to_json_for(&self, user_id: UserId) -> Value {
+        let mut value = self.to_json();
+        let role = self.role_of(user_id);
+
+        value["role"] = json!(role);
+
+        if role.is_none() {
+            value["slides"] = json!(self.slides.iter().map(Slide::to_public_json).collect::<Vec<_>>());
+        }
+
+        if self.owner_id == user_id {
+            value["folder_id"] = json!(self.folder_id.map(|folder_id| folder_id.to_string()));
+        }
+
+        value
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "quiz_id": self.id.to_string(),
+            "owner_id": self.owner_id.to_string(),
+            "title": self.title,
+            "description": self.description,
+            "slides": self.slides.iter().map(Slide::to_json).collect::<Vec<_>>(),
+            "visibility": self.visibility,
+            "tags": self.tags,
+            "subject": self.subject,
+            "grade_level": self.grade_level,
+            "language": self.language,
+            "play_count": self.play_count,
+            "revision": self.revision,
+            "forked_from": self.forked_from.as_ref().map(|forked_from| json!({
+                "quiz_id": forked_from.quiz_id.to_string(),
+                "revision": forked_from.revision,
+            })),
+            "updated_at": self.updated_at,
+            "created_at": self.created_at,
+        })
+    }
+
+    /// Library listing entry: enough to pick a quiz without shipping every slide.
+    pub fn to_summary_json(&self, owner_name: Option<&str>) -> Value {
+        json!({
+            "quiz_id": self.id.to_string(),
+            "owner_id": self.owner_id.to_string(),
+            "owner_name": owner_name,
+            "title": self.title,
+            "description": self.description,
+            "slide_count": self.slides.len(),
+            "tags": self.tags,
+            "subject": self.subject,
+            "grade_level": self.grade_level,
+            "language": self.language,
+            "play_count": self.play_count,
+            "updated_at": self.updated_at,
+            "created_at": self.created_at,
+        })
+    }
+}
+
+/// The lightweight shape of a quiz used by listings; the slide array is reduced on the server by
+/// `QuizSummary::projection` so it never leaves the database.
+#[derive(Clone, Deserialize)]
+pub struct QuizSummary {
+    #[serde(rename = "_id")]
+    pub id: QuizId,
+    pub owner_id: UserId,
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub collaborators: Vec<Collaborator>,
+    #[serde(default)]
+    pub folder_id: Option<FolderId>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub slide_count: i64,
+    #[serde(default)]
+    pub first_image: Option<String>,
+    #[serde(default)]
+    pub revision: i64,
+    pub updated_at: DateTime,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime>,
+}
+
+impl QuizSummary {
+    pub fn collection(db: &Database) -> Collection<QuizSummary> {
+        db.collection(Quiz::COLLECTION)
+    }
+
+    pub fn projection() -> Document {
+        doc! {
+            "owner_id": 1,
+            "title": 1,
+            "description": 1,
+            "collaborators": 1,
+            "folder_id": 1,
+            "visibility": 1,
+            "tags": 1,
+            "revision": 1,
+            "updated_at": 1,
+            "created_at": 1,
+            "deleted_at": 1,
+            "slide_count": { "$size": { "$ifNull": ["$slides", []] } },
+            "first_image": {
+                "$arrayElemAt": [
+                    {
+                        "$filter": {
+                            "input": { "$ifNull": ["$slides.image_path", []] },
+                            "cond": { "$and": [{ "$ne": ["$$this", null] }, { "$ne": ["$$this", ""] }] },
+                        }
+                    },
+                    0,
+                ]
+            },
+        }
+    }
+
+    pub fn role_of(&self, user_id: UserId) -> Option<Role> {
+        if self.owner_id == user_id {
+            return Some(Role::Owner);
+        }
+
+        self.collaborators
+            .iter()
+            .find(|collaborator| collaborator.user_id == user_id)
+            .map(|collaborator| collaborator.role)
+    }
+
+    pub fn to_json_for(&self, user_id: UserId) -> Value {
+        json!({
+            "quiz_id": self.id.to_string(),
+            "owner_id": self.owner_id.to_string(),
+            "role": self.role_of(user_id),
+            "folder_id": self.folder_id.filter(|_| self.owner_id == user_id).map(|folder_id| folder_id.to_string()),
+            "title": self.title,
+            "description": self.description,
+            "visibility": self.visibility,
+            "tags": self.tags,
+            "slide_count": self.slide_count,
+            "first_image": self.first_image,
+            "revision": self.revision,
+            "updated_at": self.updated_at,
+            "created_at": self.created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson;
+    use super::*;
+
+    fn quiz(language: Option<&str>) -> Quiz {
+        let now = DateTime::now();
+
+        Quiz {
+            id: QuizId::new(),
+            owner_id: UserId::new(),
+            title: "Capitals".to_string(),
+            description: None,
+            slides: Vec::new(),
+            revision: 1,
+            forked_from: None,
+            collaborators: Vec::new(),
+            folder_id: None,
+            visibility: Visibility::Private,
+            tags: Vec::new(),
+            subject: None,
+            grade_level: None,
+            language: language.map(str::to_string),
+            play_count: 0,
+            updated_at: now,
+            created_at: now,
+            is_deleted: false,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn stored_quizzes_leave_the_text_index_language_alone() {
+        for language in [None, Some("vi"), Some("Vietnamese"), Some("English (US)")] {
+            let document = bson::to_document(&quiz(language)).unwrap();
+
+            assert!(!document.contains_key(Quiz::TEXT_LANGUAGE_FIELD));
+            assert_eq!(document.get("language").and_then(|value| value.as_str()), language);
+        }
+    }
+}
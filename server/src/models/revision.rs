@@ -0,0 +1,107 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::{ClientSession, Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::models::id::{QuizId, UserId};
+use crate::models::quiz::Quiz;
+use crate::models::slide::{Slide, SlideEdit};
+
+/// An immutable copy of a quiz, written every time its content changes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QuizRevision {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub quiz_id: QuizId,
+    pub revision: i64,
+    pub author_id: UserId,
+    pub title: String,
+    pub description: Option<String>,
+    pub slides: Vec<Slide>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<i64>,
+    /// The slide edit that produced this revision, when it came from one. The collaborative
+    /// editor rebases edits made against older revisions over these.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit: Option<SlideEdit>,
+    pub created_at: DateTime,
+}
+
+impl QuizRevision {
+    pub const COLLECTION: &'static str = "quiz_revisions";
+
+    pub fn collection(db: &Database) -> Collection<QuizRevision> {
+        db.collection(Self::COLLECTION)
+    }
+
+    pub fn from_quiz(quiz: &Quiz, author_id: UserId, restored_from: Option<i64>) -> Self {
+        QuizRevision {
+            id: ObjectId::new(),
+            quiz_id: quiz.id,
+            revision: quiz.revision,
+            author_id,
+            title: quiz.title.clone(),
+            description: quiz.description.clone(),
+            slides: quiz.slides.clone(),
+            restored_from,
+            edit: None,
+            created_at: quiz.updated_at,
+        }
+    }
+
+    pub async fn record(
+        db: &Database,
+        session: &mut ClientSession,
+        quiz: &Quiz,
+        author_id: UserId,
+        restored_from: Option<i64>,
+    ) -> mongodb::error::Result<()> {
+        QuizRevision::from_quiz(quiz, author_id, restored_from).insert(db, session).await
+    }
+
+    /// `record` for a revision made by a single slide edit, which is stored with it.
+    pub async fn record_edit(
+        db: &Database,
+        session: &mut ClientSession,
+        quiz: &Quiz,
+        author_id: UserId,
+        edit: &SlideEdit,
+    ) -> mongodb::error::Result<()> {
+        let mut revision = QuizRevision::from_quiz(quiz, author_id, None);
+        revision.edit = Some(edit.clone());
+
+        revision.insert(db, session).await
+    }
+
+    async fn insert(&self, db: &Database, session: &mut ClientSession) -> mongodb::error::Result<()> {
+        QuizRevision::collection(db)
+            .insert_one(self)
+            .session(session)
+            .await
+            .map(|_| ())
+    }
+
+    pub fn to_summary_json(&self) -> Value {
+        json!({
+            "revision": self.revision,
+            "author_id": self.author_id.to_string(),
+            "title": self.title,
+            "slide_count": self.slides.len(),
+            "restored_from": self.restored_from,
+            "created_at": self.created_at,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "quiz_id": self.quiz_id.to_string(),
+            "revision": self.revision,
+            "author_id": self.author_id.to_string(),
+            "title": self.title,
+            "description": self.description,
+            "slides": self.slides.iter().map(Slide::to_json).collect::<Vec<_>>(),
+            "restored_from": self.restored_from,
+            "created_at": self.created_at,
+        })
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::libraries::redis::{with_transaction, RedisConn};
+use crate::models::id::{QuizId, UserId};
+use crate::models::quiz::Quiz;
+use crate::models::slide::Slide;
+
+pub const ROOM_TTL: u64 = 3600;
+
+/// How many times `Room::update` retries when another write to the room gets in first.
+const UPDATE_ATTEMPTS: usize = 16;
+
+fn serialize<T: Serialize>(value: &T) -> redis::RedisResult<String> {
+    serde_json::to_string(value).map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::TypeError, "Failed to serialize room", e.to_string()))
+    })
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseStatus {
+    Pending,
+    Held,
+    Approved,
+    Hidden,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OpenResponse {
+    pub response_id: String,
+    pub slide_index: i32,
+    pub player_id: String,
+    pub nickname: String,
+    pub text: String,
+    pub status: ResponseStatus,
+    pub submitted_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlayerAnswer {
+    pub slide_index: i32,
+    pub player_id: String,
+    pub answers: Vec<usize>,
+    /// What the player typed, on short answer and numerical slides.
+    #[serde(default)]
+    pub text: Option<String>,
+    pub correct: bool,
+    pub points: i64,
+    pub submitted_at: i64,
+}
+
+/// The quiz as it was when the room was created. Gameplay reads only from this copy, so edits or
+/// deletes made while a game is running don't reach the players.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QuizSnapshot {
+    pub quiz_id: QuizId,
+    pub revision: i64,
+    pub title: String,
+    pub slides: Vec<Slide>,
+    /// Paths of the obscured stages of each reveal slide's image, by slide index. Players only
+    /// ever get these until the slide's time is up.
+    #[serde(default)]
+    pub reveals: HashMap<usize, Vec<String>>,
+}
+
+impl QuizSnapshot {
+    pub fn from_quiz(quiz: &Quiz, reveals: HashMap<usize, Vec<String>>) -> Self {
+        QuizSnapshot {
+            quiz_id: quiz.id,
+            revision: quiz.revision,
+            title: quiz.title.clone(),
+            slides: quiz.slides.clone(),
+            reveals,
+        }
+    }
+
+    /// Hash-tagged on the room code like [`Room::key`], so a cluster keeps the two in one slot
+    /// and the transactions that touch both don't fail with CROSSSLOT.
+    pub fn key(room_code: &str) -> String {
+        format!("quiz_snapshot:{{{}}}", room_code)
+    }
+
+    pub fn load(redis_connect: &mut RedisConn, room_code: &str) -> Option<QuizSnapshot> {
+        let snapshot_str = redis_connect.get(&QuizSnapshot::key(room_code)).ok()??;
+
+        serde_json::from_str(&snapshot_str).ok()
+    }
+}
+
+/// Options the host picks when opening a room.
+///
+/// There is no option to shuffle the questions. It was asked for per player in self-paced mode,
+/// but rooms here are host-paced: every player is on the host's slide, with its timer, reveal
+/// stages and clip, so players can't each be on a different question. It waits for a self-paced
+/// mode, where the order would be seeded per player the way `Room::answer_order` is.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RoomSettings {
+    /// Show each player the answers of a question in their own order.
+    #[serde(default)]
+    pub shuffle_answers: bool,
+}
+
+/// Why `Room::update` didn't save.
+pub enum UpdateError<E> {
+    NotFound,
+    Redis(redis::RedisError),
+    /// The change itself refused to apply.
+    Rejected(E),
+}
+
+impl<E> From<redis::RedisError> for UpdateError<E> {
+    fn from(error: redis::RedisError) -> Self {
+        UpdateError::Redis(error)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Room {
+    pub quiz_id: QuizId,
+    #[serde(default)]
+    pub quiz_revision: i64,
+    pub owner_id: UserId,
+    pub room_code: String,
+    pub created_at: i64,
+    pub players: HashMap<String, String>,
+    /// Scores by player, kept when a player drops out so they pick up where they left off.
+    pub scores: HashMap<String, i64>,
+    /// Which socket each player is connected on, so a socket closing after its player has
+    /// reconnected elsewhere doesn't take them out of the room.
+    #[serde(default)]
+    pub connections: HashMap<String, String>,
+    pub current_slide: i32,
+    pub started: bool,
+    /// Set once the host ends the game, so nothing more is accepted while the result is stored.
+    #[serde(default)]
+    pub ended: bool,
+    #[serde(default)]
+    pub host_id: Option<String>,
+    #[serde(default)]
+    pub slide_started_at: Option<i64>,
+    #[serde(default)]
+    pub answers: Vec<PlayerAnswer>,
+    #[serde(default)]
+    pub responses: Vec<OpenResponse>,
+    #[serde(default)]
+    pub settings: RoomSettings,
+    #[serde(default)]
+    pub shuffle_seed: u64,
+}
+
+impl Room {
+    pub fn new(snapshot: &QuizSnapshot, owner_id: UserId, room_code: String, settings: RoomSettings, created_at: i64) -> Self {
+        Room {
+            quiz_id: snapshot.quiz_id,
+            quiz_revision: snapshot.revision,
+            owner_id,
+            room_code,
+            created_at,
+            players: HashMap::new(),
+            scores: HashMap::new(),
+            connections: HashMap::new(),
+            current_slide: 0,
+            started: false,
+            ended: false,
+            host_id: None,
+            slide_started_at: None,
+            answers: Vec::new(),
+            responses: Vec::new(),
+            settings,
+            shuffle_seed: rand::rng().random(),
+        }
+    }
+
+    pub fn key(room_code: &str) -> String {
+        format!("quiz_room:{{{}}}", room_code)
+    }
+
+    pub fn load(redis_connect: &mut RedisConn, room_code: &str) -> Option<Room> {
+        let room_str = redis_connect.get(&Room::key(room_code)).ok()??;
+
+        serde_json::from_str(&room_str).ok()
+    }
+
+    /// Applies `change` to the room as it is in Redis and saves it, without losing writes made
+    /// by other players' actions in between: the room is watched while `change` runs, and if it
+    /// was saved by someone else before ours, `change` runs again on the newer room.
+    pub fn update<T, E>(
+        redis_connect: &mut RedisConn,
+        room_code: &str,
+        mut change: impl FnMut(&mut Room) -> Result<T, E>,
+    ) -> Result<(Room, T), UpdateError<E>> {
+        let key = Room::key(room_code);
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            redis_connect.watch(&key)?;
+
+            let mut room = match Room::load(redis_connect, room_code) {
+                Some(room) => room,
+                None => {
+                    redis_connect.unwatch()?;
+                    return Err(UpdateError::NotFound);
+                }
+            };
+
+            let value = match change(&mut room) {
+                Ok(value) => value,
+                Err(error) => {
+                    redis_connect.unwatch()?;
+                    return Err(UpdateError::Rejected(error));
+                }
+            };
+
+            let mut pipe = redis::pipe();
+            pipe.set_ex(&key, serialize(&room)?, ROOM_TTL).ignore();
+            pipe.expire(QuizSnapshot::key(room_code), ROOM_TTL as i64).ignore();
+
+            if redis_connect.exec::<()>(&pipe)?.is_some() {
+                return Ok((room, value));
+            }
+        }
+
+        Err(UpdateError::Redis(redis::RedisError::from((
+            redis::ErrorKind::ExecAbortError,
+            "Room kept changing while being updated",
+        ))))
+    }
+
+    pub fn create(&self, snapshot: &QuizSnapshot) -> redis::RedisResult<()> {
+        let room_str = serialize(self)?;
+        let snapshot_str = serialize(snapshot)?;
+
+        with_transaction(|pipe| {
+            pipe.set_ex(Room::key(&self.room_code), &room_str, ROOM_TTL);
+            pipe.set_ex(QuizSnapshot::key(&self.room_code), &snapshot_str, ROOM_TTL);
+            Ok(())
+        })
+    }
+
+    pub fn delete(&self) -> redis::RedisResult<()> {
+        with_transaction(|pipe| {
+            pipe.del(Room::key(&self.room_code));
+            pipe.del(QuizSnapshot::key(&self.room_code));
+            Ok(())
+        })
+    }
+
+    /// How the answers of a slide are laid out for one player: `order[shown]` is the canonical
+    /// index. Seeded from the room, the player and the slide, so the same player always gets the
+    /// same order and submitted positions can be mapped back before scoring.
+    pub fn answer_order(&self, player_id: &str, slide_index: i32, count: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..count).collect();
+
+        if self.settings.shuffle_answers {
+            let seed = Sha256::digest(format!("{}:{}:{}", self.shuffle_seed, player_id, slide_index));
+
+            order.shuffle(&mut StdRng::from_seed(seed.into()));
+        }
+
+        order
+    }
+
+    /// Started and not yet ended: the only time answers and slide changes are taken.
+    pub fn is_running(&self) -> bool {
+        self.started && !self.ended
+    }
+
+    pub fn has_answered(&self, player_id: &str) -> bool {
+        self.answers.iter().any(|answer| answer.slide_index == self.current_slide && answer.player_id == player_id)
+    }
+
+    pub fn is_host(&self, user_id: Option<UserId>) -> bool {
+        user_id == Some(self.owner_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shuffles_answers_per_player() {
+        let snapshot = QuizSnapshot {
+            quiz_id: QuizId::new(),
+            revision: 1,
+            title: String::new(),
+            slides: Vec::new(),
+            reveals: HashMap::new(),
+        };
+        let settings = RoomSettings { shuffle_answers: true };
+        let room = Room::new(&snapshot, UserId::new(), "123456".to_string(), settings, 0);
+
+        let mut order = room.answer_order("ann", 0, 6);
+        assert_eq!(order, room.answer_order("ann", 0, 6));
+
+        order.sort_unstable();
+        assert_eq!(order, (0..6).collect::<Vec<_>>());
+
+        let players: Vec<String> = (0..8).map(|player| player.to_string()).collect();
+        assert!(players.iter().any(|player| room.answer_order(player, 0, 6) != room.answer_order("ann", 0, 6)));
+    }
+
+    #[test]
+    fn room_keys_share_a_cluster_slot() {
+        let hash_tag = |key: &str| key[key.find('{').unwrap()..=key.find('}').unwrap()].to_string();
+
+        assert_eq!(Room::key("123456"), "quiz_room:{123456}");
+        assert_eq!(hash_tag(&Room::key("123456")), hash_tag(&QuizSnapshot::key("123456")));
+    }
+}
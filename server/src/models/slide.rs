@@ -0,0 +1,516 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::libraries::media::RevealMode;
+use crate::libraries::rich_text;
+
+/// Points a correct answer is worth on slides that don't set their own.
+pub const DEFAULT_POINTS: u32 = 1000;
+
+/// An audio or video clip the host's screen plays before answering opens.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideClip {
+    /// `/api/media/{key}` for an uploaded clip, or `local:{name}` for a file the host plays from
+    /// their own device.
+    pub path: String,
+    /// Where playback starts, in seconds into the clip.
+    #[serde(default)]
+    pub start: f64,
+    /// Where playback stops; the end of the clip when absent.
+    pub end: Option<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideQuizQuestion {
+    pub theme: String,
+    pub time_limit: Option<u32>,
+    pub points: Option<u32>,
+    pub answer_options: String,
+    pub image_reveal: String,
+    pub image_path: String,
+    #[serde(default)]
+    pub clip: Option<SlideClip>,
+    pub question: String,
+    pub answers: Option<Vec<String>>,
+    pub correct_answers: Option<Vec<bool>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideQuizTrueOrFalse {
+    pub theme: String,
+    pub time_limit: Option<u32>,
+    pub points: Option<u32>,
+    pub image_reveal: String,
+    pub image_path: String,
+    #[serde(default)]
+    pub clip: Option<SlideClip>,
+    pub question: String,
+    pub answers: Option<Vec<String>>,
+    pub correct_answers: Option<Vec<bool>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideOpenEnded {
+    pub theme: String,
+    pub time_limit: Option<u32>,
+    pub image_reveal: String,
+    pub image_path: String,
+    #[serde(default)]
+    pub clip: Option<SlideClip>,
+    pub question: String,
+    pub max_length: Option<u32>,
+}
+
+/// A question players answer by typing a word or phrase, right if it matches any accepted answer.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideShortAnswer {
+    pub theme: String,
+    pub time_limit: Option<u32>,
+    pub points: Option<u32>,
+    pub image_reveal: String,
+    pub image_path: String,
+    #[serde(default)]
+    pub clip: Option<SlideClip>,
+    pub question: String,
+    pub accepted_answers: Vec<String>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+/// A question players answer with a number, right if it is within `tolerance` of `correct_value`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideNumerical {
+    pub theme: String,
+    pub time_limit: Option<u32>,
+    pub points: Option<u32>,
+    pub image_reveal: String,
+    pub image_path: String,
+    #[serde(default)]
+    pub clip: Option<SlideClip>,
+    pub question: String,
+    pub correct_value: f64,
+    #[serde(default)]
+    pub tolerance: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideContent {
+    pub theme: String,
+    pub title: String,
+    pub body: String,
+    pub image_path: Option<String>,
+}
+
+/// Stands in for a slide from the quiz owner's question bank until a room is created.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideBankItem {
+    pub item_id: String,
+}
+
+/// Stands in for `count` questions drawn at random from the quiz owner's question bank, limited
+/// to items carrying every tag in `tags`. Each room gets its own draw.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlideRandomDraw {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub count: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "question_type")]
+pub enum Slide {
+    #[serde(rename = "question")]
+    Question(SlideQuizQuestion),
+    #[serde(rename = "true_or_false")]
+    TrueOrFalse(SlideQuizTrueOrFalse),
+    #[serde(rename = "open_ended")]
+    OpenEnded(SlideOpenEnded),
+    #[serde(rename = "short_answer")]
+    ShortAnswer(SlideShortAnswer),
+    #[serde(rename = "numerical")]
+    Numerical(SlideNumerical),
+    #[serde(rename = "content")]
+    Content(SlideContent),
+    #[serde(rename = "bank_item")]
+    BankItem(SlideBankItem),
+    #[serde(rename = "random_draw")]
+    RandomDraw(SlideRandomDraw),
+}
+
+impl Slide {
+    pub fn time_limit(&self) -> Option<u32> {
+        match self {
+            Slide::Question(slide) => slide.time_limit,
+            Slide::TrueOrFalse(slide) => slide.time_limit,
+            Slide::OpenEnded(slide) => slide.time_limit,
+            Slide::ShortAnswer(slide) => slide.time_limit,
+            Slide::Numerical(slide) => slide.time_limit,
+            Slide::Content(_) | Slide::BankItem(_) | Slide::RandomDraw(_) => None,
+        }
+    }
+
+    pub fn is_answerable(&self) -> bool {
+        matches!(self, Slide::Question(_) | Slide::TrueOrFalse(_) | Slide::OpenEnded(_) | Slide::ShortAnswer(_) | Slide::Numerical(_))
+    }
+
+    /// The slide's image, if it has one.
+    pub fn image_path(&self) -> Option<&str> {
+        let path = match self {
+            Slide::Question(slide) => slide.image_path.as_str(),
+            Slide::TrueOrFalse(slide) => slide.image_path.as_str(),
+            Slide::OpenEnded(slide) => slide.image_path.as_str(),
+            Slide::ShortAnswer(slide) => slide.image_path.as_str(),
+            Slide::Numerical(slide) => slide.image_path.as_str(),
+            Slide::Content(slide) => slide.image_path.as_deref()?,
+            Slide::BankItem(_) | Slide::RandomDraw(_) => return None,
+        };
+
+        Some(path).filter(|path| !path.is_empty())
+    }
+
+    pub fn set_image_path(&mut self, path: String) {
+        match self {
+            Slide::Question(slide) => slide.image_path = path,
+            Slide::TrueOrFalse(slide) => slide.image_path = path,
+            Slide::OpenEnded(slide) => slide.image_path = path,
+            Slide::ShortAnswer(slide) => slide.image_path = path,
+            Slide::Numerical(slide) => slide.image_path = path,
+            Slide::Content(slide) => slide.image_path = Some(path),
+            Slide::BankItem(_) | Slide::RandomDraw(_) => {}
+        }
+    }
+
+    pub fn clip(&self) -> Option<&SlideClip> {
+        match self {
+            Slide::Question(slide) => slide.clip.as_ref(),
+            Slide::TrueOrFalse(slide) => slide.clip.as_ref(),
+            Slide::OpenEnded(slide) => slide.clip.as_ref(),
+            Slide::ShortAnswer(slide) => slide.clip.as_ref(),
+            Slide::Numerical(slide) => slide.clip.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Every media path the slide refers to: its image and its clip.
+    pub fn media_paths(&self) -> Vec<&str> {
+        self.image_path().into_iter().chain(self.clip().map(|clip| clip.path.as_str())).collect()
+    }
+
+    /// How the image is uncovered during the game, if it is revealed progressively at all.
+    pub fn reveal_mode(&self) -> Option<RevealMode> {
+        let mode = match self {
+            Slide::Question(slide) => &slide.image_reveal,
+            Slide::TrueOrFalse(slide) => &slide.image_reveal,
+            Slide::OpenEnded(slide) => &slide.image_reveal,
+            Slide::ShortAnswer(slide) => &slide.image_reveal,
+            Slide::Numerical(slide) => &slide.image_reveal,
+            _ => return None,
+        };
+
+        RevealMode::parse(mode)
+    }
+
+    /// Bank references and random draws only live in the quiz; rooms get real slides in their place.
+    pub fn is_placeholder(&self) -> bool {
+        matches!(self, Slide::BankItem(_) | Slide::RandomDraw(_))
+    }
+
+    /// The slide with its question and answers, or a content slide's title and body, also
+    /// rendered to sanitized HTML, next to the Markdown source the editor works on.
+    pub fn to_json(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+
+        let (question, answers) = match self {
+            Slide::Content(slide) => {
+                value["title_html"] = json!(rich_text::render(&slide.title));
+                value["body_html"] = json!(rich_text::render(&slide.body));
+
+                return value;
+            }
+            Slide::Question(slide) => (&slide.question, slide.answers.as_ref()),
+            Slide::TrueOrFalse(slide) => (&slide.question, slide.answers.as_ref()),
+            Slide::OpenEnded(slide) => (&slide.question, None),
+            Slide::ShortAnswer(slide) => (&slide.question, None),
+            Slide::Numerical(slide) => (&slide.question, None),
+            _ => return value,
+        };
+
+        value["question_html"] = json!(rich_text::render(question));
+
+        if let Some(answers) = answers {
+            value["answers_html"] = json!(answers.iter().map(|answer| rich_text::render(answer)).collect::<Vec<_>>());
+        }
+
+        value
+    }
+
+    /// The slide as players see it, without the answer key.
+    pub fn to_public_json(&self) -> Value {
+        let mut value = self.to_json();
+
+        if let Some(object) = value.as_object_mut() {
+            for field in ["correct_answers", "accepted_answers", "correct_value", "tolerance"] {
+                object.remove(field);
+            }
+        }
+
+        value
+    }
+
+    /// Number of choices on a question the player picks answers from, or 0 for any other slide.
+    pub fn answer_count(&self) -> usize {
+        let answers = match self {
+            Slide::Question(slide) => &slide.answers,
+            Slide::TrueOrFalse(slide) => &slide.answers,
+            _ => return 0,
+        };
+
+        answers.as_ref().map_or(0, Vec::len)
+    }
+
+    /// The public slide with its answers in `order`, where `order[shown]` is the canonical index
+    /// of the answer shown at position `shown`.
+    pub fn to_shuffled_json(&self, order: &[usize]) -> Value {
+        let mut value = self.to_public_json();
+
+        for field in ["answers", "answers_html"] {
+            if let Some(answers) = value.get(field).and_then(Value::as_array) {
+                let shuffled: Vec<Value> = order.iter().filter_map(|index| answers.get(*index).cloned()).collect();
+
+                value[field] = Value::Array(shuffled);
+            }
+        }
+
+        value
+    }
+}
+
+/// One slide-level change to a quiz. The REST slide endpoints and the collaborative editor both
+/// speak this, and the editor rebases concurrent edits with `transform`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SlideEdit {
+    Insert { position: Option<usize>, slide: Slide },
+    Update { index: usize, slide: Slide },
+    Delete { index: usize },
+    Move { from: usize, to: usize },
+    Duplicate { index: usize },
+}
+
+impl SlideEdit {
+    /// Pins down positions that depend on the slide count: inserts default to the end and moves
+    /// past the end land on the last slide.
+    pub fn resolve(self, len: usize) -> Self {
+        match self {
+            SlideEdit::Insert { position, slide } => SlideEdit::Insert {
+                position: Some(position.unwrap_or(len)),
+                slide,
+            },
+            SlideEdit::Move { from, to } => SlideEdit::Move {
+                from,
+                to: to.min(len.saturating_sub(1)),
+            },
+            edit => edit,
+        }
+    }
+
+    /// Applies the edit and returns the index of the slide it touched afterwards, or `None` if it
+    /// refers to a slide that doesn't exist.
+    pub fn apply(self, slides: &mut Vec<Slide>) -> Option<usize> {
+        match self {
+            SlideEdit::Insert { position, slide } => {
+                let position = position.unwrap_or(slides.len());
+
+                if position > slides.len() {
+                    return None;
+                }
+
+                slides.insert(position, slide);
+                Some(position)
+            }
+            SlideEdit::Update { index, slide } => {
+                *slides.get_mut(index)? = slide;
+                Some(index)
+            }
+            SlideEdit::Delete { index } => {
+                if index >= slides.len() {
+                    return None;
+                }
+
+                slides.remove(index);
+                Some(index)
+            }
+            SlideEdit::Move { from, to } => {
+                if from >= slides.len() || to >= slides.len() {
+                    return None;
+                }
+
+                let slide = slides.remove(from);
+                slides.insert(to, slide);
+                Some(to)
+            }
+            SlideEdit::Duplicate { index } => {
+                let slide = slides.get(index)?.clone();
+
+                slides.insert(index + 1, slide);
+                Some(index + 1)
+            }
+        }
+    }
+
+    /// Where an existing slide at `index` ends up once `self` has been applied; `None` if `self`
+    /// deleted it.
+    fn map_slide(&self, index: usize) -> Option<usize> {
+        match *self {
+            SlideEdit::Insert { position, .. } => {
+                Some(if position.is_some_and(|position| index >= position) { index + 1 } else { index })
+            }
+            SlideEdit::Duplicate { index: source } => {
+                Some(if index > source { index + 1 } else { index })
+            }
+            SlideEdit::Delete { index: deleted } => match index.cmp(&deleted) {
+                std::cmp::Ordering::Less => Some(index),
+                std::cmp::Ordering::Equal => None,
+                std::cmp::Ordering::Greater => Some(index - 1),
+            },
+            SlideEdit::Move { from, to } => {
+                if index == from {
+                    return Some(to);
+                }
+
+                let index = if index > from { index - 1 } else { index };
+
+                Some(if index >= to { index + 1 } else { index })
+            }
+            SlideEdit::Update { .. } => Some(index),
+        }
+    }
+
+    /// Where an insertion point between slides ends up once `self` has been applied. When two
+    /// inserts target the same gap, the one applied first stays first.
+    fn map_gap(&self, position: usize) -> usize {
+        match *self {
+            SlideEdit::Insert { position: inserted, .. } => {
+                if inserted.is_some_and(|inserted| position >= inserted) { position + 1 } else { position }
+            }
+            SlideEdit::Duplicate { index } => {
+                if position > index { position + 1 } else { position }
+            }
+            SlideEdit::Delete { index } => {
+                if position > index { position - 1 } else { position }
+            }
+            SlideEdit::Move { from, to } => {
+                let position = if position > from { position - 1 } else { position };
+
+                if position > to { position + 1 } else { position }
+            }
+            SlideEdit::Update { .. } => position,
+        }
+    }
+
+    /// Rebases `self`, written against the quiz before `applied`, onto the quiz after it. Returns
+    /// `None` when the edit no longer makes sense because `applied` deleted its slide.
+    pub fn transform(self, applied: &SlideEdit) -> Option<SlideEdit> {
+        match self {
+            SlideEdit::Insert { position, slide } => Some(SlideEdit::Insert {
+                position: position.map(|position| applied.map_gap(position)),
+                slide,
+            }),
+            SlideEdit::Update { index, slide } => Some(SlideEdit::Update {
+                index: applied.map_slide(index)?,
+                slide,
+            }),
+            SlideEdit::Delete { index } => Some(SlideEdit::Delete {
+                index: applied.map_slide(index)?,
+            }),
+            SlideEdit::Move { from, to } => Some(SlideEdit::Move {
+                from: applied.map_slide(from)?,
+                to: applied.map_gap(to),
+            }),
+            SlideEdit::Duplicate { index } => Some(SlideEdit::Duplicate {
+                index: applied.map_slide(index)?,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slide(title: &str) -> Slide {
+        Slide::Content(SlideContent {
+            theme: String::new(),
+            title: title.to_string(),
+            body: String::new(),
+            image_path: None,
+        })
+    }
+
+    fn deck(titles: &str) -> Vec<Slide> {
+        titles.chars().map(|title| slide(&title.to_string())).collect()
+    }
+
+    fn titles(slides: &[Slide]) -> String {
+        slides
+            .iter()
+            .map(|slide| match slide {
+                Slide::Content(content) => content.title.clone(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    /// Applies `applied`, then `edit` rebased over it, to the deck `base`.
+    fn rebase(base: &str, applied: SlideEdit, edit: SlideEdit) -> Option<String> {
+        let mut slides = deck(base);
+
+        applied.clone().apply(&mut slides)?;
+        edit.transform(&applied)?.apply(&mut slides)?;
+
+        Some(titles(&slides))
+    }
+
+    #[test]
+    fn inserts_into_the_same_gap_keep_the_first_one_first() {
+        let first = SlideEdit::Insert { position: Some(1), slide: slide("x") };
+        let second = SlideEdit::Insert { position: Some(1), slide: slide("y") };
+
+        assert_eq!(rebase("abc", first, second).as_deref(), Some("axybc"));
+    }
+
+    #[test]
+    fn edits_follow_their_slide() {
+        let deleted = SlideEdit::Delete { index: 0 };
+        let update = SlideEdit::Update { index: 2, slide: slide("z") };
+
+        assert_eq!(rebase("abcd", deleted, update).as_deref(), Some("bzd"));
+
+        let duplicated = SlideEdit::Duplicate { index: 0 };
+        let delete = SlideEdit::Delete { index: 1 };
+
+        assert_eq!(rebase("abc", duplicated, delete).as_deref(), Some("aac"));
+    }
+
+    #[test]
+    fn edits_to_a_deleted_slide_conflict() {
+        let deleted = SlideEdit::Delete { index: 1 };
+
+        assert!(SlideEdit::Update { index: 1, slide: slide("z") }.transform(&deleted).is_none());
+        assert!(SlideEdit::Move { from: 1, to: 0 }.transform(&deleted).is_none());
+        assert!(SlideEdit::Duplicate { index: 1 }.transform(&deleted).is_none());
+    }
+
+    #[test]
+    fn moves_land_next_to_the_same_neighbours() {
+        let to_after_c = SlideEdit::Move { from: 0, to: 2 };
+
+        assert_eq!(rebase("abcd", SlideEdit::Insert { position: Some(0), slide: slide("x") }, to_after_c.clone()).as_deref(), Some("xbcad"));
+        assert_eq!(rebase("abcd", SlideEdit::Delete { index: 1 }, to_after_c.clone()).as_deref(), Some("cad"));
+        assert_eq!(rebase("abcd", SlideEdit::Move { from: 3, to: 0 }, to_after_c).as_deref(), Some("dbca"));
+
+        let to_front = SlideEdit::Move { from: 3, to: 0 };
+
+        assert_eq!(rebase("abcd", SlideEdit::Move { from: 0, to: 3 }, to_front).as_deref(), Some("dbca"));
+    }
+}
@@ -0,0 +1,27 @@
+use mongodb::bson::DateTime;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+
+use crate::models::id::UserId;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct User {
+    #[serde(rename = "_id")]
+    pub id: UserId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub google_id: Option<String>,
+    pub email: String,
+    pub name: String,
+    pub method: String,
+    pub created_at: DateTime,
+    pub last_active: DateTime,
+    pub last_auth: DateTime,
+}
+
+impl User {
+    pub const COLLECTION: &'static str = "users";
+
+    pub fn collection(db: &Database) -> Collection<User> {
+        db.collection(Self::COLLECTION)
+    }
+}
@@ -1,7 +1,11 @@
 use actix_web::web;
 
 mod auth;
+mod bank;
+mod folder;
 mod index;
+mod library;
+mod media;
 mod quiz;
 mod play;
 
@@ -9,7 +13,11 @@ mod user;
 
 pub fn register_routes(cfg: &mut web::ServiceConfig) {
     cfg.configure(auth::configure);
+    cfg.configure(bank::configure);
+    cfg.configure(folder::configure);
     cfg.configure(index::configure);
+    cfg.configure(library::configure);
+    cfg.configure(media::configure);
     cfg.configure(quiz::configure);
     cfg.configure(play::configure);
     cfg.configure(user::configure);
@@ -3,13 +3,15 @@ use actix_web::cookie::Cookie;
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl, AuthorizationCode, CsrfToken, TokenResponse, Client, StandardRevocableToken, EndpointSet, EndpointNotSet, Scope};
 use reqwest::Client as ReqwestClient;
 use serde::{Deserialize, Serialize};
-use mongodb::bson::{doc, DateTime, Document};
+use mongodb::bson::{doc, DateTime};
 use jsonwebtoken::{encode, EncodingKey, Header};
 use chrono::Utc;
 use mongodb::Database;
 use oauth2::basic::{BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse, BasicTokenResponse};
 use crate::env::{APP_URL, GOOGLE_CLIENT_ID, GOOGLE_CLIENT_SECRET, JWT_SECRET};
 use crate::libraries::{response_bad_request, response_internal_server_error, response_ok_builder};
+use crate::models::id::UserId;
+use crate::models::user::User;
 
 #[derive(Deserialize, Serialize)]
 struct GoogleUser {
@@ -107,7 +109,7 @@ async fn google_auth_callback(
                 Err(_) => return response_bad_request(),
             };
 
-            let existing_user = match db.collection::<Document>("users").find_one(
+            let existing_user = match User::collection(&db).find_one(
                 doc! { "google_id": user_info.id.clone() },
             ).await {
                 Ok(user) => user,
@@ -115,8 +117,8 @@ async fn google_auth_callback(
             };
 
             let user_id = if let Some(user) = existing_user {
-                match db.collection::<Document>("users").update_one(
-                    doc! { "google_id": user_info.id.clone() },
+                match User::collection(&db).update_one(
+                    doc! { "_id": user.id },
                     doc! {
                         "$set": {
                             "last_active": DateTime::now(),
@@ -124,31 +126,31 @@ async fn google_auth_callback(
                         }
                     }
                 ).await {
-                    Ok(_) => {
-                        user.get("_id").unwrap().as_object_id().unwrap().to_string()
-                    },
+                    Ok(_) => user.id,
                     Err(_) => return response_internal_server_error(),
                 }
             } else {
-                let new_user = doc! {
-                    "google_id": user_info.id.clone(),
-                    "email": user_info.email.clone(),
-                    "name": user_info.name.clone().unwrap_or_default(),
-                    "method": "google",
-                    "created_at": DateTime::now(),
-                    "last_active": DateTime::now(),
-                    "last_auth": DateTime::now()
-                };
-                let insert_result = match db.collection::<Document>("users").insert_one(new_user).await {
-                    Ok(result) => result,
-                    Err(_) => return response_internal_server_error(),
+                let now = DateTime::now();
+                let new_user = User {
+                    id: UserId::new(),
+                    google_id: Some(user_info.id.clone()),
+                    email: user_info.email.clone(),
+                    name: user_info.name.clone().unwrap_or_default(),
+                    method: "google".to_string(),
+                    created_at: now,
+                    last_active: now,
+                    last_auth: now,
                 };
 
-                insert_result.inserted_id.as_object_id().unwrap().to_string()
+                if User::collection(&db).insert_one(&new_user).await.is_err() {
+                    return response_internal_server_error();
+                }
+
+                new_user.id
             };
 
             let claims = Claims {
-                sub: format!("0x{}", user_id),
+                sub: user_id.to_string(),
                 method: "google".to_string(),
                 exp: (Utc::now() + chrono::Duration::hours(1)).timestamp() as usize,
             };
@@ -176,7 +178,7 @@ async fn google_auth_callback(
                         window.opener.postMessage({{ status: 'success' }}, '{}');
                         window.close();
                     </script>"#,
-                    APP_URL.clone().to_string()
+                    APP_URL.clone()
                 ))
         }
         Err(_) => response_bad_request(),
@@ -0,0 +1,117 @@
+mod item_id;
+
+use actix_web::{http::Method, web, HttpRequest, Responder};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use mongodb::bson::{doc, DateTime};
+use mongodb::Database;
+
+use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_ok_builder, response_unprocessable_entity};
+use crate::models::bank::BankItem;
+use crate::models::id::BankItemId;
+use crate::models::slide::Slide;
+use crate::routes::quiz::normalize_tags;
+use crate::routes::quiz::validation::{validate_bank_item, validate_media};
+
+pub const PATH: &str = "/api/bank";
+
+#[derive(Deserialize)]
+struct BankQuery {
+    /// Comma separated; an item must carry every tag listed.
+    tags: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BankItemCreation {
+    slide: Slide,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+async fn handler(
+    req: HttpRequest,
+    query: web::Query<BankQuery>,
+    body: Option<web::Json<BankItemCreation>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        match *req.method() {
+            Method::GET => {
+                let mut filter = doc! { "owner_id": user_id };
+
+                if let Some(tags) = &query.tags {
+                    let tags: Vec<String> = normalize_tags(&tags.split(',').map(str::to_string).collect::<Vec<_>>());
+
+                    if !tags.is_empty() {
+                        filter.insert("tags", doc! { "$all": tags });
+                    }
+                }
+
+                let cursor = match BankItem::collection(&db)
+                    .find(filter)
+                    .sort(doc! { "updated_at": -1 })
+                    .await
+                {
+                    Ok(cursor) => cursor,
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                match cursor.try_collect::<Vec<BankItem>>().await {
+                    Ok(items) => {
+                        let items: Vec<Value> = items.iter().map(BankItem::to_json).collect();
+
+                        response_ok_builder().json(json!({ "items": items }))
+                    }
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            Method::POST => {
+                let body = match body.ok_or_else(response_bad_request) {
+                    Ok(body) => body.into_inner(),
+                    Err(response) => return response,
+                };
+
+                if let Err(errors) = validate_bank_item(&body.slide, &body.tags) {
+                    return response_unprocessable_entity(errors);
+                }
+
+                match validate_media(&db, std::slice::from_ref(&body.slide), &[user_id]).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(errors)) => return response_unprocessable_entity(errors),
+                    Err(_) => return response_internal_server_error(),
+                }
+
+                let created_at = DateTime::now();
+
+                let item = BankItem {
+                    id: BankItemId::new(),
+                    owner_id: user_id,
+                    slide: body.slide,
+                    tags: normalize_tags(&body.tags),
+                    updated_at: created_at,
+                    created_at,
+                };
+
+                match BankItem::collection(&db).insert_one(&item).await {
+                    Ok(_) => response_ok_builder().json(item.to_json()),
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH)
+        .route(web::get().to(handler))
+        .route(web::post().to(handler)));
+    cfg.configure(item_id::configure);
+}
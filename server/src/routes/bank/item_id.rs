@@ -0,0 +1,105 @@
+use actix_web::{http::Method, web, HttpRequest, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use mongodb::bson::{doc, to_bson, DateTime};
+use mongodb::options::ReturnDocument;
+use mongodb::Database;
+
+use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_not_found, response_ok_builder, response_unprocessable_entity};
+use crate::models::bank::BankItem;
+use crate::models::id::BankItemId;
+use crate::routes::quiz::normalize_tags;
+use crate::routes::quiz::validation::{validate_bank_item, validate_media};
+use super::BankItemCreation;
+
+pub const PATH: &str = "/api/bank/{item_id}";
+
+#[derive(Deserialize)]
+struct Request {
+    item_id: String,
+}
+
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    body: Option<web::Json<BankItemCreation>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let item_id = match BankItemId::parse(&path.item_id) {
+            Some(item_id) => item_id,
+            None => return response_not_found(),
+        };
+
+        let filter = doc! { "_id": item_id, "owner_id": user_id };
+
+        match *req.method() {
+            Method::GET => match BankItem::collection(&db).find_one(filter).await {
+                Ok(Some(item)) => response_ok_builder().json(item.to_json()),
+                Ok(None) => response_not_found(),
+                Err(_) => response_internal_server_error(),
+            },
+            Method::PUT => {
+                let body = match body.ok_or_else(response_bad_request) {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+
+                if let Err(errors) = validate_bank_item(&body.slide, &body.tags) {
+                    return response_unprocessable_entity(errors);
+                }
+
+                match validate_media(&db, std::slice::from_ref(&body.slide), &[user_id]).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(errors)) => return response_unprocessable_entity(errors),
+                    Err(_) => return response_internal_server_error(),
+                }
+
+                let slide = match to_bson(&body.slide) {
+                    Ok(slide) => slide,
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                // Quizzes referencing the item pick up the change the next time they are hosted.
+                match BankItem::collection(&db).find_one_and_update(
+                        filter,
+                        doc! {
+                            "$set": {
+                                "slide": slide,
+                                "tags": normalize_tags(&body.tags),
+                                "updated_at": DateTime::now(),
+                            }
+                        },
+                    )
+                    .return_document(ReturnDocument::After)
+                    .await
+                {
+                    Ok(Some(item)) => response_ok_builder().json(item.to_json()),
+                    Ok(None) => response_not_found(),
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            Method::DELETE => match BankItem::collection(&db).delete_one(filter).await {
+                Ok(result) if result.deleted_count == 0 => response_not_found(),
+                Ok(_) => response_ok_builder().json(json!({ "item_id": item_id.to_string() })),
+                Err(_) => response_internal_server_error(),
+            },
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource(PATH)
+            .route(web::get().to(handler))
+            .route(web::put().to(handler))
+            .route(web::delete().to(handler))
+    );
+}
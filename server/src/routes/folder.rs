@@ -0,0 +1,135 @@
+mod folder_id;
+
+use actix_web::{http::Method, web, HttpRequest, HttpResponse, Responder};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use mongodb::bson::{doc, DateTime};
+use mongodb::Database;
+
+use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_not_found, response_ok_builder, response_unprocessable_entity};
+use crate::models::folder::Folder;
+use crate::models::id::{FolderId, UserId};
+
+pub const PATH: &str = "/api/folder";
+
+pub const NAME_MAX_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+struct FolderCreation {
+    name: String,
+    parent_id: Option<String>,
+}
+
+/// Trims the name, or returns the field errors in the same shape quiz validation uses.
+fn validate_name(name: &str) -> Result<String, Value> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        Err(json!([{ "field": "name", "reason": "must not be empty" }]))
+    } else if name.chars().count() > NAME_MAX_LENGTH {
+        Err(json!([{
+            "field": "name",
+            "reason": format!("must be at most {} characters", NAME_MAX_LENGTH),
+        }]))
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+/// Resolves an optional `parent_id` from a request body to one of the user's folders.
+async fn find_parent(db: &Database, parent_id: Option<&str>, user_id: UserId) -> Result<Option<FolderId>, HttpResponse> {
+    let parent_id = match parent_id {
+        Some(parent_id) => match FolderId::parse(parent_id) {
+            Some(parent_id) => parent_id,
+            None => return Err(response_bad_request()),
+        },
+        None => return Ok(None),
+    };
+
+    match Folder::find_owned(db, parent_id, user_id).await {
+        Ok(Some(_)) => Ok(Some(parent_id)),
+        Ok(None) => Err(response_not_found()),
+        Err(_) => Err(response_internal_server_error()),
+    }
+}
+
+async fn find_all(db: &Database, user_id: UserId) -> Result<Vec<Folder>, HttpResponse> {
+    let cursor = match Folder::collection(db)
+        .find(doc! { "owner_id": user_id })
+        .sort(doc! { "name": 1 })
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(response_internal_server_error()),
+    };
+
+    cursor.try_collect().await.map_err(|_| response_internal_server_error())
+}
+
+async fn handler(
+    req: HttpRequest,
+    body: Option<web::Json<FolderCreation>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        match *req.method() {
+            Method::GET => {
+                let folders = match find_all(&db, user_id).await {
+                    Ok(folders) => folders,
+                    Err(response) => return response,
+                };
+
+                let folders: Vec<Value> = folders.iter().map(Folder::to_json).collect();
+
+                response_ok_builder().json(json!({ "folders": folders }))
+            }
+            Method::POST => {
+                let body = match body.ok_or_else(response_bad_request) {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+
+                let name = match validate_name(&body.name) {
+                    Ok(name) => name,
+                    Err(errors) => return response_unprocessable_entity(errors),
+                };
+
+                let parent_id = match find_parent(&db, body.parent_id.as_deref(), user_id).await {
+                    Ok(parent_id) => parent_id,
+                    Err(response) => return response,
+                };
+
+                let created_at = DateTime::now();
+
+                let folder = Folder {
+                    id: FolderId::new(),
+                    owner_id: user_id,
+                    name,
+                    parent_id,
+                    updated_at: created_at,
+                    created_at,
+                };
+
+                match Folder::collection(&db).insert_one(&folder).await {
+                    Ok(_) => response_ok_builder().json(folder.to_json()),
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH)
+        .route(web::get().to(handler))
+        .route(web::post().to(handler)));
+    cfg.configure(folder_id::configure);
+}
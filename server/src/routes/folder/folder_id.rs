@@ -0,0 +1,232 @@
+use actix_web::{http::Method, web, HttpRequest, Responder};
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use mongodb::bson::{doc, DateTime};
+use mongodb::Database;
+
+use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_not_found, response_ok_builder, response_unprocessable_entity};
+use crate::models::folder::Folder;
+use crate::models::id::FolderId;
+use crate::models::quiz::Quiz;
+use super::{find_all, find_parent, validate_name};
+
+pub const PATH: &str = "/api/folder/{folder_id}";
+
+#[derive(Deserialize)]
+struct Request {
+    folder_id: String,
+}
+
+/// Tells an absent `parent_id` (leave it alone) apart from `"parent_id": null` (move to the root).
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+struct FolderUpdate {
+    name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    parent_id: Option<Option<String>>,
+}
+
+/// The folder itself followed by every folder nested below it.
+fn subtree(folders: &[Folder], root: FolderId) -> Vec<FolderId> {
+    let mut ids = vec![root];
+    let mut index = 0;
+
+    while index < ids.len() {
+        let parent = ids[index];
+        ids.extend(folders.iter().filter(|folder| folder.parent_id == Some(parent)).map(|folder| folder.id));
+        index += 1;
+    }
+
+    ids
+}
+
+/// Folders from the root down to `folder_id`, for breadcrumbs.
+fn ancestry(folders: &[Folder], folder_id: FolderId) -> Vec<&Folder> {
+    let mut path = Vec::new();
+    let mut current = Some(folder_id);
+
+    while let Some(id) = current {
+        match folders.iter().find(|folder| folder.id == id) {
+            Some(folder) if path.len() < folders.len() => {
+                path.push(folder);
+                current = folder.parent_id;
+            }
+            _ => break,
+        }
+    }
+
+    path.reverse();
+    path
+}
+
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    body: Option<web::Json<FolderUpdate>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let folder_id = match FolderId::parse(&path.folder_id) {
+            Some(folder_id) => folder_id,
+            None => return response_not_found(),
+        };
+
+        let folders = match find_all(&db, user_id).await {
+            Ok(folders) => folders,
+            Err(response) => return response,
+        };
+
+        let folder = match folders.iter().find(|folder| folder.id == folder_id) {
+            Some(folder) => folder.clone(),
+            None => return response_not_found(),
+        };
+
+        match *req.method() {
+            Method::GET => {
+                let children: Vec<Value> = folders
+                    .iter()
+                    .filter(|child| child.parent_id == Some(folder_id))
+                    .map(Folder::to_json)
+                    .collect();
+
+                let path: Vec<Value> = ancestry(&folders, folder_id).into_iter().map(Folder::to_json).collect();
+
+                let mut value = folder.to_json();
+                value["children"] = json!(children);
+                value["path"] = json!(path);
+
+                response_ok_builder().json(value)
+            }
+            Method::PUT => {
+                let body = match body.ok_or_else(response_bad_request) {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+
+                let updated_at = DateTime::now();
+                let mut update = doc! { "updated_at": updated_at };
+
+                if let Some(name) = &body.name {
+                    match validate_name(name) {
+                        Ok(name) => update.insert("name", name),
+                        Err(errors) => return response_unprocessable_entity(errors),
+                    };
+                }
+
+                if let Some(parent_id) = &body.parent_id {
+                    let parent_id = match find_parent(&db, parent_id.as_deref(), user_id).await {
+                        Ok(parent_id) => parent_id,
+                        Err(response) => return response,
+                    };
+
+                    // A folder can't be moved into itself or anything nested below it.
+                    if parent_id.is_some_and(|parent_id| subtree(&folders, folder_id).contains(&parent_id)) {
+                        return response_bad_request();
+                    }
+
+                    update.insert("parent_id", parent_id);
+                }
+
+                match Folder::collection(&db).find_one_and_update(
+                    doc! { "_id": folder_id, "owner_id": user_id },
+                    doc! { "$set": update },
+                )
+                .return_document(mongodb::options::ReturnDocument::After)
+                .await
+                {
+                    Ok(Some(folder)) => response_ok_builder().json(folder.to_json()),
+                    Ok(None) => response_not_found(),
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            Method::DELETE => {
+                let folder_ids = subtree(&folders, folder_id);
+
+                let mut session = match db.client().start_session().await {
+                    Ok(session) => session,
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                let deleted_at = DateTime::now();
+
+                // Quizzes inside go to the trash and lose their folder, so restoring one puts it
+                // back at the root instead of pointing at a folder that no longer exists.
+                let result: mongodb::error::Result<u64> = async {
+                    session.start_transaction().await?;
+
+                    let trashed = Quiz::collection(&db).update_many(
+                            doc! {
+                                "owner_id": user_id,
+                                "folder_id": { "$in": folder_ids.clone() },
+                                "is_deleted": { "$ne": true },
+                            },
+                            doc! {
+                                "$set": {
+                                    "is_deleted": true,
+                                    "deleted_at": deleted_at,
+                                }
+                            },
+                        )
+                        .session(&mut session)
+                        .await?;
+
+                    Quiz::collection(&db).update_many(
+                            doc! {
+                                "owner_id": user_id,
+                                "folder_id": { "$in": folder_ids.clone() },
+                            },
+                            doc! { "$unset": { "folder_id": "" } },
+                        )
+                        .session(&mut session)
+                        .await?;
+
+                    Folder::collection(&db).delete_many(doc! {
+                            "_id": { "$in": folder_ids.clone() },
+                            "owner_id": user_id,
+                        })
+                        .session(&mut session)
+                        .await?;
+
+                    session.commit_transaction().await?;
+
+                    Ok(trashed.modified_count)
+                }.await;
+
+                match result {
+                    Ok(trashed) => {
+                        response_ok_builder().json(json!({
+                            "folder_id": folder_id.to_string(),
+                            "deleted_folders": folder_ids.len(),
+                            "trashed_quizzes": trashed,
+                            "deleted_at": deleted_at,
+                        }))
+                    }
+                    Err(_) => {
+                        let _ = session.abort_transaction().await;
+
+                        response_internal_server_error()
+                    }
+                }
+            }
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource(PATH)
+            .route(web::get().to(handler))
+            .route(web::put().to(handler))
+            .route(web::delete().to(handler))
+    );
+}
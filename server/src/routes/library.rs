@@ -0,0 +1,141 @@
+use actix_web::{web, Responder};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use mongodb::bson::{doc, Document};
+use mongodb::Database;
+
+use crate::libraries::{response_bad_request, response_internal_server_error, response_ok_builder};
+use crate::models::quiz::Quiz;
+use crate::models::user::User;
+
+pub const PATH: &str = "/api/library";
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+
+const MAX_PAGE_SIZE: u64 = 50;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Sort {
+    Relevance,
+    Popular,
+    Recent,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: Option<String>,
+    /// Comma separated; a quiz must carry every tag listed.
+    tags: Option<String>,
+    subject: Option<String>,
+    grade_level: Option<String>,
+    language: Option<String>,
+    sort: Option<Sort>,
+    page: Option<u64>,
+    limit: Option<u64>,
+}
+
+fn search_filter(query: &SearchQuery) -> Document {
+    let mut filter = doc! {
+        "visibility": "public",
+        "is_deleted": { "$ne": true },
+    };
+
+    if let Some(text) = query.q.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+        filter.insert("$text", doc! { "$search": text });
+    }
+
+    if let Some(tags) = &query.tags {
+        let tags: Vec<String> = tags
+            .split(',')
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        if !tags.is_empty() {
+            filter.insert("tags", doc! { "$all": tags });
+        }
+    }
+
+    for (field, value) in [("subject", &query.subject), ("grade_level", &query.grade_level), ("language", &query.language)] {
+        if let Some(value) = value.as_deref().map(str::trim).filter(|value| !value.is_empty()) {
+            filter.insert(field, value);
+        }
+    }
+
+    filter
+}
+
+async fn handler(
+    query: web::Query<SearchQuery>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let filter = search_filter(&query);
+    let is_text_search = filter.contains_key("$text");
+
+    let sort = query.sort.unwrap_or(if is_text_search { Sort::Relevance } else { Sort::Recent });
+
+    if sort == Sort::Relevance && !is_text_search {
+        return response_bad_request();
+    }
+
+    let sort = match sort {
+        Sort::Relevance => doc! { "score": { "$meta": "textScore" }, "play_count": -1 },
+        Sort::Popular => doc! { "play_count": -1, "updated_at": -1 },
+        Sort::Recent => doc! { "updated_at": -1 },
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let total = match Quiz::collection(&db).count_documents(filter.clone()).await {
+        Ok(total) => total,
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let cursor = Quiz::collection(&db)
+        .find(filter)
+        .sort(sort)
+        .skip((page - 1) * limit)
+        .limit(limit as i64)
+        .await;
+
+    let quizzes: Vec<Quiz> = match cursor {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(quizzes) => quizzes,
+            Err(_) => return response_internal_server_error(),
+        },
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let owner_ids: Vec<_> = quizzes.iter().map(|quiz| quiz.owner_id).collect();
+
+    let owners: Vec<User> = match User::collection(&db).find(doc! { "_id": { "$in": owner_ids } }).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(owners) => owners,
+            Err(_) => return response_internal_server_error(),
+        },
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let quizzes: Vec<Value> = quizzes.iter().map(|quiz| {
+        let owner_name = owners
+            .iter()
+            .find(|owner| owner.id == quiz.owner_id)
+            .map(|owner| owner.name.as_str());
+
+        quiz.to_summary_json(owner_name)
+    }).collect();
+
+    response_ok_builder().json(json!({
+        "quizzes": quizzes,
+        "page": page,
+        "limit": limit,
+        "total": total,
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::get().to(handler)));
+}
@@ -0,0 +1,148 @@
+mod key;
+
+use actix_web::{http::Method, web, HttpRequest, HttpResponse, Responder};
+use actix_web::http::header::CONTENT_TYPE;
+use futures::TryStreamExt;
+use serde_json::{json, Value};
+use mongodb::bson::doc;
+use mongodb::Database;
+
+use crate::env::{MEDIA_CLIP_MAX_BYTES, MEDIA_MAX_BYTES};
+use crate::libraries::media::{is_image, probe_clip, process, ProcessedClip, ProcessedImage};
+use crate::libraries::{method_not_allowed, response_internal_server_error, response_ok_builder, response_unprocessable_entity};
+use crate::models::id::UserId;
+use crate::models::media::Media;
+
+pub const PATH: &str = "/api/media";
+
+pub const CLIPS_PATH: &str = "/api/media/clips";
+
+enum Upload {
+    Image(ProcessedImage),
+    Clip(ProcessedClip, web::Bytes),
+}
+
+/// Checks and stores one upload. Decoding and probing are CPU bound, so they stay off the async
+/// workers.
+async fn upload(db: &Database, user_id: UserId, body: web::Bytes, declared: Option<String>, clip: bool) -> HttpResponse {
+    let upload = web::block(move || {
+        if clip {
+            probe_clip(body.clone(), declared.as_deref()).map(|clip| Upload::Clip(clip, body))
+        } else {
+            process(&body).map(Upload::Image).ok_or("must be a JPEG, PNG, GIF or WebP image")
+        }
+    });
+
+    let upload = match upload.await {
+        Ok(Ok(upload)) => upload,
+        Ok(Err(reason)) => {
+            return response_unprocessable_entity(json!([{ "field": "file", "reason": reason }]));
+        }
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let stored = match &upload {
+        Upload::Image(image) => Media::store_image(db, user_id, image).await,
+        Upload::Clip(clip, bytes) => Media::store(db, user_id, &clip.key, bytes, doc! {
+            "kind": clip.kind.as_str(),
+            "content_type": clip.content_type,
+            "duration": clip.duration,
+            "codec": clip.codec,
+        }).await,
+    };
+
+    match stored {
+        Ok(Some(media)) => response_ok_builder().json(media.to_json()),
+        _ => response_internal_server_error(),
+    }
+}
+
+fn declared_type(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// `POST` takes the raw image as the request body; `GET` lists the user's uploads. Clips go to
+/// `CLIPS_PATH`, which allows larger bodies.
+async fn handler(
+    req: HttpRequest,
+    body: web::Bytes,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        match *req.method() {
+            Method::GET => {
+                let cursor = match Media::collection(&db)
+                    .find(doc! { "owner_id": user_id })
+                    .sort(doc! { "created_at": -1 })
+                    .await
+                {
+                    Ok(cursor) => cursor,
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                match cursor.try_collect::<Vec<Media>>().await {
+                    Ok(media) => {
+                        let media: Vec<Value> = media.iter().map(Media::to_json).collect();
+
+                        response_ok_builder().json(json!({ "media": media }))
+                    }
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            Method::POST => {
+                // Anything that isn't an image is taken for a clip sent to the wrong route.
+                if !is_image(&body) {
+                    return response_unprocessable_entity(json!([{
+                        "field": "file",
+                        "reason": format!("must be a JPEG, PNG, GIF or WebP image; upload clips to {}", CLIPS_PATH),
+                    }]));
+                }
+
+                upload(&db, user_id, body, declared_type(&req), false).await
+            }
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+/// `POST` takes a raw audio or video clip as the request body.
+async fn clip_handler(
+    req: HttpRequest,
+    body: web::Bytes,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        upload(&db, user_id, body, declared_type(&req), true).await
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    // Each route gets its own body limit, so the image route doesn't take clip-sized bodies.
+    cfg.service(
+        web::resource(PATH)
+            .app_data(web::PayloadConfig::new(*MEDIA_MAX_BYTES))
+            .route(web::get().to(handler))
+            .route(web::post().to(handler))
+    );
+    cfg.service(
+        web::resource(CLIPS_PATH)
+            .app_data(web::PayloadConfig::new(*MEDIA_CLIP_MAX_BYTES))
+            .route(web::post().to(clip_handler))
+    );
+    cfg.configure(key::configure);
+}
@@ -0,0 +1,128 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::http::header::{ACCEPT_RANGES, CACHE_CONTROL, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RANGE, X_CONTENT_TYPE_OPTIONS};
+use serde::Deserialize;
+
+use crate::libraries::media::{content_type, is_valid_key};
+use crate::libraries::storage::get_storage;
+use crate::libraries::{response_internal_server_error, response_not_found, response_ok_builder};
+
+pub const PATH: &str = "/api/media/{key}";
+
+/// Keys are content hashes, so a response never goes stale.
+const CACHE_FOREVER: &str = "public, max-age=31536000, immutable";
+
+#[derive(Deserialize)]
+struct Request {
+    key: String,
+}
+
+/// The inclusive byte range asked for by a single-range `Range` header (`bytes=0-99`,
+/// `bytes=100-` or `bytes=-100`), or `None` when it can't be satisfied. Media players seek in clips
+/// with these.
+fn byte_range(header: &str, length: u64) -> Option<(u64, u64)> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let last = length.checked_sub(1)?;
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => (length.saturating_sub(suffix.parse().ok()?), last),
+        (start, "") => (start.parse().ok()?, last),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(last)),
+    };
+
+    (start <= end).then_some((start, end))
+}
+
+/// Serves an uploaded image or clip. Public, since players load slide images without signing in.
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+) -> impl Responder {
+    if !is_valid_key(&path.key) {
+        return response_not_found();
+    }
+
+    let etag = format!("\"{}\"", path.key);
+
+    let cached = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    if cached {
+        return HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .insert_header((CACHE_CONTROL, CACHE_FOREVER))
+            .finish();
+    }
+
+    let size = match get_storage().size(&path.key).await {
+        Ok(Some(size)) => size,
+        Ok(None) => return response_not_found(),
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let range = req
+        .headers()
+        .get(RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|range| byte_range(range, size));
+
+    let (mut response, start, end) = match range {
+        Some(Some((start, end))) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size)));
+
+            (response, start, end)
+        }
+        Some(None) => {
+            return HttpResponse::RangeNotSatisfiable()
+                .insert_header((CONTENT_RANGE, format!("bytes */{}", size)))
+                .finish();
+        }
+        // An empty range, for an empty object.
+        None if size == 0 => (response_ok_builder(), 1, 0),
+        None => (response_ok_builder(), 0, size - 1),
+    };
+
+    let body = match get_storage().stream(&path.key, start, end).await {
+        Ok(Some(body)) => body,
+        Ok(None) => return response_not_found(),
+        Err(_) => return response_internal_server_error(),
+    };
+
+    response
+        .insert_header((CONTENT_TYPE, content_type(&path.key).unwrap_or("application/octet-stream")))
+        .insert_header((ACCEPT_RANGES, "bytes"))
+        .insert_header((ETAG, etag))
+        .insert_header((CACHE_CONTROL, CACHE_FOREVER))
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .no_chunking(end + 1 - start)
+        .streaming(body)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::get().to(handler)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        assert_eq!(byte_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(byte_range("bytes=900-", 1000), Some((900, 999)));
+        assert_eq!(byte_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(byte_range("bytes=990-2000", 1000), Some((990, 999)));
+    }
+
+    #[test]
+    fn refuses_ranges_it_cant_satisfy() {
+        assert_eq!(byte_range("bytes=1000-", 1000), None);
+        assert_eq!(byte_range("bytes=50-10", 1000), None);
+        assert_eq!(byte_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(byte_range("items=0-1", 1000), None);
+        assert_eq!(byte_range("bytes=0-", 0), None);
+    }
+}
@@ -1,67 +1,109 @@
+mod game;
+
 use std::collections::HashMap;
-use actix_web::{web, HttpRequest, HttpResponse, Error, HttpMessage};
+use chrono::Utc;
+use actix_web::{web, HttpRequest, HttpResponse, Error};
 use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use mongodb::bson::{doc, oid::ObjectId, Document};
 use mongodb::Database;
 use sha2::{Sha256, Digest};
+use rand::Rng;
 use actix::{Actor, Addr, AsyncContext, Context, Message, Handler};
 use std::sync::Arc;
 use std::thread;
 use tokio::sync::Mutex;
-use actix_rt;
 use crate::env::JWT_SECRET;
-use crate::libraries::redis::{RedisConn, with_transaction};
+use crate::libraries::redis::{subscribe_forever, RedisConn};
+use crate::models::id::UserId;
+use crate::models::room::{QuizSnapshot, Room, UpdateError};
+use self::game::{current_slide_message, GameAction, Player};
+
+pub use self::game::OPEN_ENDED_MAX_LENGTH;
 
 #[derive(Message)]
 #[rtype(result = "()")]
 struct Register {
     room_code: String,
     unique_id: String,
+    connection_id: String,
     addr: Addr<QuizWebSocket>,
 }
 
-#[derive(Message)]
+/// Closes the socket a player was on before reconnecting. Also published on `disconnect` for the
+/// other servers; names the replaced connection so it never closes the one that replaced it.
+#[derive(Message, Serialize, Deserialize, Debug, PartialEq)]
 #[rtype(result = "()")]
 struct Disconnect {
     room_code: String,
     unique_id: String,
+    connection_id: String,
+}
+
+/// Sent when a socket closes; only forgets it if it is still the one registered for its player.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Unregister {
+    room_code: String,
+    unique_id: String,
+    connection_id: String,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
 struct WsMessage(String);
 
-#[derive(Clone, Deserialize)]
-pub(self) struct QuizRequest {
+#[derive(Serialize, Deserialize)]
+struct BroadcastPayload {
     room_code: String,
-    nickname: String,
+    target: Option<String>,
+    message: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct QuizRoom {
-    quiz_id: String,
-    owner_id: String,
+#[derive(Clone, Deserialize)]
+struct QuizRequest {
     room_code: String,
-    created_at: i64,
-    players: HashMap<String, String>,
-    scores: HashMap<String, i64>,
-    current_slide: i32,
-    started: bool,
+    nickname: String,
 }
 
 struct QuizWebSocket {
     unique_id: String,
-    user_id: String,
+    /// Tells this socket apart from the player's earlier or later ones.
+    connection_id: String,
+    user_id: Option<UserId>,
     nickname: String,
-    room_key: String,
+    room_code: String,
     db: web::Data<Database>,
     manager: Addr<ConnectionManager>,
 }
 
-pub(self) struct ConnectionManager {
-    connections: Arc<Mutex<HashMap<String, HashMap<String, Addr<QuizWebSocket>>>>>,
+struct Connection {
+    connection_id: String,
+    addr: Addr<QuizWebSocket>,
+}
+
+type RoomConnections = HashMap<String, HashMap<String, Connection>>;
+
+/// Forgets the socket registered for the player if it is the connection `connection_id`.
+fn take_connection(conns: &mut RoomConnections, room_code: &str, unique_id: &str, connection_id: &str) -> Option<Connection> {
+    let room_conns = conns.get_mut(room_code)?;
+
+    if room_conns.get(unique_id)?.connection_id != connection_id {
+        return None;
+    }
+
+    room_conns.remove(unique_id)
+}
+
+fn close_replaced(conns: &mut RoomConnections, disconnect: &Disconnect) {
+    if let Some(connection) = take_connection(conns, &disconnect.room_code, &disconnect.unique_id, &disconnect.connection_id) {
+        connection.addr.do_send(WsMessage(json!({ "error": "Disconnected due to new connection." }).to_string()));
+        connection.addr.do_send(WsMessage("close".to_string()));
+    }
+}
+
+struct ConnectionManager {
+    connections: Arc<Mutex<RoomConnections>>,
 }
 
 impl Actor for ConnectionManager {
@@ -73,43 +115,35 @@ impl Actor for ConnectionManager {
         thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
-                let mut redis_connect = match RedisConn::get_connection() {
-                    Ok(conn) => conn,
-                    Err(_) => return,
-                };
-
-                let mut pubsub = match redis_connect.pubsub() {
-                    Ok(pubsub) => pubsub,
-                    Err(_) => return,
-                };
-
-                pubsub.subscribe("disconnect").unwrap();
-
-                loop {
-                    match pubsub.get_message() {
-                        Ok(msg) => {
-                            let payload: String = msg.get_payload().unwrap_or_default();
-
-                            if let Some((room_code, unique_id)) = payload.split_once(':') {
-                                let room_code = room_code.to_string();
-                                let unique_id = unique_id.to_string();
-                                let connections = connections.clone();
-
-                                tokio::spawn(async move {
-                                    let mut conns = connections.lock().await;
-
-                                    if let Some(room_conns) = conns.get_mut(&room_code) {
-                                        if let Some(addr) = room_conns.remove(&unique_id) {
-                                            addr.do_send(WsMessage(json!({ "error": "Disconnected due to new connection." }).to_string()));
-                                            addr.do_send(WsMessage("close".to_string()));
-                                        }
+                subscribe_forever(&["disconnect", "broadcast"], |msg| {
+                    let payload: String = msg.get_payload().unwrap_or_default();
+
+                    if msg.get_channel_name() == "broadcast" {
+                        let broadcast: BroadcastPayload = match serde_json::from_str(&payload) {
+                            Ok(broadcast) => broadcast,
+                            Err(_) => return,
+                        };
+                        let connections = connections.clone();
+
+                        tokio::spawn(async move {
+                            let conns = connections.lock().await;
+
+                            if let Some(room_conns) = conns.get(&broadcast.room_code) {
+                                for (unique_id, connection) in room_conns {
+                                    if broadcast.target.as_ref().is_none_or(|target| target == unique_id) {
+                                        connection.addr.do_send(WsMessage(broadcast.message.clone()));
                                     }
-                                });
+                                }
                             }
-                        }
-                        Err(_) => break,
+                        });
+                    } else if let Ok(disconnect) = serde_json::from_str::<Disconnect>(&payload) {
+                        let connections = connections.clone();
+
+                        tokio::spawn(async move {
+                            close_replaced(&mut *connections.lock().await, &disconnect);
+                        });
                     }
-                }
+                })
             });
         });
     }
@@ -123,9 +157,24 @@ impl Handler<Register> for ConnectionManager {
 
         Box::pin(async move {
             let mut conns = connections.lock().await;
-            let room_conns = conns.entry(msg.room_code).or_insert_with(HashMap::new);
+            let room_conns = conns.entry(msg.room_code).or_default();
+
+            room_conns.insert(msg.unique_id, Connection {
+                connection_id: msg.connection_id,
+                addr: msg.addr,
+            });
+        })
+    }
+}
 
-            room_conns.insert(msg.unique_id, msg.addr);
+impl Handler<Unregister> for ConnectionManager {
+    type Result = actix::ResponseFuture<()>;
+
+    fn handle(&mut self, msg: Unregister, _: &mut Self::Context) -> Self::Result {
+        let connections = self.connections.clone();
+
+        Box::pin(async move {
+            take_connection(&mut *connections.lock().await, &msg.room_code, &msg.unique_id, &msg.connection_id);
         })
     }
 }
@@ -137,20 +186,54 @@ impl Handler<Disconnect> for ConnectionManager {
         let connections = self.connections.clone();
 
         Box::pin(async move {
-            let mut conns = connections.lock().await;
+            close_replaced(&mut *connections.lock().await, &msg);
+        })
+    }
+}
 
-            if let Some(room_conns) = conns.get_mut(&msg.room_code) {
-                if let Some(addr) = room_conns.remove(&msg.unique_id) {
+fn broadcast(redis_connect: &mut RedisConn, room_code: &str, target: Option<&str>, message: serde_json::Value) {
+    let payload = BroadcastPayload {
+        room_code: room_code.to_string(),
+        target: target.map(|target| target.to_string()),
+        message: message.to_string(),
+    };
 
-                    addr.do_send(WsMessage(json!({ "error": "Disconnected." }).to_string()));
-                    addr.do_send(WsMessage("close".to_string()));
-                }
-            }
-        })
+    if let Ok(payload) = serde_json::to_string(&payload) {
+        redis_connect.publish("broadcast", &payload).unwrap_or(0);
     }
 }
 
-fn generate_unique_id(req: &HttpRequest) -> String {
+/// Seats the player (or host) in the room on the connection `connection_id`. A player coming back
+/// keeps their score; only the socket they were on is replaced, and that one is returned to be
+/// closed.
+fn join(room: &mut Room, user_id: Option<UserId>, unique_id: &str, nickname: &str, connection_id: &str) -> Option<Disconnect> {
+    let previous_id = if room.is_host(user_id) {
+        room.host_id.replace(unique_id.to_string())
+    } else {
+        room.scores.entry(unique_id.to_string()).or_insert(0);
+
+        room.players.insert(unique_id.to_string(), nickname.to_string()).map(|_| unique_id.to_string())
+    };
+
+    let replaced = previous_id.and_then(|previous_id| {
+        room.connections.remove(&previous_id).map(|connection_id| Disconnect {
+            room_code: room.room_code.clone(),
+            unique_id: previous_id,
+            connection_id,
+        })
+    });
+
+    room.connections.insert(unique_id.to_string(), connection_id.to_string());
+
+    replaced
+}
+
+/// Who the player is across reconnects: their account when signed in, otherwise their device.
+fn generate_unique_id(req: &HttpRequest, user_id: Option<UserId>) -> String {
+    if let Some(user_id) = user_id {
+        return format!("{:x}", Sha256::digest(format!("user:{}:{}", user_id, JWT_SECRET.clone())));
+    }
+
     let conn_info = req.connection_info();
     let ip = conn_info.realip_remote_addr().unwrap_or("unknown");
     let user_agent = req.headers().get("user-agent").map_or("unknown", |h| h.to_str().unwrap());
@@ -174,79 +257,19 @@ async fn handler(
 ) -> Result<HttpResponse, Error> {
     crate::middlewares::jwt::middleware(&req, &db).await;
 
-    let user_id = req
-        .extensions()
-        .get::<crate::middlewares::jwt::RequestUser>()
-        .map(|data| data.user.user_id.clone())
-        .unwrap_or(String::new());
+    let user_id = crate::middlewares::jwt::user_id(&req);
 
-    let unique_id = generate_unique_id(&req);
+    let unique_id = generate_unique_id(&req, user_id);
     let room_code = query.room_code.clone();
     let nickname = query.nickname.clone();
-    let room_key = format!("quiz_room:{}", room_code);
-
-    // let mut redis_connect = RedisConn::get_connection()
-    //     .map_err(|_| crate::libraries::response_internal_server_error()).unwrap();
-    // let room_exists: bool = redis_connect.exists(&room_key).unwrap_or(false);
-    // if !room_exists {
-    //     let mock_quiz_id = "507f1f77bcf86cd799439011";
-    //     let mock_room = QuizRoom {
-    //         quiz_id: mock_quiz_id.to_string(),
-    //         owner_id: "507f191e810c19729de860ea".to_string(),
-    //         room_code: room_code.clone(),
-    //         created_at: chrono::Utc::now().timestamp_millis(),
-    //         players: HashMap::new(),
-    //         current_slide: 0,
-    //         scores: HashMap::new(),
-    //         started: false,
-    //     };
-    //
-    //     let room_str = serde_json::to_string(&mock_room)?;
-    //
-    //     let result: redis::RedisResult<()> = with_transaction(|pipe| {
-    //         pipe.set_ex(&room_key, &room_str, 3600);
-    //         Ok(())
-    //     });
-    //
-    //     result.map_err(|_| crate::libraries::response_internal_server_error()).unwrap();
-    //
-    //     let quiz_collection = db.collection::<Document>("quizzes");
-    //     let quiz_exists = quiz_collection
-    //         .find_one(doc! { "_id": ObjectId::parse_str(mock_quiz_id).unwrap() })
-    //         .await
-    //         .map_err(|_| crate::libraries::response_internal_server_error()).unwrap()
-    //         .is_some();
-    //
-    //     if !quiz_exists {
-    //         let mock_quiz = doc! {
-    //             "_id": ObjectId::parse_str(mock_quiz_id).unwrap(),
-    //             "owner_id": ObjectId::parse_str("507f191e810c19729de860ea").unwrap(),
-    //             "title": "Mock Quiz",
-    //             "description": "This is a mock quiz for testing",
-    //             "slides": [
-    //                 {
-    //                     "question": "What is 2 + 2?",
-    //                     "options": ["3", "4", "5", "6"],
-    //                     "correct_answer": 1
-    //                 }
-    //             ],
-    //             "created_at": mongodb::bson::DateTime::now(),
-    //             "updated_at": mongodb::bson::DateTime::now(),
-    //             "is_deleted": false
-    //         };
-    //         quiz_collection
-    //             .insert_one(mock_quiz)
-    //             .await
-    //             .map_err(|_| crate::libraries::response_internal_server_error()).unwrap();
-    //     }
-    // }
 
     let ws = ws::start(
         QuizWebSocket {
             unique_id,
+            connection_id: format!("{:016x}", rand::rng().random::<u64>()),
             user_id,
             nickname,
-            room_key,
+            room_code,
             db,
             manager: manager.get_ref().clone(),
         },
@@ -261,100 +284,56 @@ impl Actor for QuizWebSocket {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        let db = self.db.clone();
         let unique_id = self.unique_id.clone();
-        let room_key = self.room_key.clone();
+        let connection_id = self.connection_id.clone();
+        let user_id = self.user_id;
+        let room_code = self.room_code.clone();
         let nickname = self.nickname.clone();
         let addr = ctx.address();
         let manager = self.manager.clone();
-        let room_code = room_key.replace("quiz_room:", "");
 
         ctx.spawn(actix::fut::wrap_future(async move {
+            let close = |message: &str| {
+                addr.do_send(WsMessage(json!({ "error": message }).to_string()));
+                addr.do_send(WsMessage("close".to_string()));
+            };
+
             let mut redis_connect = match RedisConn::get_connection() {
                 Ok(conn) => conn,
-                Err(_) => {
-                    addr.do_send(WsMessage(json!({ "error": "Internal server error." }).to_string()));
-                    addr.do_send(WsMessage("close".to_string()));
-                    return;
-                }
+                Err(_) => return close("Internal server error."),
             };
 
-            let room_exists: bool = redis_connect.exists(&room_key).unwrap_or(false);
+            let snapshot_exists = redis_connect.exists(&QuizSnapshot::key(&room_code)).unwrap_or(false);
 
-            if !room_exists {
-                addr.do_send(WsMessage(json!({ "error": "Not found." }).to_string()));
-                addr.do_send(WsMessage("close".to_string()));
-                return;
+            if !snapshot_exists {
+                return close("Not found");
             }
 
-            let room_str: Option<String> = redis_connect.get(&room_key).unwrap_or(None);
+            let result = Room::update(&mut redis_connect, &room_code, |room| {
+                Ok::<_, ()>(join(room, user_id, &unique_id, &nickname, &connection_id))
+            });
 
-            let mut room: QuizRoom = match room_str {
-                Some(r) => match serde_json::from_str(&r) {
-                    Ok(room) => room,
-                    Err(_) => {
-                        addr.do_send(WsMessage(json!({ "error": "Bot found." }).to_string()));
-                        addr.do_send(WsMessage("close".to_string()));
-                        return;
-                    }
-                },
-                None => {
-                    addr.do_send(WsMessage(json!({ "error": "Not found." }).to_string()));
-                    addr.do_send(WsMessage("close".to_string()));
-                    return;
-                }
+            let (room, replaced) = match result {
+                Ok(updated) => updated,
+                Err(UpdateError::NotFound) => return close("Not found."),
+                Err(_) => return close("Bad request."),
             };
 
-            let quiz_exists = db.collection::<Document>("quizzes")
-                .find_one(doc! {
-                    "_id": ObjectId::parse_str(&room.quiz_id).unwrap(),
-                    "is_deleted": { "$ne": true }
-                })
-                .await
-                .is_ok_and(|doc| doc.is_some());
-
-            if !quiz_exists {
-                addr.do_send(WsMessage(json!({ "error": "Not found" }).to_string()));
-                addr.do_send(WsMessage("close".to_string()));
-                return;
-            }
-
-            if room.players.contains_key(&unique_id) {
-                manager.do_send(Disconnect {
-                    room_code: room_code.clone(),
-                    unique_id: unique_id.clone(),
-                });
-
-                redis_connect.publish("disconnect", &format!("{}:{}", room_code, unique_id)).unwrap_or(0);
-
-                room.players.remove(&unique_id);
+            if let Some(disconnect) = replaced {
+                if let Ok(payload) = serde_json::to_string(&disconnect) {
+                    redis_connect.publish("disconnect", &payload).unwrap_or(0);
+                }
 
-                room.scores.remove(&unique_id);
+                manager.do_send(disconnect);
             }
 
-            room.players.insert(unique_id.clone(), nickname.clone());
-
-            room.scores.insert(unique_id.clone(), 0);
-
             manager.do_send(Register {
                 room_code: room_code.clone(),
                 unique_id: unique_id.clone(),
+                connection_id: connection_id.clone(),
                 addr: addr.clone(),
             });
 
-            let room_str = serde_json::to_string(&room).unwrap();
-
-            let result: redis::RedisResult<()> = with_transaction(|pipe| {
-                pipe.set_ex(&room_key, &room_str, 3600);
-                Ok(())
-            });
-
-            if result.is_err() {
-                addr.do_send(WsMessage(json!({ "error": "Bad request." }).to_string()));
-                addr.do_send(WsMessage("close".to_string()));
-                return;
-            }
-
             let players_list: Vec<(String, String)> = room.players.clone().into_iter().collect();
 
             let scores_list: Vec<(String, i64)> = room.scores.clone().into_iter().collect();
@@ -367,37 +346,42 @@ impl Actor for QuizWebSocket {
                 "scores": scores_list,
                 "started": room.started,
             }).to_string()));
+
+            if let Some(snapshot) = QuizSnapshot::load(&mut redis_connect, &room_code) {
+                if let Some(message) = current_slide_message(&room, &snapshot, &unique_id, Utc::now().timestamp_millis()) {
+                    addr.do_send(WsMessage(message.to_string()));
+                }
+            }
         }));
     }
 
     fn stopped(&mut self, ctx: &mut Self::Context) {
-        let room_key = self.room_key.clone();
+        let room_code = self.room_code.clone();
         let unique_id = self.unique_id.clone();
+        let connection_id = self.connection_id.clone();
         let manager = self.manager.clone();
-        let room_code = room_key.replace("quiz_room:", "");
 
         ctx.spawn(actix::fut::wrap_future(async move {
+            // Leaves the score in place for when the player reconnects, and leaves the player
+            // alone entirely if they already have.
             if let Ok(mut redis_connect) = RedisConn::get_connection() {
-                if let Some(room_str) = redis_connect.get(&room_key).unwrap_or(None) {
-                    if let Ok(mut room) = serde_json::from_str::<QuizRoom>(&room_str) {
+                let _ = Room::update(&mut redis_connect, &room_code, |room| {
+                    if room.connections.get(&unique_id) != Some(&connection_id) {
+                        return Err(());
+                    }
 
-                        room.players.remove(&unique_id);
+                    room.connections.remove(&unique_id);
 
-                        room.scores.remove(&unique_id);
+                    room.players.remove(&unique_id);
 
-                        if let Ok(room_str) = serde_json::to_string(&room) {
-                            let _: redis::RedisResult<()> = with_transaction(|pipe| {
-                                pipe.set_ex(&room_key, &room_str, 3600);
-                                Ok(())
-                            });
-                        }
-                    }
-                }
+                    Ok(())
+                });
             }
 
-            manager.do_send(Disconnect {
+            manager.do_send(Unregister {
                 room_code,
                 unique_id,
+                connection_id,
             });
         }));
     }
@@ -419,8 +403,28 @@ impl actix::StreamHandler<Result<ws::Message, ws::ProtocolError>> for QuizWebSoc
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Text(text)) => {
-                let message = text.trim().to_string();
-                ctx.text(json!({"message": format!("Echo: {}", message)}).to_string());
+                let action: GameAction = match serde_json::from_str(text.trim()) {
+                    Ok(action) => action,
+                    Err(_) => {
+                        ctx.text(json!({ "error": "Bad request." }).to_string());
+                        return;
+                    }
+                };
+
+                let db = self.db.clone();
+                let addr = ctx.address();
+                let player = Player {
+                    unique_id: self.unique_id.clone(),
+                    user_id: self.user_id,
+                    nickname: self.nickname.clone(),
+                    room_code: self.room_code.clone(),
+                };
+
+                ctx.spawn(actix::fut::wrap_future(async move {
+                    let reply = game::handle_action(db, player, action).await;
+
+                    addr.do_send(WsMessage(reply.to_string()));
+                }));
             }
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
             Ok(ws::Message::Close(_)) => ctx.close(None),
@@ -435,4 +439,48 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     }.start();
     cfg.app_data(web::Data::new(manager));
     cfg.service(web::resource("/api/play").route(web::get().to(handler)));
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::models::id::QuizId;
+    use crate::models::room::RoomSettings;
+
+    use super::*;
+
+    fn room(owner_id: UserId) -> Room {
+        let snapshot = QuizSnapshot {
+            quiz_id: QuizId::new(),
+            revision: 1,
+            title: String::new(),
+            slides: Vec::new(),
+            reveals: HashMap::new(),
+        };
+
+        Room::new(&snapshot, owner_id, "123456".to_string(), RoomSettings::default(), 0)
+    }
+
+    #[test]
+    fn reconnecting_replaces_only_the_previous_connection() {
+        let owner_id = UserId::new();
+        let mut room = room(owner_id);
+
+        assert_eq!(join(&mut room, None, "player", "Ann", "first"), None);
+        room.scores.insert("player".to_string(), 300);
+
+        assert_eq!(join(&mut room, None, "player", "Ann", "second"), Some(Disconnect {
+            room_code: "123456".to_string(),
+            unique_id: "player".to_string(),
+            connection_id: "first".to_string(),
+        }));
+        assert_eq!(room.connections.get("player").map(String::as_str), Some("second"));
+        assert_eq!(room.scores.get("player"), Some(&300));
+
+        assert_eq!(join(&mut room, Some(owner_id), "host", "", "host-first"), None);
+        assert_eq!(join(&mut room, Some(owner_id), "host-elsewhere", "", "host-second"), Some(Disconnect {
+            room_code: "123456".to_string(),
+            unique_id: "host".to_string(),
+            connection_id: "host-first".to_string(),
+        }));
+        assert!(!room.connections.contains_key("host"));
+    }
+}
@@ -0,0 +1,745 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use actix_web::web;
+use chrono::Utc;
+use mongodb::bson::{doc, to_bson, Bson, DateTime, Document};
+use mongodb::Database;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::libraries::profanity::is_suspicious;
+use crate::libraries::redis::RedisConn;
+use crate::models::id::UserId;
+use crate::models::room::{OpenResponse, PlayerAnswer, QuizSnapshot, ResponseStatus, Room, UpdateError};
+use crate::models::slide::{Slide, DEFAULT_POINTS};
+use crate::routes::quiz::validation::ANSWER_MAX_LENGTH;
+use super::broadcast;
+
+pub const OPEN_ENDED_DEFAULT_LENGTH: u32 = 200;
+
+pub const OPEN_ENDED_MAX_LENGTH: u32 = 1000;
+
+/// Seconds a reveal takes on slides without a time limit.
+pub const REVEAL_DEFAULT_DURATION: u32 = 20;
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum GameAction {
+    Start,
+    NextSlide,
+    SubmitAnswer { answers: Vec<usize> },
+    SubmitResponse { text: String },
+    /// A typed answer to a short answer or numerical slide.
+    SubmitText { text: String },
+    ModerateResponse { response_id: String, status: ResponseStatus },
+    /// The host's clip finished playing; opens answering on the current slide.
+    ClipEnded,
+    EndGame,
+}
+
+pub struct Player {
+    pub unique_id: String,
+    pub user_id: Option<UserId>,
+    pub nickname: String,
+    pub room_code: String,
+}
+
+fn error(message: &str) -> Value {
+    json!({ "error": message })
+}
+
+fn is_window_open(room: &Room, slide: &Slide, now: i64) -> bool {
+    if !slide.is_answerable() {
+        return false;
+    }
+
+    match (room.slide_started_at, slide.time_limit()) {
+        (Some(started_at), Some(limit)) if limit > 0 => now <= started_at + limit as i64 * 1000,
+        (Some(_), _) => true,
+        (None, _) => false,
+    }
+}
+
+fn score_answer(
+    chosen: &[usize],
+    correct_answers: &Option<Vec<bool>>,
+    points: Option<u32>,
+    limit: Option<u32>,
+    elapsed: i64,
+) -> (bool, i64) {
+    let expected: HashSet<usize> = correct_answers
+        .as_ref()
+        .map(|correct| correct.iter().enumerate().filter(|(_, c)| **c).map(|(i, _)| i).collect())
+        .unwrap_or_default();
+    let chosen: HashSet<usize> = chosen.iter().copied().collect();
+
+    if expected.is_empty() || chosen != expected {
+        return (false, 0);
+    }
+
+    (true, timed_points(points, limit, elapsed))
+}
+
+/// Whether typed `text` answers a short answer or numerical slide; `None` for other slides.
+fn check_typed_answer(slide: &Slide, text: &str) -> Option<bool> {
+    let normalize = |text: &str, case_sensitive: bool| {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if case_sensitive { text } else { text.to_lowercase() }
+    };
+
+    match slide {
+        Slide::ShortAnswer(slide) => {
+            let text = normalize(text, slide.case_sensitive);
+
+            Some(slide.accepted_answers.iter().any(|answer| normalize(answer, slide.case_sensitive) == text))
+        }
+        Slide::Numerical(slide) => {
+            // Accept a decimal comma as well as a point.
+            let value = text.trim().replace(',', ".").parse::<f64>().ok();
+
+            Some(value.is_some_and(|value| (value - slide.correct_value).abs() <= slide.tolerance))
+        }
+        _ => None,
+    }
+}
+
+/// Points for a correct answer: the full amount early on, down to half at the time limit.
+fn timed_points(points: Option<u32>, limit: Option<u32>, elapsed: i64) -> i64 {
+    let points = points.unwrap_or(DEFAULT_POINTS) as f64;
+
+    let awarded = match limit {
+        Some(limit) if limit > 0 => {
+            let ratio = (elapsed as f64 / (limit as f64 * 1000.0)).clamp(0.0, 1.0);
+            points * (1.0 - ratio / 2.0)
+        }
+        _ => points,
+    };
+
+    awarded.round() as i64
+}
+
+/// When answering opens on a slide shown at `now`: straight away, or once the host reports its
+/// clip has finished.
+fn answering_start(slide: Option<&Slide>, now: i64) -> Option<i64> {
+    match slide {
+        Some(slide) if slide.clip().is_some() => None,
+        _ => Some(now),
+    }
+}
+
+fn new_response_id() -> String {
+    format!("{:016x}", rand::rng().random::<u64>())
+}
+
+/// The current slide as `viewer` sees it: players get their own answer order when the room
+/// shuffles answers, the host and everyone else the canonical one.
+fn slide_message(room: &Room, snapshot: &QuizSnapshot, viewer: Option<&str>, stage: usize) -> Option<Value> {
+    let slide = snapshot.slides.get(room.current_slide as usize)?;
+
+    let mut public = match viewer {
+        Some(player_id) if room.settings.shuffle_answers && room.players.contains_key(player_id) && slide.answer_count() >= 2 => {
+            slide.to_shuffled_json(&room.answer_order(player_id, room.current_slide, slide.answer_count()))
+        }
+        _ => slide.to_public_json(),
+    };
+
+    // Reveal slides go out with the `stage` reached so far in place of the image, or the image
+    // itself once every stage has passed; the rest follow from `schedule_reveal`.
+    if let Some(stages) = snapshot.reveals.get(&(room.current_slide as usize)) {
+        if let Some(path) = stages.get(stage) {
+            public["image_path"] = json!(path);
+        }
+
+        public["reveal"] = json!({
+            "stage": stage.min(stages.len()),
+            "stages": stages.len(),
+            "revealed": stage >= stages.len(),
+        });
+    }
+
+    Some(json!({
+        "action": "slide",
+        "current_slide": room.current_slide,
+        "slide": public,
+        "answerable": slide.is_answerable() && room.slide_started_at.is_some(),
+        "awaiting_clip": slide.clip().is_some() && room.slide_started_at.is_none(),
+        "started_at": room.slide_started_at,
+    }))
+}
+
+/// The current slide for someone joining a game that is already running, so they don't sit on
+/// the lobby until the host moves on. A reveal slide comes with the stage it has reached.
+pub fn current_slide_message(room: &Room, snapshot: &QuizSnapshot, unique_id: &str, now: i64) -> Option<Value> {
+    if !room.is_running() {
+        return None;
+    }
+
+    let slide = snapshot.slides.get(room.current_slide as usize)?;
+    let stages = snapshot.reveals.get(&(room.current_slide as usize)).map_or(0, Vec::len);
+
+    let stage = match room.slide_started_at {
+        Some(started_at) if stages > 0 => reveal_stage(slide, stages, now - started_at),
+        _ => 0,
+    };
+
+    slide_message(room, snapshot, Some(unique_id), stage)
+}
+
+/// How long each stage of a reveal is shown, in milliseconds.
+fn reveal_step(slide: &Slide, stages: usize) -> u64 {
+    let duration = slide.time_limit().filter(|limit| *limit > 0).unwrap_or(REVEAL_DEFAULT_DURATION) as u64 * 1000;
+
+    duration / stages.max(1) as u64
+}
+
+/// The reveal stage reached `elapsed` milliseconds into a slide; `stages` means fully revealed.
+fn reveal_stage(slide: &Slide, stages: usize, elapsed: i64) -> usize {
+    let step = reveal_step(slide, stages).max(1);
+
+    (elapsed.max(0) as u64 / step).min(stages as u64) as usize
+}
+
+fn broadcast_slide(redis_connect: &mut RedisConn, room: &Room, snapshot: &QuizSnapshot) {
+    let slide = match snapshot.slides.get(room.current_slide as usize) {
+        Some(slide) => slide,
+        None => return,
+    };
+
+    if !room.settings.shuffle_answers || slide.answer_count() < 2 {
+        if let Some(message) = slide_message(room, snapshot, None, 0) {
+            broadcast(redis_connect, &room.room_code, None, message);
+        }
+    } else {
+        // With shuffling on, everyone gets their own copy: the host sees the canonical order and
+        // each player their seeded one.
+        let viewers = room.host_id.iter().chain(room.players.keys());
+
+        for viewer in viewers {
+            if let Some(message) = slide_message(room, snapshot, Some(viewer), 0) {
+                broadcast(redis_connect, &room.room_code, Some(viewer), message);
+            }
+        }
+    }
+
+    start_reveal(room, snapshot);
+}
+
+/// Starts uncovering the current slide's image if it is a reveal slide and its clock is running.
+fn start_reveal(room: &Room, snapshot: &QuizSnapshot) {
+    if room.slide_started_at.is_none() {
+        return;
+    }
+
+    let slide = match snapshot.slides.get(room.current_slide as usize) {
+        Some(slide) => slide,
+        None => return,
+    };
+
+    if let (Some(stages), Some(image_path)) = (snapshot.reveals.get(&(room.current_slide as usize)), slide.image_path()) {
+        schedule_reveal(room, slide, stages.clone(), image_path.to_string());
+    }
+}
+
+/// Sends the later stages of a reveal slide as its time passes and the full image once it is
+/// up. Stops as soon as the room has moved on to another slide.
+fn schedule_reveal(room: &Room, slide: &Slide, stages: Vec<String>, image_path: String) {
+    let room_code = room.room_code.clone();
+    let slide_index = room.current_slide;
+    let started_at = room.slide_started_at;
+    let step = Duration::from_millis(reveal_step(slide, stages.len()));
+
+    actix_rt::spawn(async move {
+        for stage in 1..=stages.len() {
+            tokio::time::sleep(step).await;
+
+            let mut redis_connect = match RedisConn::get_connection() {
+                Ok(conn) => conn,
+                Err(_) => return,
+            };
+
+            match Room::load(&mut redis_connect, &room_code) {
+                Some(room) if room.current_slide == slide_index && room.slide_started_at == started_at => {}
+                _ => return,
+            }
+
+            let revealed = stage == stages.len();
+
+            broadcast(&mut redis_connect, &room_code, None, json!({
+                "action": "reveal",
+                "current_slide": slide_index,
+                "stage": stage,
+                "stages": stages.len(),
+                "image_path": if revealed { &image_path } else { &stages[stage] },
+                "revealed": revealed,
+            }));
+        }
+    });
+}
+
+/// `Room::update`, with failures turned into the reply for the player.
+fn update_room<T>(
+    redis_connect: &mut RedisConn,
+    room_code: &str,
+    change: impl FnMut(&mut Room) -> Result<T, Value>,
+) -> Result<(Room, T), Value> {
+    Room::update(redis_connect, room_code, change).map_err(|update_error| match update_error {
+        UpdateError::NotFound => error("Not found."),
+        UpdateError::Redis(redis_error) => {
+            tracing::error!("Failed to update room {}: {}", room_code, redis_error);
+
+            error("Internal server error.")
+        }
+        UpdateError::Rejected(reply) => reply,
+    })
+}
+
+pub async fn handle_action(db: web::Data<Database>, player: Player, action: GameAction) -> Value {
+    let mut redis_connect = match RedisConn::get_connection() {
+        Ok(conn) => conn,
+        Err(_) => return error("Internal server error."),
+    };
+
+    let is_host = match Room::load(&mut redis_connect, &player.room_code) {
+        Some(room) => room.is_host(player.user_id),
+        None => return error("Not found."),
+    };
+
+    let snapshot = match QuizSnapshot::load(&mut redis_connect, &player.room_code) {
+        Some(snapshot) => snapshot,
+        None => return error("Not found."),
+    };
+
+    let now = Utc::now().timestamp_millis();
+
+    // Every change below goes through `update_room`, which may run it more than once if other
+    // players' actions land at the same time, so the checks live inside the closures.
+    match action {
+        GameAction::Start | GameAction::NextSlide | GameAction::ModerateResponse { .. } | GameAction::ClipEnded | GameAction::EndGame if !is_host => {
+            error("Forbidden.")
+        }
+        GameAction::SubmitAnswer { .. } | GameAction::SubmitResponse { .. } | GameAction::SubmitText { .. } if is_host => {
+            error("Forbidden.")
+        }
+        GameAction::Start => {
+            if snapshot.slides.is_empty() {
+                return error("Not found.");
+            }
+
+            let room = match update_room(&mut redis_connect, &player.room_code, |room| {
+                if room.started {
+                    return Err(error("Game already started."));
+                }
+
+                room.started = true;
+                room.current_slide = 0;
+                room.slide_started_at = answering_start(snapshot.slides.first(), now);
+
+                Ok(())
+            }) {
+                Ok((room, ())) => room,
+                Err(reply) => return reply,
+            };
+
+            broadcast_slide(&mut redis_connect, &room, &snapshot);
+
+            json!({ "action": "started" })
+        }
+        GameAction::NextSlide => {
+            let room = match update_room(&mut redis_connect, &player.room_code, |room| {
+                if !room.is_running() {
+                    return Err(error("Game not started."));
+                }
+
+                let next_slide = room.current_slide + 1;
+
+                if next_slide as usize >= snapshot.slides.len() {
+                    return Err(error("No more slides."));
+                }
+
+                room.current_slide = next_slide;
+                room.slide_started_at = answering_start(snapshot.slides.get(next_slide as usize), now);
+
+                Ok(())
+            }) {
+                Ok((room, ())) => room,
+                Err(reply) => return reply,
+            };
+
+            broadcast_slide(&mut redis_connect, &room, &snapshot);
+
+            json!({ "action": "next_slide", "current_slide": room.current_slide })
+        }
+        GameAction::SubmitAnswer { answers } => {
+            let result = update_room(&mut redis_connect, &player.room_code, |room| {
+                if !room.is_running() || !room.players.contains_key(&player.unique_id) {
+                    return Err(error("Bad request."));
+                }
+
+                let slide = snapshot.slides.get(room.current_slide as usize).ok_or_else(|| error("Not found."))?;
+
+                let (correct_answers, points) = match slide {
+                    Slide::Question(slide) => (&slide.correct_answers, slide.points),
+                    Slide::TrueOrFalse(slide) => (&slide.correct_answers, slide.points),
+                    _ => return Err(error("Bad request.")),
+                };
+
+                if !is_window_open(room, slide, now) {
+                    return Err(error("Answering window closed."));
+                }
+
+                if room.has_answered(&player.unique_id) {
+                    return Err(error("Already answered."));
+                }
+
+                // Players submit the positions they saw; scoring and the stored answers use the
+                // canonical indices.
+                let order = room.answer_order(&player.unique_id, room.current_slide, slide.answer_count());
+
+                let answers: Vec<usize> = answers
+                    .iter()
+                    .map(|shown| order.get(*shown).copied())
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| error("Bad request."))?;
+
+                let elapsed = now - room.slide_started_at.unwrap_or(now);
+                let (correct, awarded) = score_answer(&answers, correct_answers, points, slide.time_limit(), elapsed);
+
+                room.answers.push(PlayerAnswer {
+                    slide_index: room.current_slide,
+                    player_id: player.unique_id.clone(),
+                    answers,
+                    text: None,
+                    correct,
+                    points: awarded,
+                    submitted_at: now,
+                });
+
+                *room.scores.entry(player.unique_id.clone()).or_insert(0) += awarded;
+
+                Ok((correct, awarded))
+            });
+
+            match result {
+                Ok((_, (correct, awarded))) => json!({ "action": "answer_received", "correct": correct, "points": awarded }),
+                Err(reply) => reply,
+            }
+        }
+        GameAction::SubmitText { text } => {
+            let text = text.trim().to_string();
+
+            if text.is_empty() || text.chars().count() > ANSWER_MAX_LENGTH {
+                return error("Bad request.");
+            }
+
+            let result = update_room(&mut redis_connect, &player.room_code, |room| {
+                if !room.is_running() || !room.players.contains_key(&player.unique_id) {
+                    return Err(error("Bad request."));
+                }
+
+                let slide = snapshot.slides.get(room.current_slide as usize).ok_or_else(|| error("Not found."))?;
+
+                let correct = check_typed_answer(slide, &text).ok_or_else(|| error("Bad request."))?;
+
+                if !is_window_open(room, slide, now) {
+                    return Err(error("Answering window closed."));
+                }
+
+                if room.has_answered(&player.unique_id) {
+                    return Err(error("Already answered."));
+                }
+
+                let points = match slide {
+                    Slide::ShortAnswer(slide) => slide.points,
+                    Slide::Numerical(slide) => slide.points,
+                    _ => None,
+                };
+
+                let elapsed = now - room.slide_started_at.unwrap_or(now);
+                let awarded = if correct { timed_points(points, slide.time_limit(), elapsed) } else { 0 };
+
+                room.answers.push(PlayerAnswer {
+                    slide_index: room.current_slide,
+                    player_id: player.unique_id.clone(),
+                    answers: Vec::new(),
+                    text: Some(text.clone()),
+                    correct,
+                    points: awarded,
+                    submitted_at: now,
+                });
+
+                *room.scores.entry(player.unique_id.clone()).or_insert(0) += awarded;
+
+                Ok((correct, awarded))
+            });
+
+            match result {
+                Ok((_, (correct, awarded))) => json!({ "action": "answer_received", "correct": correct, "points": awarded }),
+                Err(reply) => reply,
+            }
+        }
+        GameAction::SubmitResponse { text } => {
+            let text = text.trim().to_string();
+
+            let (room, response) = match update_room(&mut redis_connect, &player.room_code, |room| {
+                if !room.is_running() || !room.players.contains_key(&player.unique_id) {
+                    return Err(error("Bad request."));
+                }
+
+                let slide = snapshot.slides.get(room.current_slide as usize).ok_or_else(|| error("Not found."))?;
+
+                let max_length = match slide {
+                    Slide::OpenEnded(slide) => slide
+                        .max_length
+                        .unwrap_or(OPEN_ENDED_DEFAULT_LENGTH)
+                        .min(OPEN_ENDED_MAX_LENGTH),
+                    _ => return Err(error("Bad request.")),
+                };
+
+                if !is_window_open(room, slide, now) {
+                    return Err(error("Answering window closed."));
+                }
+
+                if text.is_empty() || text.chars().count() > max_length as usize {
+                    return Err(error("Bad request."));
+                }
+
+                let already_responded = room.responses.iter().any(|response| {
+                    response.slide_index == room.current_slide && response.player_id == player.unique_id
+                });
+
+                if already_responded {
+                    return Err(error("Already answered."));
+                }
+
+                let response = OpenResponse {
+                    response_id: new_response_id(),
+                    slide_index: room.current_slide,
+                    player_id: player.unique_id.clone(),
+                    nickname: player.nickname.clone(),
+                    status: if is_suspicious(&text) { ResponseStatus::Held } else { ResponseStatus::Pending },
+                    text: text.clone(),
+                    submitted_at: now,
+                };
+
+                room.responses.push(response.clone());
+
+                Ok(response)
+            }) {
+                Ok(updated) => updated,
+                Err(reply) => return reply,
+            };
+
+            if let Some(host_id) = room.host_id.as_deref() {
+                broadcast(&mut redis_connect, &room.room_code, Some(host_id), json!({
+                    "action": "moderation_queue",
+                    "response": response,
+                }));
+            }
+
+            json!({ "action": "response_received", "response_id": response.response_id })
+        }
+        GameAction::ModerateResponse { response_id, status } => {
+            if status != ResponseStatus::Approved && status != ResponseStatus::Hidden {
+                return error("Bad request.");
+            }
+
+            let (room, (previous_status, response)) = match update_room(&mut redis_connect, &player.room_code, |room| {
+                let response = room
+                    .responses
+                    .iter_mut()
+                    .find(|response| response.response_id == response_id)
+                    .ok_or_else(|| error("Not found."))?;
+
+                let previous_status = response.status;
+                response.status = status;
+
+                Ok((previous_status, response.clone()))
+            }) {
+                Ok(updated) => updated,
+                Err(reply) => return reply,
+            };
+
+            if status == ResponseStatus::Approved {
+                broadcast(&mut redis_connect, &room.room_code, None, json!({
+                    "action": "response_approved",
+                    "response": {
+                        "response_id": response.response_id,
+                        "slide_index": response.slide_index,
+                        "nickname": response.nickname,
+                        "text": response.text,
+                    },
+                }));
+            } else if previous_status == ResponseStatus::Approved {
+                broadcast(&mut redis_connect, &room.room_code, None, json!({
+                    "action": "response_hidden",
+                    "response_id": response.response_id,
+                }));
+            }
+
+            json!({ "action": "response_moderated", "response_id": response.response_id, "status": status })
+        }
+        GameAction::ClipEnded => {
+            let room = match update_room(&mut redis_connect, &player.room_code, |room| {
+                if !room.is_running() {
+                    return Err(error("Game not started."));
+                }
+
+                let has_clip = snapshot.slides.get(room.current_slide as usize).is_some_and(|slide| slide.clip().is_some());
+
+                if !has_clip || room.slide_started_at.is_some() {
+                    return Err(error("Bad request."));
+                }
+
+                room.slide_started_at = Some(now);
+
+                Ok(())
+            }) {
+                Ok((room, ())) => room,
+                Err(reply) => return reply,
+            };
+
+            broadcast(&mut redis_connect, &room.room_code, None, json!({
+                "action": "answering_open",
+                "current_slide": room.current_slide,
+                "started_at": now,
+            }));
+
+            start_reveal(&room, &snapshot);
+
+            json!({ "action": "clip_ended", "current_slide": room.current_slide })
+        }
+        GameAction::EndGame => {
+            // Closing the room first means no answer can slip in after the result is taken.
+            let room = match update_room(&mut redis_connect, &player.room_code, |room| {
+                if room.ended {
+                    return Err(error("Game already ended."));
+                }
+
+                room.ended = true;
+
+                Ok(())
+            }) {
+                Ok((room, ())) => room,
+                Err(reply) => return reply,
+            };
+
+            // The slides go in with the result: with bank draws made per room and the quiz free to
+            // change later, they are the only way to tell what each `answers[].slide_index` was.
+            let result = doc! {
+                "quiz_id": room.quiz_id,
+                "quiz_revision": snapshot.revision,
+                "quiz_title": snapshot.title.clone(),
+                "slides": to_bson(&snapshot.slides).unwrap_or(Bson::Null),
+                "owner_id": room.owner_id,
+                "room_code": room.room_code.clone(),
+                "players": to_bson(&room.players).unwrap_or(Bson::Null),
+                "scores": to_bson(&room.scores).unwrap_or(Bson::Null),
+                "answers": to_bson(&room.answers).unwrap_or(Bson::Null),
+                "responses": to_bson(&room.responses).unwrap_or(Bson::Null),
+                "created_at": DateTime::from_millis(room.created_at),
+                "ended_at": DateTime::from_millis(now),
+            };
+
+            if db.collection::<Document>("results").insert_one(result).await.is_err() {
+                let _ = Room::update(&mut redis_connect, &player.room_code, |room| {
+                    room.ended = false;
+
+                    Ok::<_, ()>(())
+                });
+
+                return error("Internal server error.");
+            }
+
+            let _ = room.delete();
+
+            let scores_list: Vec<(String, i64)> = room.scores.clone().into_iter().collect();
+
+            broadcast(&mut redis_connect, &room.room_code, None, json!({
+                "action": "game_ended",
+                "players": room.players,
+                "scores": scores_list,
+            }));
+
+            json!({ "action": "ended" })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_exact_choice_sets_only() {
+        let correct = Some(vec![true, false, true, false]);
+
+        assert_eq!(score_answer(&[0, 2], &correct, Some(1000), None, 0), (true, 1000));
+        assert_eq!(score_answer(&[2, 0, 2], &correct, Some(1000), None, 0), (true, 1000));
+        assert_eq!(score_answer(&[0], &correct, Some(1000), None, 0), (false, 0));
+        assert_eq!(score_answer(&[0, 1, 2], &correct, Some(1000), None, 0), (false, 0));
+        assert_eq!(score_answer(&[], &Some(vec![false, false]), Some(1000), None, 0), (false, 0));
+        assert_eq!(score_answer(&[0], &None, Some(1000), None, 0), (false, 0));
+    }
+
+    #[test]
+    fn timed_points_fall_to_half_at_the_limit() {
+        assert_eq!(timed_points(Some(1000), Some(20), 0), 1000);
+        assert_eq!(timed_points(Some(1000), Some(20), 10_000), 750);
+        assert_eq!(timed_points(Some(1000), Some(20), 20_000), 500);
+        assert_eq!(timed_points(Some(1000), Some(20), 60_000), 500);
+        assert_eq!(timed_points(None, None, 60_000), DEFAULT_POINTS as i64);
+        assert_eq!(timed_points(Some(2000), Some(0), 5_000), 2000);
+    }
+
+    #[test]
+    fn joiners_get_the_reveal_stage_reached_so_far() {
+        let slide: Slide = serde_json::from_value(json!({
+            "question_type": "open_ended", "theme": "", "time_limit": 20, "image_reveal": "blur",
+            "image_path": "", "question": "What is it?", "max_length": null,
+        })).unwrap();
+
+        assert_eq!(reveal_stage(&slide, 4, -50), 0);
+        assert_eq!(reveal_stage(&slide, 4, 4_999), 0);
+        assert_eq!(reveal_stage(&slide, 4, 5_000), 1);
+        assert_eq!(reveal_stage(&slide, 4, 19_999), 3);
+        assert_eq!(reveal_stage(&slide, 4, 20_000), 4);
+        assert_eq!(reveal_stage(&slide, 4, 90_000), 4);
+    }
+
+    #[test]
+    fn checks_typed_answers() {
+        let short_answer: Slide = serde_json::from_value(json!({
+            "question_type": "short_answer", "theme": "", "time_limit": null, "points": null,
+            "image_reveal": "", "image_path": "", "question": "Capital of France?",
+            "accepted_answers": ["Paris", "City of  Light"], "case_sensitive": false,
+        })).unwrap();
+
+        assert_eq!(check_typed_answer(&short_answer, " paris "), Some(true));
+        assert_eq!(check_typed_answer(&short_answer, "city   of light"), Some(true));
+        assert_eq!(check_typed_answer(&short_answer, "Lyon"), Some(false));
+
+        let numerical: Slide = serde_json::from_value(json!({
+            "question_type": "numerical", "theme": "", "time_limit": null, "points": null,
+            "image_reveal": "", "image_path": "", "question": "How many litres?",
+            "correct_value": 1.5, "tolerance": 0.01,
+        })).unwrap();
+
+        assert_eq!(check_typed_answer(&numerical, "1,505"), Some(true));
+        assert_eq!(check_typed_answer(&numerical, "1.6"), Some(false));
+        assert_eq!(check_typed_answer(&numerical, "one"), Some(false));
+    }
+
+    #[test]
+    fn clips_hold_answering_until_they_end() {
+        let clip: Slide = serde_json::from_value(json!({
+            "question_type": "true_or_false", "theme": "", "time_limit": 10, "points": null,
+            "image_reveal": "", "image_path": "", "question": "?",
+            "clip": { "path": "/media/clip.mp3", "start": 0.0 },
+        })).unwrap();
+
+        assert_eq!(answering_start(Some(&clip), 42), None);
+        assert_eq!(answering_start(None, 42), Some(42));
+    }
+}
@@ -1,59 +1,63 @@
+mod import;
+mod listing;
 mod quiz_id;
+mod trash;
+pub mod validation;
 
-use actix_web::{http::Method, web, HttpMessage, HttpRequest, Responder};
+use actix_web::{http::Method, web, HttpRequest, Responder};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use mongodb::bson::{doc, oid::ObjectId, to_bson, DateTime, Document};
-use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
 use mongodb::Database;
-use mongodb::results::InsertOneResult;
-use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_ok_builder};
+use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_ok_builder, response_unprocessable_entity};
+use crate::models::id::QuizId;
+use crate::models::quiz::{Quiz, Visibility};
+use crate::models::revision::QuizRevision;
+use crate::models::slide::Slide;
+use self::listing::{list_quizzes, ListQuery};
+use self::validation::{validate_media, validate_quiz};
 
 pub const PATH: &str = "/api/quiz";
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct SlideQuizQuestion {
-    theme: String,
-    time_limit: Option<u32>,
-    points: Option<u32>,
-    answer_options: String,
-    image_reveal: String,
-    image_path: String,
-    question: String,
-    answers: Option<Vec<String>>,
-    correct_answers: Option<Vec<bool>>,
-}
-
-#[derive(Clone, Deserialize, Serialize)]
-pub struct SlideQuizTrueOrFalse {
-    theme: String,
-    time_limit: Option<u32>,
-    points: Option<u32>,
-    image_reveal: String,
-    image_path: String,
-    question: String,
-    answers: Option<Vec<String>>,
-    correct_answers: Option<Vec<bool>>,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-#[serde(tag = "question_type")]
-pub enum Slide {
-    #[serde(rename = "question")]
-    Question(SlideQuizQuestion),
-    #[serde(rename = "true_or_false")]
-    TrueOrFalse(SlideQuizTrueOrFalse),
-}
-
 #[derive(Serialize, Deserialize)]
 pub struct QuizCreation {
     title: String,
     description: Option<String>,
     slides: Vec<Slide>,
+    #[serde(default)]
+    visibility: Visibility,
+    #[serde(default)]
+    tags: Vec<String>,
+    subject: Option<String>,
+    grade_level: Option<String>,
+    language: Option<String>,
+}
+
+/// Tags are matched exactly by the library filters and random draws, so they are stored
+/// trimmed, lowercased and without duplicates.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+
+    normalized
+}
+
+impl QuizCreation {
+    fn tags(&self) -> Vec<String> {
+        normalize_tags(&self.tags)
+    }
 }
 
 pub async fn handler(
     req: HttpRequest,
+    query: web::Query<ListQuery>,
     body: Option<web::Json<QuizCreation>>,
     db: web::Data<Database>,
 ) -> impl Responder {
@@ -61,28 +65,25 @@ pub async fn handler(
         return response_error;
     }
 
-    if let Some(user_data) = req.extensions().get::<crate::middlewares::jwt::RequestUser>() {
-        let owner_id = ObjectId::parse_str(&user_data.user.user_id).unwrap();
-
+    if let Some(owner_id) = crate::middlewares::jwt::user_id(&req) {
         match *req.method() {
-            Method::GET => {
-                let cursor = db.collection::<Document>("quizzes").find(doc! {
-                    "owner_id": owner_id.clone(),
-                    "is_deleted": { "$ne": true },
-                }).projection(doc! {
-                    "owner_id": 0,
-                }).await.unwrap();
-
-                let quizzes: Vec<Document> = cursor.try_collect().await.unwrap();
-
-                response_ok_builder().json(json!({ "quizzes": quizzes }))
-            }
+            Method::GET => list_quizzes(&db, owner_id, &query).await,
             Method::POST => {
                 let quiz_data = match body.ok_or_else(response_bad_request) {
-                    Ok(data) => data,
+                    Ok(data) => data.into_inner(),
                     Err(response) => return response,
                 };
 
+                if let Err(errors) = validate_quiz(&quiz_data) {
+                    return response_unprocessable_entity(errors);
+                }
+
+                match validate_media(&db, &quiz_data.slides, &[owner_id]).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(errors)) => return response_unprocessable_entity(errors),
+                    Err(_) => return response_internal_server_error(),
+                }
+
                 let mut session = match db.client().start_session().await {
                     Ok(session) => session,
                     Err(_) => return response_internal_server_error(),
@@ -90,36 +91,47 @@ pub async fn handler(
 
                 let created_at = DateTime::now();
 
-                let result: Result<InsertOneResult, mongodb::error::Error> = async {
+                let quiz = Quiz {
+                    id: QuizId::new(),
+                    owner_id,
+                    tags: quiz_data.tags(),
+                    title: quiz_data.title,
+                    description: quiz_data.description,
+                    slides: quiz_data.slides,
+                    revision: 1,
+                    forked_from: None,
+                    collaborators: Vec::new(),
+                    folder_id: None,
+                    visibility: quiz_data.visibility,
+                    subject: quiz_data.subject,
+                    grade_level: quiz_data.grade_level,
+                    language: quiz_data.language,
+                    play_count: 0,
+                    updated_at: created_at,
+                    created_at,
+                    is_deleted: false,
+                    deleted_at: None,
+                };
+
+                let result: Result<(), mongodb::error::Error> = async {
                     session.start_transaction().await?;
 
-                    let slides_bson = to_bson(&quiz_data.slides).map_err(|e| mongodb::error::Error::from(e))?;
-
-                    let quiz_result = db.collection::<Document>("quizzes")
-                        .insert_one(
-                            doc! {
-                                "owner_id": owner_id.clone(),
-                                "title": quiz_data.title.clone(),
-                                "description": quiz_data.description.clone(),
-                                "slides": slides_bson, // Dùng Bson từ to_bson
-                                "updated_at": created_at.clone(),
-                                "created_at": created_at.clone(),
-                            }
-                        )
+                    Quiz::collection(&db)
+                        .insert_one(&quiz)
                         .session(&mut session)
                         .await?;
 
+                    QuizRevision::record(&db, &mut session, &quiz, owner_id, None).await?;
+
                     session.commit_transaction().await?;
 
-                    Ok(quiz_result)
+                    Ok(())
                 }.await;
 
                 match result {
-                    Ok(quiz_result) => {
-                        let quiz_id = quiz_result.inserted_id.as_object_id().unwrap().to_hex();
-
+                    Ok(()) => {
                         response_ok_builder().json(json!({
-                            "quiz_id": format!("0x{}", quiz_id),
+                            "quiz_id": quiz.id.to_string(),
                             "created_at": created_at,
                         }))
                     }
@@ -140,5 +152,8 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource(PATH)
         .route(web::post().to(handler))
         .route(web::get().to(handler)));
+    // Registered before `quiz_id` so "import" and "trash" aren't captured as quiz ids.
+    cfg.configure(import::configure);
+    cfg.configure(trash::configure);
     cfg.configure(quiz_id::configure);
-}
\ No newline at end of file
+}
@@ -0,0 +1,502 @@
+//! Turns a spreadsheet of questions into a quiz draft.
+//!
+//! The first non-empty row is a header naming the columns; case, spaces and underscores don't
+//! matter and the columns may come in any order:
+//!
+//! | column                   | required | contents                                                    |
+//! |--------------------------|----------|-------------------------------------------------------------|
+//! | `question`               | yes      | the question, in the rich text subset                       |
+//! | `answer_1` .. `answer_4` | yes      | the answers; blank ones are left out, at least two are kept |
+//! | `correct`                | either   | correct answers by number or letter: `2`, `B`, `1,3`, `A;C` |
+//! | `correct_1` .. `correct_4` | or     | `x`, `yes`, `true` or `1` under each correct answer         |
+//! | `time_limit`             | no       | seconds                                                     |
+//! | `points`                 | no       | points for a correct answer                                 |
+//! | `image_url`              | no       | an `http(s)` image URL, or a path from `/api/media`         |
+//!
+//! A question whose two answers are "True" and "False" becomes a true-or-false slide. Rows that
+//! don't make a valid slide are reported and left out rather than failing the whole file.
+//!
+//! Moodle and QTI question files are handled by `interchange`.
+
+use std::collections::HashMap;
+use actix_web::{http::Method, web, HttpRequest, HttpResponse, Responder};
+use actix_web::http::header::CONTENT_DISPOSITION;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use mongodb::Database;
+
+use crate::libraries::media::ProcessedImage;
+use crate::libraries::interchange::Format;
+use crate::libraries::spreadsheet::{self, Sheet};
+use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_ok_builder, response_unprocessable_entity};
+use crate::models::id::UserId;
+use crate::models::media::{Media, MEDIA_PATH_PREFIX};
+use crate::models::quiz::Visibility;
+use crate::models::slide::{Slide, SlideQuizQuestion, SlideQuizTrueOrFalse};
+use crate::routes::quiz::validation::{validate_media, validate_slides, MAX_SLIDES, TITLE_MAX_LENGTH};
+use crate::routes::quiz::QuizCreation;
+
+mod interchange;
+mod remote;
+
+pub const PATH: &str = "/api/quiz/import";
+
+pub const IMPORT_MAX_BYTES: usize = 5 * 1024 * 1024;
+
+const ANSWER_COLUMNS: usize = 4;
+
+const DEFAULT_TITLE: &str = "Imported quiz";
+
+const TEMPLATE: &str = "question,answer_1,answer_2,answer_3,answer_4,correct,time_limit,points,image_url\n\
+What is 2 + 2?,3,4,5,22,2,20,1000,\n";
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    title: Option<String>,
+    /// `gift`, `moodle_xml`, `qti21` or `qti30` for another tool's question file; a spreadsheet
+    /// otherwise.
+    format: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RowError {
+    row: usize,
+    field: String,
+    reason: String,
+}
+
+/// Where each known column sits in the sheet.
+#[derive(Default)]
+struct Columns {
+    question: Option<usize>,
+    answers: [Option<usize>; ANSWER_COLUMNS],
+    correct: Option<usize>,
+    correct_flags: [Option<usize>; ANSWER_COLUMNS],
+    time_limit: Option<usize>,
+    points: Option<usize>,
+    image_url: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &[String]) -> Columns {
+        let mut columns = Columns::default();
+
+        for (index, name) in header.iter().enumerate() {
+            let name: String = name.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_lowercase();
+
+            let slot = match name.as_str() {
+                "question" => &mut columns.question,
+                "correct" => &mut columns.correct,
+                "timelimit" => &mut columns.time_limit,
+                "points" => &mut columns.points,
+                "imageurl" => &mut columns.image_url,
+                _ => {
+                    let numbered = |prefix: &str| {
+                        name.strip_prefix(prefix)
+                            .and_then(|number| number.parse::<usize>().ok())
+                            .filter(|number| (1..=ANSWER_COLUMNS).contains(number))
+                    };
+
+                    if let Some(number) = numbered("answer") {
+                        &mut columns.answers[number - 1]
+                    } else if let Some(number) = numbered("correct") {
+                        &mut columns.correct_flags[number - 1]
+                    } else {
+                        continue;
+                    }
+                }
+            };
+
+            slot.get_or_insert(index);
+        }
+
+        columns
+    }
+}
+
+fn cell(cells: &[String], column: Option<usize>) -> &str {
+    column.and_then(|column| cells.get(column)).map_or("", |value| value.trim())
+}
+
+/// Answer numbers (1-based) marked correct in a `correct` cell such as `2`, `B` or `1, 3`.
+fn parse_correct(value: &str) -> Option<Vec<usize>> {
+    value
+        .split(|c: char| c == ',' || c == ';' || c == '/' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| match token.to_ascii_lowercase().as_str() {
+            letter @ ("a" | "b" | "c" | "d") => Some((letter.as_bytes()[0] - b'a') as usize + 1),
+            number => number.parse().ok().filter(|number| (1..=ANSWER_COLUMNS).contains(number)),
+        })
+        .collect()
+}
+
+fn is_checked(value: &str) -> bool {
+    matches!(value.to_lowercase().as_str(), "x" | "1" | "true" | "yes" | "y")
+}
+
+fn parse_number(value: &str) -> Option<Option<u32>> {
+    if value.is_empty() {
+        return Some(None);
+    }
+
+    // XLSX numbers can come through as `20.0`.
+    let value = value.strip_suffix(".0").unwrap_or(value);
+
+    value.parse().ok().map(Some)
+}
+
+struct Importer<'a> {
+    db: &'a Database,
+    user_id: UserId,
+    /// Every downloaded `image_url`, by URL, fetched up front and all at once.
+    fetched: HashMap<String, Result<ProcessedImage, &'static str>>,
+    /// Media paths of images already stored, by URL, so repeated URLs are stored once.
+    images: HashMap<String, String>,
+}
+
+impl Importer<'_> {
+    /// The `image_path` for an `image_url` cell: uploaded media is used as is, anything else was
+    /// downloaded beforehand and is stored as the user's own upload.
+    async fn image_path(&mut self, url: &str) -> mongodb::error::Result<Result<String, &'static str>> {
+        if url.starts_with(MEDIA_PATH_PREFIX) {
+            return Ok(Ok(url.to_string()));
+        }
+
+        if let Some(path) = self.images.get(url) {
+            return Ok(Ok(path.clone()));
+        }
+
+        let image = match self.fetched.get(url) {
+            Some(Ok(image)) => image,
+            Some(Err(reason)) => return Ok(Err(reason)),
+            None => return Ok(Err("could not be downloaded")),
+        };
+
+        let path = match Media::store_image(self.db, self.user_id, image).await? {
+            Some(media) => media.path(),
+            None => return Err(mongodb::error::Error::custom("Media not stored")),
+        };
+
+        self.images.insert(url.to_string(), path.clone());
+
+        Ok(Ok(path))
+    }
+
+    /// Builds the slide for one row, or the problems with it as `(field, reason)`.
+    async fn slide(&mut self, columns: &Columns, cells: &[String]) -> mongodb::error::Result<Result<Slide, Vec<(String, String)>>> {
+        let mut errors: Vec<(String, String)> = Vec::new();
+        let mut error = |field: &str, reason: &str| errors.push((field.to_string(), reason.to_string()));
+
+        // Blank answer columns are dropped, so remember which column each kept answer came from.
+        let answers: Vec<(usize, String)> = (0..ANSWER_COLUMNS)
+            .map(|index| (index + 1, cell(cells, columns.answers[index]).to_string()))
+            .filter(|(_, answer)| !answer.is_empty())
+            .collect();
+
+        let correct: Vec<usize> = match columns.correct {
+            Some(_) => match parse_correct(cell(cells, columns.correct)) {
+                Some(correct) => correct,
+                None => {
+                    error("correct", &format!("must list answer numbers 1-{} or letters A-D", ANSWER_COLUMNS));
+                    Vec::new()
+                }
+            },
+            None => (0..ANSWER_COLUMNS)
+                .filter(|index| is_checked(cell(cells, columns.correct_flags[*index])))
+                .map(|index| index + 1)
+                .collect(),
+        };
+
+        if let Some(number) = correct.iter().find(|number| !answers.iter().any(|(column, _)| column == *number)) {
+            error("correct", &format!("marks answer_{} correct but it is empty", number));
+        }
+
+        let time_limit = parse_number(cell(cells, columns.time_limit)).unwrap_or_else(|| {
+            error("time_limit", "must be a whole number of seconds");
+            None
+        });
+
+        let points = parse_number(cell(cells, columns.points)).unwrap_or_else(|| {
+            error("points", "must be a whole number");
+            None
+        });
+
+        let image_url = cell(cells, columns.image_url);
+
+        let image_path = if image_url.is_empty() || !errors.is_empty() {
+            String::new()
+        } else {
+            match self.image_path(image_url).await? {
+                Ok(path) => path,
+                Err(reason) => {
+                    errors.push(("image_url".to_string(), reason.to_string()));
+                    String::new()
+                }
+            }
+        };
+
+        if !errors.is_empty() {
+            return Ok(Err(errors));
+        }
+
+        let question = cell(cells, columns.question).to_string();
+        let correct_answers: Vec<bool> = answers.iter().map(|(column, _)| correct.contains(column)).collect();
+        let is_true_or_false = answers.len() == 2
+            && answers.iter().any(|(_, answer)| answer.eq_ignore_ascii_case("true"))
+            && answers.iter().any(|(_, answer)| answer.eq_ignore_ascii_case("false"));
+
+        let slide = if is_true_or_false {
+            Slide::TrueOrFalse(SlideQuizTrueOrFalse {
+                theme: "default".to_string(),
+                time_limit,
+                points,
+                image_reveal: "none".to_string(),
+                image_path,
+                clip: None,
+                question,
+                answers: Some(answers.iter().map(|(_, answer)| answer.clone()).collect()),
+                correct_answers: Some(correct_answers),
+            })
+        } else {
+            Slide::Question(SlideQuizQuestion {
+                theme: "default".to_string(),
+                time_limit,
+                points,
+                answer_options: if correct.len() > 1 { "multiple" } else { "single" }.to_string(),
+                image_reveal: "none".to_string(),
+                image_path,
+                clip: None,
+                question,
+                answers: Some(answers.iter().map(|(_, answer)| answer.clone()).collect()),
+                correct_answers: Some(correct_answers),
+            })
+        };
+
+        let problems = match validate_slides(std::slice::from_ref(&slide)) {
+            Ok(()) => validate_media(self.db, std::slice::from_ref(&slide), &[self.user_id]).await?.err(),
+            Err(problems) => Some(problems),
+        };
+
+        // Report slide problems under the spreadsheet's column names.
+        let column = |field: &str| -> String {
+            match field {
+                "correct_answers" => "correct".to_string(),
+                "image_path" => "image_url".to_string(),
+                field => match field.strip_prefix("answers[").and_then(|rest| rest.strip_suffix(']')) {
+                    Some(index) => index
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| answers.get(index))
+                        .map_or(field.to_string(), |(column, _)| format!("answer_{}", column)),
+                    None => field.to_string(),
+                },
+            }
+        };
+
+        Ok(match problems {
+            Some(problems) => Err(problems.into_iter().map(|problem| (column(&problem.field), problem.reason)).collect()),
+            None => Ok(slide),
+        })
+    }
+}
+
+fn draft(title: Option<&str>, slides: Vec<Slide>) -> QuizCreation {
+    let title: String = title
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or(DEFAULT_TITLE)
+        .chars()
+        .take(TITLE_MAX_LENGTH)
+        .collect();
+
+    QuizCreation {
+        title,
+        description: None,
+        slides,
+        visibility: Visibility::default(),
+        tags: Vec::new(),
+        subject: None,
+        grade_level: None,
+        language: None,
+    }
+}
+
+fn response_invalid_file(reason: &str) -> HttpResponse {
+    response_unprocessable_entity(json!([{ "field": "file", "reason": reason }]))
+}
+
+/// Builds the draft from an uploaded file.
+async fn import(db: &Database, user_id: UserId, title: Option<&str>, body: web::Bytes) -> HttpResponse {
+    let Sheet { first_row, rows } = match web::block(move || spreadsheet::read(&body)).await {
+        Ok(Ok(sheet)) => sheet,
+        Ok(Err(reason)) => return response_invalid_file(reason),
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let is_blank = |cells: &Vec<String>| cells.iter().all(|value| value.trim().is_empty());
+
+    let mut rows = rows.into_iter().enumerate().map(|(index, cells)| (first_row + index, cells)).filter(|(_, cells)| !is_blank(cells));
+
+    let columns = match rows.next() {
+        Some((_, header)) => Columns::from_header(&header),
+        None => return response_invalid_file("is empty"),
+    };
+
+    if columns.question.is_none() {
+        return response_invalid_file("must have a header row with a question column");
+    }
+
+    if columns.correct.is_none() && columns.correct_flags.iter().all(Option::is_none) {
+        return response_invalid_file("must have a correct column, or correct_1 to correct_4 columns");
+    }
+
+    let rows: Vec<(usize, Vec<String>)> = rows.collect();
+
+    // Downloads run side by side under one deadline before any row is built, instead of one
+    // after another as the rows come.
+    let mut urls: Vec<String> = Vec::new();
+
+    for (_, cells) in &rows {
+        let url = cell(cells, columns.image_url);
+
+        if !url.is_empty() && !url.starts_with(MEDIA_PATH_PREFIX) && !urls.iter().any(|seen| seen == url) && urls.len() < MAX_SLIDES {
+            urls.push(url.to_string());
+        }
+    }
+
+    let fetched = match remote::client() {
+        Ok(client) => remote::fetch_images(&client, urls).await,
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let mut importer = Importer {
+        db,
+        user_id,
+        fetched,
+        images: HashMap::new(),
+    };
+
+    let mut slides = Vec::new();
+    let mut errors = Vec::new();
+    let mut row_count = 0;
+
+    for (row, cells) in rows {
+        row_count += 1;
+
+        if slides.len() >= MAX_SLIDES {
+            errors.push(RowError { row, field: "row".to_string(), reason: format!("is past the limit of {} questions", MAX_SLIDES) });
+            continue;
+        }
+
+        match importer.slide(&columns, &cells).await {
+            Ok(Ok(slide)) => slides.push(slide),
+            Ok(Err(problems)) => {
+                errors.extend(problems.into_iter().map(|(field, reason)| RowError { row, field, reason }));
+            }
+            Err(_) => return response_internal_server_error(),
+        }
+    }
+
+    let imported = slides.len();
+
+    response_ok_builder().json(json!({
+        "quiz": draft(title, slides),
+        "rows": row_count,
+        "imported": imported,
+        "errors": errors,
+    }))
+}
+
+/// `POST` takes the raw CSV or XLSX file as the request body and returns a quiz draft to review
+/// and save through `/api/quiz`, along with the rows that were left out. With a `format` the
+/// body is a Moodle or QTI question file instead. `GET` returns a CSV
+/// template with the expected columns.
+async fn handler(
+    req: HttpRequest,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        match *req.method() {
+            Method::GET => {
+                response_ok_builder()
+                    .content_type("text/csv; charset=utf-8")
+                    .insert_header((CONTENT_DISPOSITION, "attachment; filename=\"quiz-template.csv\""))
+                    .body(TEMPLATE)
+            }
+            Method::POST => match query.format.as_deref() {
+                None | Some("spreadsheet") => import(&db, user_id, query.title.as_deref(), body).await,
+                Some(format) => match Format::parse(format) {
+                    Some(format) => interchange::import(&db, user_id, format, query.title.as_deref(), body).await,
+                    None => response_bad_request(),
+                },
+            },
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource(PATH)
+            .app_data(web::PayloadConfig::new(IMPORT_MAX_BYTES))
+            .route(web::get().to(handler))
+            .route(web::post().to(handler))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(names: &[&str]) -> Columns {
+        Columns::from_header(&names.iter().map(|name| name.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn finds_columns_by_loose_name() {
+        let columns = header(&["Question", "Answer 1", "answer_2", "ANSWER-3", "Correct", "Time Limit", "image_url", "notes", "answer_9"]);
+
+        assert_eq!(columns.question, Some(0));
+        assert_eq!(columns.answers, [Some(1), Some(2), Some(3), None]);
+        assert_eq!(columns.correct, Some(4));
+        assert_eq!(columns.time_limit, Some(5));
+        assert_eq!(columns.image_url, Some(6));
+        assert_eq!(columns.points, None);
+
+        let flags = header(&["question", "answer_1", "answer_2", "correct_1", "correct_2", "question"]);
+
+        assert_eq!(flags.question, Some(0));
+        assert_eq!(flags.correct_flags, [Some(3), Some(4), None, None]);
+    }
+
+    #[test]
+    fn reads_correct_answers_by_number_or_letter() {
+        assert_eq!(parse_correct("2"), Some(vec![2]));
+        assert_eq!(parse_correct("b"), Some(vec![2]));
+        assert_eq!(parse_correct("1, 3"), Some(vec![1, 3]));
+        assert_eq!(parse_correct("A;D"), Some(vec![1, 4]));
+        assert_eq!(parse_correct("5"), None);
+        assert_eq!(parse_correct("E"), None);
+        assert_eq!(parse_correct(""), Some(vec![]));
+    }
+
+    #[test]
+    fn reads_numbers_and_checkmarks() {
+        assert_eq!(parse_number(""), Some(None));
+        assert_eq!(parse_number("20"), Some(Some(20)));
+        assert_eq!(parse_number("20.0"), Some(Some(20)));
+        assert_eq!(parse_number("twenty"), None);
+        assert_eq!(parse_number("-5"), None);
+
+        assert!(is_checked("X") && is_checked("yes") && is_checked("1"));
+        assert!(!is_checked("") && !is_checked("no"));
+    }
+}
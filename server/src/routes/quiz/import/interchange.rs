@@ -0,0 +1,83 @@
+//! Turns a Moodle or QTI question file into a quiz draft. Questions that can't be carried
+//! over, and the parts of others that were dropped, come back as warnings by their position in
+//! the file.
+
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use mongodb::Database;
+
+use crate::env::MEDIA_MAX_BYTES;
+use crate::libraries::media::{is_image, process};
+use crate::libraries::interchange::{self, Format, Image, Imported, Warning};
+use crate::libraries::{response_internal_server_error, response_ok_builder};
+use crate::models::id::UserId;
+use crate::models::media::Media;
+use crate::models::slide::Slide;
+use crate::routes::quiz::validation::{validate_slides, MAX_SLIDES};
+use super::{draft, response_invalid_file};
+
+/// Stores an embedded image as the user's upload, returning its media path or why it was left out.
+async fn store_image(db: &Database, user_id: UserId, image: Image) -> mongodb::error::Result<Result<String, &'static str>> {
+    if image.bytes.len() > *MEDIA_MAX_BYTES {
+        return Ok(Err("is larger than the upload limit"));
+    }
+
+    if !is_image(&image.bytes) {
+        return Ok(Err("is not a JPEG, PNG, GIF or WebP image"));
+    }
+
+    let processed = match web::block(move || process(&image.bytes)).await {
+        Ok(Some(processed)) => processed,
+        Ok(None) => return Ok(Err("is not a JPEG, PNG, GIF or WebP image")),
+        Err(_) => return Ok(Err("could not be processed")),
+    };
+
+    match Media::store_image(db, user_id, &processed).await? {
+        Some(media) => Ok(Ok(media.path())),
+        None => Err(mongodb::error::Error::custom("Media not stored")),
+    }
+}
+
+pub async fn import(db: &Database, user_id: UserId, format: Format, title: Option<&str>, body: web::Bytes) -> HttpResponse {
+    let Imported { count, mut slides, positions, images, mut warnings } = match web::block(move || interchange::import(format, &body)).await {
+        Ok(Ok(imported)) => imported,
+        Ok(Err(reason)) => return response_invalid_file(reason),
+        Err(_) => return response_internal_server_error(),
+    };
+
+    for (index, image) in images {
+        match store_image(db, user_id, image).await {
+            Ok(Ok(path)) => slides[index].set_image_path(path),
+            Ok(Err(reason)) => warnings.push(Warning { index: positions[index], message: format!("the image {}", reason) }),
+            Err(_) => return response_internal_server_error(),
+        }
+    }
+
+    let mut kept: Vec<Slide> = Vec::new();
+
+    for (slide, index) in slides.into_iter().zip(positions) {
+        if kept.len() >= MAX_SLIDES {
+            warnings.push(Warning { index, message: format!("is past the limit of {} questions", MAX_SLIDES) });
+            continue;
+        }
+
+        match validate_slides(std::slice::from_ref(&slide)) {
+            Ok(()) => kept.push(slide),
+            Err(problems) => warnings.extend(problems.into_iter().map(|problem| Warning {
+                index,
+                message: format!("left out: {} {}", problem.field, problem.reason),
+            })),
+        }
+    }
+
+    warnings.sort_by_key(|warning| warning.index);
+
+    let imported = kept.len();
+
+    response_ok_builder().json(json!({
+        "quiz": draft(title, kept),
+        "questions": count,
+        "imported": imported,
+        "warnings": warnings,
+    }))
+}
@@ -0,0 +1,258 @@
+//! Downloads spreadsheet `image_url`s. They point anywhere on the web, so requests only ever go
+//! to public addresses: names are resolved here, refused if any address they resolve to is
+//! internal, and the connection is made to exactly the addresses that were checked. Redirects go
+//! through the same checks.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::web;
+use futures::StreamExt;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tokio::time::Instant;
+
+use crate::env::MEDIA_MAX_BYTES;
+use crate::libraries::media::{is_image, process, ProcessedImage};
+
+/// Downloading one image must not hold up the import for long.
+const IMAGE_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// All of an import's downloads together get this long.
+const IMPORT_FETCH_DEADLINE: Duration = Duration::from_secs(30);
+
+const FETCH_CONCURRENCY: usize = 8;
+
+const MAX_REDIRECTS: usize = 3;
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // 0.0.0.0/8 reaches the local host on some systems.
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10.
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments, 192.0.0.0/24.
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        // Benchmarking, 198.18.0.0/15.
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved, 240.0.0.0/4.
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let [first, second, ..] = ip.segments();
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7.
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10, and the old site-local, fec0::/10.
+        || (first & 0xffc0) == 0xfe80
+        || (first & 0xffc0) == 0xfec0
+        // Addresses that embed an IPv4 one: mapped ::ffff:0:0/96, compatible ::/96, NAT64
+        // 64:ff9b::/96 and 6to4 2002::/16. Whatever IPv4 address they carry, they aren't needed
+        // to reach a public image host.
+        || ip.to_ipv4().is_some()
+        || (first == 0x64 && second == 0xff9b)
+        || first == 0x2002
+        // Documentation, 2001:db8::/32.
+        || (first == 0x2001 && second == 0x0db8))
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+/// The checks that can be made on the URL alone: the scheme, and the host when it is an address
+/// or a local name. Hosts given by name are checked again once resolved, by `PublicResolver`.
+pub fn is_public_host(url: &reqwest::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return false,
+    };
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+            host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+/// Resolves names for the download client and fails any name that resolves to a non-public
+/// address. reqwest connects to the addresses returned here, so the check can't be undone by the
+/// name resolving differently a moment later.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+
+            if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+                return Err(format!("{} does not resolve to a public address", name.as_str()).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub fn client() -> reqwest::Result<reqwest::Client> {
+    let redirects = reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS || !is_public_host(attempt.url()) {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    });
+
+    reqwest::Client::builder()
+        .timeout(IMAGE_FETCH_TIMEOUT)
+        .redirect(redirects)
+        .dns_resolver(Arc::new(PublicResolver))
+        // A proxy would resolve the name itself, past the resolver's checks.
+        .no_proxy()
+        .build()
+}
+
+async fn fetch_image(client: &reqwest::Client, url: &str) -> Result<ProcessedImage, &'static str> {
+    let url = match reqwest::Url::parse(url) {
+        Ok(url) if is_public_host(&url) => url,
+        _ => return Err("must be a public http(s) URL or an uploaded image"),
+    };
+
+    let mut response = match client.get(url).send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return Err("could not be downloaded"),
+    };
+
+    let mut bytes = Vec::new();
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if bytes.len() + chunk.len() > *MEDIA_MAX_BYTES {
+                    return Err("is larger than the upload limit");
+                }
+
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(_) => return Err("could not be downloaded"),
+        }
+    }
+
+    if !is_image(&bytes) {
+        return Err("is not a JPEG, PNG, GIF or WebP image");
+    }
+
+    match web::block(move || process(&bytes)).await {
+        Ok(Some(image)) => Ok(image),
+        Ok(None) => Err("is not a JPEG, PNG, GIF or WebP image"),
+        Err(_) => Err("could not be processed"),
+    }
+}
+
+/// Downloads `urls` a few at a time, by URL. Whatever hasn't finished by the import's deadline is
+/// reported as such rather than holding up the response.
+pub async fn fetch_images(client: &reqwest::Client, urls: Vec<String>) -> HashMap<String, Result<ProcessedImage, &'static str>> {
+    let deadline = Instant::now() + IMPORT_FETCH_DEADLINE;
+    let mut results = HashMap::new();
+
+    let mut fetches = futures::stream::iter(urls.iter().cloned())
+        .map(|url| async move {
+            let image = fetch_image(client, &url).await;
+
+            (url, image)
+        })
+        .buffer_unordered(FETCH_CONCURRENCY);
+
+    while let Ok(Some((url, image))) = tokio::time::timeout_at(deadline, fetches.next()).await {
+        results.insert(url, image);
+    }
+
+    drop(fetches);
+
+    for url in urls {
+        results.entry(url).or_insert(Err("took too long to download"));
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(url: &str) -> bool {
+        is_public_host(&reqwest::Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn allows_public_hosts() {
+        assert!(public("https://example.com/cat.png"));
+        assert!(public("http://93.184.215.14/cat.png"));
+        assert!(public("https://[2606:2800:21f:cb07:6820:80da:af6b:8b2c]/cat.png"));
+    }
+
+    #[test]
+    fn refuses_internal_hosts() {
+        for url in [
+            "http://localhost/",
+            "http://LOCALHOST./",
+            "http://admin.localhost/",
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://172.16.0.1/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://0.1.2.3/",
+            "http://[::1]/",
+            "http://[::]/",
+            "http://[fc00::1]/",
+            "http://[fd12:3456::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[::ffff:169.254.169.254]/",
+            "http://[64:ff9b::a00:1]/",
+            "http://[2002:a00:1::]/",
+            // Decimal and hex IPv4 forms are normalised by the URL parser before the check.
+            "http://2130706433/",
+            "http://0x7f000001/",
+            "ftp://example.com/cat.png",
+            "file:///etc/passwd",
+        ] {
+            assert!(!public(url), "{} should be refused", url);
+        }
+    }
+
+    #[test]
+    fn refuses_internal_resolved_addresses() {
+        assert!(!is_public_ip("127.0.0.53".parse().unwrap()));
+        assert!(!is_public_ip("100.127.255.255".parse().unwrap()));
+        assert!(!is_public_ip("fe80::1".parse().unwrap()));
+        assert!(is_public_ip("100.128.0.1".parse().unwrap()));
+        assert!(is_public_ip("8.8.8.8".parse().unwrap()));
+    }
+}
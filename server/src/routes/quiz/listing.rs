@@ -0,0 +1,203 @@
+use actix_web::HttpResponse;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use mongodb::bson::{doc, Bson, DateTime, Document, Regex};
+use mongodb::Database;
+
+use crate::libraries::{response_bad_request, response_internal_server_error, response_ok_builder};
+use crate::models::id::{FolderId, QuizId, UserId};
+use crate::models::quiz::{Quiz, QuizSummary};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SortField {
+    UpdatedAt,
+    CreatedAt,
+    Title,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    sort: Option<SortField>,
+    order: Option<SortOrder>,
+    q: Option<String>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    /// A folder id, or `root` for quizzes outside any folder. Omitted lists everything.
+    folder_id: Option<String>,
+}
+
+/// Position of the last quiz on a page: its sort key plus `_id` to break ties. Handed to the
+/// client hex-encoded so it stays opaque.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    key: Value,
+    id: QuizId,
+}
+
+impl SortField {
+    fn name(self) -> &'static str {
+        match self {
+            SortField::UpdatedAt => "updated_at",
+            SortField::CreatedAt => "created_at",
+            SortField::Title => "title",
+        }
+    }
+
+    fn key_of(self, quiz: &QuizSummary) -> Value {
+        match self {
+            SortField::UpdatedAt => json!(quiz.updated_at.timestamp_millis()),
+            SortField::CreatedAt => json!(quiz.created_at.timestamp_millis()),
+            SortField::Title => json!(quiz.title),
+        }
+    }
+
+    fn key_to_bson(self, key: &Value) -> Option<Bson> {
+        match self {
+            SortField::UpdatedAt | SortField::CreatedAt => {
+                key.as_i64().map(|millis| Bson::DateTime(DateTime::from_millis(millis)))
+            }
+            SortField::Title => key.as_str().map(|title| Bson::String(title.to_string())),
+        }
+    }
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        serde_json::to_vec(self)
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        if !value.len().is_multiple_of(2) {
+            return None;
+        }
+
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for character in value.chars() {
+        if "\\.^$|?*+()[]{}-/".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+
+    escaped
+}
+
+/// `GET /api/quiz`: the user's own and shared quizzes, one page at a time, as summaries.
+pub async fn list_quizzes(db: &Database, user_id: UserId, query: &ListQuery) -> HttpResponse {
+    let sort = query.sort.unwrap_or(SortField::UpdatedAt);
+    let order = query.order.unwrap_or(match sort {
+        SortField::Title => SortOrder::Asc,
+        SortField::UpdatedAt | SortField::CreatedAt => SortOrder::Desc,
+    });
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut conditions = vec![
+        Quiz::access_filter(user_id),
+        doc! { "is_deleted": { "$ne": true } },
+    ];
+
+    match query.folder_id.as_deref() {
+        Some("root") => conditions.push(doc! { "owner_id": user_id, "folder_id": null }),
+        Some(folder_id) => match FolderId::parse(folder_id) {
+            Some(folder_id) => conditions.push(doc! { "owner_id": user_id, "folder_id": folder_id }),
+            None => return response_bad_request(),
+        },
+        None => {}
+    }
+
+    if let Some(text) = query.q.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+        conditions.push(doc! {
+            "title": Regex {
+                pattern: escape_regex(text),
+                options: "i".to_string(),
+            }
+        });
+    }
+
+    let (direction, operator) = match order {
+        SortOrder::Asc => (1, "$gt"),
+        SortOrder::Desc => (-1, "$lt"),
+    };
+
+    if let Some(cursor) = &query.cursor {
+        let cursor = match Cursor::decode(cursor) {
+            Some(cursor) => cursor,
+            None => return response_bad_request(),
+        };
+
+        let key = match sort.key_to_bson(&cursor.key) {
+            Some(key) => key,
+            None => return response_bad_request(),
+        };
+
+        conditions.push(doc! {
+            "$or": [
+                { sort.name(): { operator: key.clone() } },
+                { sort.name(): key, "_id": { operator: cursor.id } },
+            ]
+        });
+    }
+
+    let filter: Document = doc! { "$and": conditions };
+
+    let cursor = QuizSummary::collection(db)
+        .find(filter)
+        .projection(QuizSummary::projection())
+        .sort(doc! { sort.name(): direction, "_id": direction })
+        .limit(limit + 1)
+        .await;
+
+    let mut quizzes: Vec<QuizSummary> = match cursor {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(quizzes) => quizzes,
+            Err(_) => return response_internal_server_error(),
+        },
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let next_cursor = if quizzes.len() as i64 > limit {
+        quizzes.truncate(limit as usize);
+
+        quizzes.last().map(|quiz| Cursor {
+            key: sort.key_of(quiz),
+            id: quiz.id,
+        }.encode())
+    } else {
+        None
+    };
+
+    let quizzes: Vec<Value> = quizzes.iter().map(|quiz| quiz.to_json_for(user_id)).collect();
+
+    response_ok_builder().json(json!({
+        "quizzes": quizzes,
+        "next_cursor": next_cursor,
+    }))
+}
@@ -1,14 +1,31 @@
-use std::collections::HashMap;
-use actix_web::{web, HttpRequest, Responder, http::Method, HttpMessage};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, http::Method};
+use actix_web::http::header::{ETAG, IF_MATCH};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use mongodb::bson::{doc, DateTime, oid::ObjectId, to_document, Document};
+use serde_json::{json, Value};
+use mongodb::bson::{doc, to_bson, DateTime};
+use mongodb::options::ReturnDocument;
 use mongodb::Database;
 use rand::Rng;
-
-use crate::libraries::{method_not_allowed, response_bad_request, response_internal_server_error, response_not_found, response_ok_builder};
-use crate::libraries::redis::with_transaction;
+use sha2::{Digest, Sha256};
+
+use crate::libraries::{method_not_allowed, response_bad_request, response_forbidden, response_internal_server_error, response_not_found, response_ok_builder, response_precondition_required, response_unprocessable_entity};
+use crate::models::bank::BankItem;
+use crate::models::id::{QuizId, UserId};
+use crate::models::media::RevealVariants;
+use crate::models::quiz::{Permission, Quiz};
+use crate::models::revision::QuizRevision;
+use crate::models::room::{QuizSnapshot, Room, RoomSettings};
 use crate::routes::quiz::QuizCreation;
+use crate::routes::quiz::validation::{validate_quiz, validate_quiz_media};
+use edit::publish_refresh;
+
+mod collaborators;
+mod duplicate;
+mod edit;
+mod export;
+mod folder;
+mod revisions;
+mod slides;
 
 pub const PATH: &str = "/api/quiz/{quiz_id}";
 
@@ -17,19 +34,190 @@ struct Request {
     quiz_id: String,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct QuizRoom {
-    quiz_id: String,
-    owner_id: String,
-    room_code: String,
-    created_at: i64,
-    players: HashMap<String, String>,
-    current_slide: i32,
-    scores: HashMap<String, i64>,
-    started: bool,
+/// Loads a quiz the user can see, then checks that their role on it (or its visibility) grants
+/// `permission`.
+async fn find_quiz(db: &Database, quiz_id: QuizId, user_id: UserId, permission: Permission) -> Result<Quiz, HttpResponse> {
+    let mut filter = Quiz::reachable_filter(user_id);
+    filter.insert("_id", quiz_id);
+    filter.insert("is_deleted", doc! { "$ne": true });
+
+    let quiz = match Quiz::collection(db).find_one(filter).await {
+        Ok(Some(quiz)) => quiz,
+        Ok(None) => return Err(response_not_found()),
+        Err(_) => return Err(response_internal_server_error()),
+    };
+
+    if quiz.allows(user_id, permission) {
+        Ok(quiz)
+    } else {
+        Err(response_forbidden())
+    }
+}
+
+/// The revision counter is the quiz's version, so it doubles as its ETag.
+fn etag(revision: i64) -> String {
+    format!("\"{}\"", revision)
+}
+
+/// The revision the client last saw, from `If-Match` (`"12"` or `W/"12"`).
+fn if_match(req: &HttpRequest) -> Option<i64> {
+    let value = req.headers().get(IF_MATCH)?.to_str().ok()?.trim();
+    let value = value.strip_prefix("W/").unwrap_or(value);
+
+    value.strip_prefix('"')?.strip_suffix('"')?.parse().ok()
+}
+
+/// 412 carrying the current version, so the editor can show what changed and retry on top of it.
+fn response_precondition_failed(quiz: &Quiz, user_id: UserId) -> HttpResponse {
+    HttpResponse::PreconditionFailed()
+        .insert_header((ETAG, etag(quiz.revision)))
+        .json(json!({
+            "message": "Precondition failed.",
+            "revision": quiz.revision,
+            "quiz": quiz.to_json_for(user_id),
+        }))
+}
+
+/// The quiz for an edit that must be based on its current revision: `If-Match` is required
+/// (428 without it) and must name the current revision (412 with the current version if not).
+async fn find_quiz_at(req: &HttpRequest, db: &Database, quiz_id: QuizId, user_id: UserId) -> Result<Quiz, HttpResponse> {
+    let expected_revision = match if_match(req) {
+        Some(revision) => revision,
+        None => return Err(response_precondition_required()),
+    };
+
+    let quiz = find_quiz(db, quiz_id, user_id, Permission::Edit).await?;
+
+    if quiz.revision != expected_revision {
+        return Err(response_precondition_failed(&quiz, user_id));
+    }
+
+    Ok(quiz)
+}
+
+/// Replaces `quiz` with `body` as its next revision, provided nobody saved it since it was read.
+async fn save_quiz(db: &Database, quiz: &Quiz, user_id: UserId, body: &QuizCreation) -> HttpResponse {
+    let quiz_id = quiz.id;
+    let expected_revision = quiz.revision;
+
+    if quiz.visibility != body.visibility && !quiz.allows(user_id, Permission::Manage) {
+        return response_forbidden();
+    }
+
+    match validate_quiz_media(db, quiz, &body.slides, user_id).await {
+        Ok(Ok(())) => {}
+        Ok(Err(errors)) => return response_unprocessable_entity(errors),
+        Err(_) => return response_internal_server_error(),
+    }
+
+    let slides = match to_bson(&body.slides) {
+        Ok(slides) => slides,
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let mut session = match db.client().start_session().await {
+        Ok(session) => session,
+        Err(_) => return response_internal_server_error(),
+    };
+
+    let updated_at = DateTime::now();
+
+    let result: mongodb::error::Result<Option<Quiz>> = async {
+        session.start_transaction().await?;
+
+        // Matching on the revision closes the gap between reading the quiz and this write: a
+        // concurrent save makes it match nothing.
+        let quiz = match Quiz::collection(db).find_one_and_update(
+                doc! {
+                    "_id": quiz_id,
+                    "revision": expected_revision,
+                    "is_deleted": { "$ne": true },
+                },
+                doc! {
+                    "$set": {
+                        "title": body.title.clone(),
+                        "description": body.description.clone(),
+                        "slides": slides,
+                        "visibility": to_bson(&body.visibility)?,
+                        "tags": body.tags(),
+                        "subject": body.subject.clone(),
+                        "grade_level": body.grade_level.clone(),
+                        "language": body.language.clone(),
+                        "updated_at": updated_at,
+                    },
+                    "$inc": { "revision": 1 },
+                },
+            )
+            .return_document(ReturnDocument::After)
+            .session(&mut session)
+            .await?
+        {
+            Some(quiz) => quiz,
+            None => {
+                session.abort_transaction().await?;
+
+                return Ok(None);
+            }
+        };
+
+        QuizRevision::record(db, &mut session, &quiz, user_id, None).await?;
+
+        session.commit_transaction().await?;
+
+        Ok(Some(quiz))
+    }.await;
+
+    match result {
+        Ok(None) => match find_quiz(db, quiz_id, user_id, Permission::View).await {
+            Ok(quiz) => response_precondition_failed(&quiz, user_id),
+            Err(response) => response,
+        },
+        Ok(Some(quiz)) => {
+            publish_refresh(&quiz, user_id);
+
+            response_ok_builder()
+                .insert_header((ETAG, etag(quiz.revision)))
+                .json(json!({
+                    "quiz_id": quiz_id.to_string(),
+                    "revision": quiz.revision,
+                    "updated_at": updated_at,
+                }))
+        }
+        Err(_) => {
+            let _ = session.abort_transaction().await;
+
+            response_internal_server_error()
+        }
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7396) to `target`: objects merge key by key, `null` removes a
+/// key and anything else replaces what was there.
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        patch => {
+            *target = patch.clone();
+            return;
+        }
+    };
+
+    if !target.is_object() {
+        *target = json!({});
+    }
+
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
 }
 
-pub(self) async fn handler(
+async fn handler(
     path: web::Path<Request>,
     req: HttpRequest,
     body: Option<web::Json<QuizCreation>>,
@@ -39,115 +227,72 @@ pub(self) async fn handler(
         return response_error;
     }
 
-    if let Some(user_data) = req.extensions().get::<crate::middlewares::jwt::RequestUser>() {
-        let owner_id = ObjectId::parse_str(&user_data.user.user_id).unwrap();
-        let quiz_id = &path.quiz_id[2..].to_string();
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
 
         match *req.method() {
             Method::GET => {
-                let quiz: Document = match db.collection("quizzes").find_one(doc! {
-                    "_id": ObjectId::parse_str(quiz_id.clone()).unwrap(),
-                    "owner_id": owner_id.clone(),
-                    "is_deleted": { "$ne": true }
-                }).projection(doc! { "_id": 0 }).await {
-                    Ok(Some(doc)) => doc,
-                    Ok(None) => return response_not_found(),
-                    Err(_) => return response_internal_server_error(),
+                let quiz = match find_quiz(&db, quiz_id, user_id, Permission::View).await {
+                    Ok(quiz) => quiz,
+                    Err(response) => return response,
                 };
 
-                response_ok_builder().json(json!({
-                    "quiz_id": path.quiz_id.clone(),
-                    "title": quiz.get("title").unwrap(),
-                    "description": quiz.get("description").unwrap_or(&mongodb::bson::Bson::Null),
-                    "slides": quiz.get("slides").unwrap(),
-                    "updated_at": quiz.get("updated_at").unwrap_or(&mongodb::bson::Bson::Null),
-                    "created_at": quiz.get("created_at").unwrap(),
-                }))
+                response_ok_builder()
+                    .insert_header((ETAG, etag(quiz.revision)))
+                    .json(quiz.to_json_for(user_id))
             }
-            Method::POST => {
-                let quiz_exists = match db.collection::<Document>("quizzes").find_one(doc! {
-                    "_id": ObjectId::parse_str(quiz_id.clone()).unwrap(),
-                    "owner_id": owner_id.clone(),
-                    "is_deleted": { "$ne": true }
-                }).await {
-                    Ok(Some(_)) => true,
-                    Ok(None) => return response_not_found(),
-                    Err(_) => return response_internal_server_error(),
+            Method::PUT => {
+                let body = match body.ok_or_else(response_bad_request) {
+                    Ok(body) => body,
+                    Err(response) => return response,
                 };
 
-                if !quiz_exists {
-                    return response_not_found();
+                if let Err(errors) = validate_quiz(&body) {
+                    return response_unprocessable_entity(errors);
                 }
 
-                let room_code: String = rand::rng()
-                    .random_range(10000000..99999999)
-                    .to_string();
-
-                let room_key = format!("quiz_room:{}", room_code);
-
-                let created_at = DateTime::now();
-
-                let room = QuizRoom {
-                    quiz_id: quiz_id.to_string(),
-                    owner_id: user_data.user.user_id[2..].to_string(),
-                    room_code: room_code.clone(),
-                    created_at: created_at.clone().timestamp_millis(),
-                    players: HashMap::new(),
-                    current_slide: 0,
-                    scores: HashMap::new(),
-                    started: false,
+                let quiz = match find_quiz_at(&req, &db, quiz_id, user_id).await {
+                    Ok(quiz) => quiz,
+                    Err(response) => return response,
                 };
 
-                let result: redis::RedisResult<()> = with_transaction(|pipe| {
-                    let serialized = serde_json::to_string(&room).unwrap();
-                    pipe.set_ex(&room_key, serialized, 3600);
-                    Ok(())
-                });
-
-                match result {
-                    Ok(()) => {
-                        response_ok_builder().json(json!({
-                            "quiz_id": format!("0x{}", quiz_id),
-                            "room_code": room_code,
-                            "created_at": created_at.clone(),
-                        }))
-                    }
-                    Err(_) => response_internal_server_error(),
-                }
+                save_quiz(&db, &quiz, user_id, &body).await
             }
-            Method::PUT => {
+            Method::DELETE => {
+                if let Err(response) = find_quiz(&db, quiz_id, user_id, Permission::Manage).await {
+                    return response;
+                }
+
                 let mut session = match db.client().start_session().await {
                     Ok(session) => session,
                     Err(_) => return response_internal_server_error(),
                 };
 
-                let updated_at = DateTime::now();
+                let deleted_at = DateTime::now();
 
                 let result = async {
                     session.start_transaction().await?;
 
-                    let body = body.ok_or_else(response_bad_request).unwrap();
-
-                    let update_result = db.collection::<Document>("quizzes").update_one(
+                    let update_result = Quiz::collection(&db).update_one(
                             doc! {
-                                "_id": ObjectId::parse_str(quiz_id).unwrap(),
-                                "owner_id": owner_id.clone(),
+                                "_id": quiz_id,
                                 "is_deleted": { "$ne": true },
                             },
                             doc! {
                                 "$set": {
-                                    "title": body.title.clone(),
-                                    "description": body.description.clone(),
-                                    "slides": to_document(&body.slides).unwrap(),
-                                    "updated_at": updated_at.clone(),
+                                    "is_deleted": true,
+                                    "deleted_at": deleted_at,
                                 }
-                            },
+                            }
                         )
                         .session(&mut session)
                         .await?;
 
                     if update_result.matched_count == 0 {
-                        return Err(mongodb::error::Error::custom("Quiz not found or not owned by user"));
+                        return Err(mongodb::error::Error::custom("Quiz not found"));
                     }
 
                     session.commit_transaction().await?;
@@ -158,8 +303,8 @@ pub(self) async fn handler(
                 match result {
                     Ok(()) => {
                         response_ok_builder().json(json!({
-                            "quiz_id": format!("0x{}", quiz_id),
-                            "updated_at": updated_at,
+                            "quiz_id": quiz_id.to_string(),
+                            "deleted_at": deleted_at,
                         }))
                     }
                     Err(_) => {
@@ -169,58 +314,145 @@ pub(self) async fn handler(
                     }
                 }
             }
-            Method::DELETE => {
-                let mut session = match db.client().start_session().await {
-                    Ok(session) => session,
-                    Err(_) => return response_internal_server_error(),
-                };
+            _ => method_not_allowed()
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
 
-                let deleted_at = DateTime::now();
+/// Opens a room for the quiz. Only this route takes the room settings.
+async fn host_handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    settings: web::Query<RoomSettings>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
 
-                let result = async {
-                    session.start_transaction().await?;
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
 
-                    let update_result = db.collection::<Document>("quizzes").update_one(
-                            doc! {
-                                "_id": ObjectId::parse_str(quiz_id).unwrap(),
-                                "owner_id": owner_id.clone(),
-                                "is_deleted": { "$ne": true },
-                            },
-                            doc! {
-                                "$set": {
-                                    "is_deleted": true,
-                                    "deleted_at": deleted_at.clone(),
-                                }
-                            }
-                        )
-                        .session(&mut session)
-                        .await?;
+        let mut quiz = match find_quiz(&db, quiz_id, user_id, Permission::Host).await {
+            Ok(quiz) => quiz,
+            Err(response) => return response,
+        };
 
-                    if update_result.matched_count == 0 {
-                        return Err(mongodb::error::Error::custom("Quiz not found or not owned by user"));
-                    }
+        // Bank references and random draws are filled in from the owner's bank per room, so each
+        // game of the same quiz can get a different draw. Hosts who only have the link get the
+        // same draw every time for a given revision.
+        let draw_seed = quiz.role_of(user_id).is_none().then(|| {
+            let digest = Sha256::digest(format!("{}:{}", quiz.id, quiz.revision));
 
-                    session.commit_transaction().await?;
+            u64::from_be_bytes(digest[..8].try_into().unwrap_or_default())
+        });
 
-                    Ok(())
-                }.await;
+        quiz.slides = match BankItem::resolve_slides(&db, quiz.owner_id, &quiz.slides, draw_seed).await {
+            Ok(slides) => slides,
+            Err(_) => return response_internal_server_error(),
+        };
 
-                match result {
-                    Ok(()) => {
-                        response_ok_builder().json(json!({
-                            "quiz_id": format!("0x{}", quiz_id),
-                            "deleted_at": deleted_at,
-                        }))
-                    }
-                    Err(_) => {
-                        let _ = session.abort_transaction().await;
+        let reveals = match RevealVariants::for_slides(&db, &quiz.slides).await {
+            Ok(reveals) => reveals,
+            Err(_) => return response_internal_server_error(),
+        };
 
-                        response_internal_server_error()
-                    }
-                }
+        let snapshot = QuizSnapshot::from_quiz(&quiz, reveals);
+
+        let room_code: String = rand::rng()
+            .random_range(10000000..99999999)
+            .to_string();
+
+        let created_at = DateTime::now();
+
+        let room = Room::new(&snapshot, user_id, room_code.clone(), settings.into_inner(), created_at.timestamp_millis());
+
+        if room.create(&snapshot).is_err() {
+            return response_internal_server_error();
+        }
+
+        match Quiz::collection(&db).update_one(
+            doc! { "_id": quiz_id },
+            doc! { "$inc": { "play_count": 1 } },
+        ).await {
+            Ok(_) => {
+                response_ok_builder().json(json!({
+                    "quiz_id": quiz_id.to_string(),
+                    "quiz_revision": snapshot.revision,
+                    "room_code": room_code,
+                    "settings": room.settings,
+                    "created_at": created_at,
+                }))
             }
-            _ => method_not_allowed()
+            Err(_) => response_internal_server_error(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+/// `PATCH /api/quiz/{quiz_id}` with a JSON merge patch of the quiz (`title`, `description`,
+/// `slides`, `visibility`, `tags`, ...). Arrays such as `slides` are replaced whole; the slide
+/// endpoints edit single slides. Same `If-Match` rules as PUT.
+async fn patch_handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    patch: Option<web::Json<Value>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        let patch = match patch {
+            Some(patch) if patch.is_object() => patch.into_inner(),
+            _ => return response_bad_request(),
+        };
+
+        let quiz = match find_quiz_at(&req, &db, quiz_id, user_id).await {
+            Ok(quiz) => quiz,
+            Err(response) => return response,
+        };
+
+        let current = QuizCreation {
+            title: quiz.title.clone(),
+            description: quiz.description.clone(),
+            slides: quiz.slides.clone(),
+            visibility: quiz.visibility,
+            tags: quiz.tags.clone(),
+            subject: quiz.subject.clone(),
+            grade_level: quiz.grade_level.clone(),
+            language: quiz.language.clone(),
+        };
+
+        let mut value = match serde_json::to_value(&current) {
+            Ok(value) => value,
+            Err(_) => return response_internal_server_error(),
+        };
+
+        merge_patch(&mut value, &patch);
+
+        let body: QuizCreation = match serde_json::from_value(value) {
+            Ok(body) => body,
+            Err(_) => return response_bad_request(),
+        };
+
+        if let Err(errors) = validate_quiz(&body) {
+            return response_unprocessable_entity(errors);
         }
+
+        save_quiz(&db, &quiz, user_id, &body).await
     } else {
         response_internal_server_error()
     }
@@ -230,8 +462,56 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource(PATH)
             .route(web::get().to(handler))
-            .route(web::post().to(handler))
+            .route(web::post().to(host_handler))
             .route(web::put().to(handler))
+            .route(web::patch().to(patch_handler))
             .route(web::delete().to(handler))
     );
-}
\ No newline at end of file
+    cfg.configure(collaborators::configure);
+    cfg.configure(duplicate::configure);
+    cfg.configure(edit::configure);
+    cfg.configure(export::configure);
+    cfg.configure(folder::configure);
+    cfg.configure(revisions::configure);
+    cfg.configure(slides::configure);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::merge_patch;
+
+    #[test]
+    fn merge_patch_follows_rfc_7396() {
+        let mut quiz = json!({
+            "title": "Old",
+            "description": "Kept",
+            "subject": "Maths",
+            "tags": ["a", "b"],
+            "slides": [{ "question": "1" }, { "question": "2" }],
+        });
+
+        merge_patch(&mut quiz, &json!({
+            "title": "New",
+            "subject": null,
+            "tags": ["c"],
+        }));
+
+        assert_eq!(quiz, json!({
+            "title": "New",
+            "description": "Kept",
+            "tags": ["c"],
+            "slides": [{ "question": "1" }, { "question": "2" }],
+        }));
+    }
+
+    #[test]
+    fn merge_patch_merges_nested_objects() {
+        let mut value = json!({ "settings": { "a": 1, "b": 2 }, "scalar": 1 });
+
+        merge_patch(&mut value, &json!({ "settings": { "b": null, "c": 3 }, "scalar": { "now": "object" } }));
+
+        assert_eq!(value, json!({ "settings": { "a": 1, "c": 3 }, "scalar": { "now": "object" } }));
+    }
+}
@@ -0,0 +1,250 @@
+use actix_web::{http::Method, web, HttpRequest, Responder};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use mongodb::bson::{doc, to_bson, DateTime};
+use mongodb::Database;
+
+use crate::libraries::{method_not_allowed, response_bad_request, response_forbidden, response_internal_server_error, response_not_found, response_ok_builder};
+use crate::models::id::{QuizId, UserId};
+use crate::models::quiz::{Collaborator, Permission, Quiz, Role};
+use crate::models::user::User;
+use super::find_quiz;
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/collaborators";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+#[derive(Deserialize)]
+struct CollaboratorRequest {
+    quiz_id: String,
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct Invitation {
+    email: String,
+    role: Role,
+}
+
+#[derive(Deserialize)]
+struct RoleUpdate {
+    role: Role,
+}
+
+async fn save_collaborators(db: &Database, quiz: &Quiz) -> Result<(), mongodb::error::Error> {
+    Quiz::collection(db).update_one(
+        doc! { "_id": quiz.id },
+        doc! { "$set": { "collaborators": to_bson(&quiz.collaborators)? } },
+    ).await.map(|_| ())
+}
+
+async fn list_handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    body: Option<web::Json<Invitation>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        match *req.method() {
+            Method::GET => {
+                let quiz = match find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+                    Ok(quiz) => quiz,
+                    Err(response) => return response,
+                };
+
+                let mut user_ids = vec![quiz.owner_id];
+                user_ids.extend(quiz.collaborators.iter().map(|collaborator| collaborator.user_id));
+
+                let cursor = match User::collection(&db).find(doc! { "_id": { "$in": user_ids } }).await {
+                    Ok(cursor) => cursor,
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                let users: Vec<User> = match cursor.try_collect().await {
+                    Ok(users) => users,
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                let describe = |id: UserId, role: Role| -> Value {
+                    let user = users.iter().find(|user| user.id == id);
+
+                    json!({
+                        "user_id": id.to_string(),
+                        "name": user.map(|user| user.name.clone()),
+                        "email": user.map(|user| user.email.clone()),
+                        "role": role,
+                    })
+                };
+
+                let mut collaborators = vec![describe(quiz.owner_id, Role::Owner)];
+                collaborators.extend(quiz.collaborators.iter().map(|collaborator| {
+                    describe(collaborator.user_id, collaborator.role)
+                }));
+
+                response_ok_builder().json(json!({
+                    "quiz_id": quiz_id.to_string(),
+                    "collaborators": collaborators,
+                }))
+            }
+            Method::POST => {
+                let invitation = match body.ok_or_else(response_bad_request) {
+                    Ok(invitation) => invitation,
+                    Err(response) => return response,
+                };
+
+                if invitation.role == Role::Owner {
+                    return response_bad_request();
+                }
+
+                let mut quiz = match find_quiz(&db, quiz_id, user_id, Permission::Manage).await {
+                    Ok(quiz) => quiz,
+                    Err(response) => return response,
+                };
+
+                let invitee = match User::collection(&db).find_one(doc! {
+                    "email": invitation.email.trim(),
+                }).await {
+                    Ok(Some(user)) => user,
+                    Ok(None) => return response_not_found(),
+                    Err(_) => return response_internal_server_error(),
+                };
+
+                if invitee.id == quiz.owner_id {
+                    return response_bad_request();
+                }
+
+                match quiz.collaborators.iter_mut().find(|collaborator| collaborator.user_id == invitee.id) {
+                    Some(collaborator) => collaborator.role = invitation.role,
+                    None => quiz.collaborators.push(Collaborator {
+                        user_id: invitee.id,
+                        role: invitation.role,
+                        invited_by: user_id,
+                        invited_at: DateTime::now(),
+                    }),
+                }
+
+                match save_collaborators(&db, &quiz).await {
+                    Ok(()) => {
+                        response_ok_builder().json(json!({
+                            "quiz_id": quiz_id.to_string(),
+                            "user_id": invitee.id.to_string(),
+                            "name": invitee.name,
+                            "email": invitee.email,
+                            "role": invitation.role,
+                        }))
+                    }
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+async fn collaborator_handler(
+    path: web::Path<CollaboratorRequest>,
+    req: HttpRequest,
+    body: Option<web::Json<RoleUpdate>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let (quiz_id, collaborator_id) = match (QuizId::parse(&path.quiz_id), UserId::parse(&path.user_id)) {
+            (Some(quiz_id), Some(collaborator_id)) => (quiz_id, collaborator_id),
+            _ => return response_not_found(),
+        };
+
+        let mut quiz = match find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+            Ok(quiz) => quiz,
+            Err(response) => return response,
+        };
+
+        let is_owner = quiz.role_of(user_id).is_some_and(|role| role.allows(Permission::Manage));
+
+        let position = match quiz.collaborators.iter().position(|collaborator| collaborator.user_id == collaborator_id) {
+            Some(position) => position,
+            None => return response_not_found(),
+        };
+
+        match *req.method() {
+            Method::PUT => {
+                if !is_owner {
+                    return response_forbidden();
+                }
+
+                let update = match body.ok_or_else(response_bad_request) {
+                    Ok(update) => update,
+                    Err(response) => return response,
+                };
+
+                if update.role == Role::Owner {
+                    return response_bad_request();
+                }
+
+                quiz.collaborators[position].role = update.role;
+
+                match save_collaborators(&db, &quiz).await {
+                    Ok(()) => {
+                        response_ok_builder().json(json!({
+                            "quiz_id": quiz_id.to_string(),
+                            "user_id": collaborator_id.to_string(),
+                            "role": update.role,
+                        }))
+                    }
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            Method::DELETE => {
+                if !is_owner && collaborator_id != user_id {
+                    return response_forbidden();
+                }
+
+                quiz.collaborators.remove(position);
+
+                match save_collaborators(&db, &quiz).await {
+                    Ok(()) => {
+                        response_ok_builder().json(json!({
+                            "quiz_id": quiz_id.to_string(),
+                            "user_id": collaborator_id.to_string(),
+                        }))
+                    }
+                    Err(_) => response_internal_server_error(),
+                }
+            }
+            _ => method_not_allowed(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource(PATH)
+            .route(web::get().to(list_handler))
+            .route(web::post().to(list_handler))
+    );
+    cfg.service(
+        web::resource(format!("{}/{{user_id}}", PATH))
+            .route(web::put().to(collaborator_handler))
+            .route(web::delete().to(collaborator_handler))
+    );
+}
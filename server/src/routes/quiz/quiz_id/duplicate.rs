@@ -0,0 +1,137 @@
+use actix_web::{web, HttpRequest, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use mongodb::bson::DateTime;
+use mongodb::Database;
+
+use crate::libraries::{response_internal_server_error, response_not_found, response_ok_builder};
+use crate::models::bank::BankItem;
+use crate::models::id::QuizId;
+use crate::models::media::Media;
+use crate::models::quiz::{ForkedFrom, Permission, Quiz, Visibility};
+use crate::models::revision::QuizRevision;
+use crate::routes::quiz::validation::TITLE_MAX_LENGTH;
+use super::find_quiz;
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/duplicate";
+
+const COPY_SUFFIX: &str = " (copy)";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+fn copy_title(title: &str) -> String {
+    let max_length = TITLE_MAX_LENGTH - COPY_SUFFIX.chars().count();
+    let title: String = title.chars().take(max_length).collect();
+
+    format!("{}{}", title.trim_end(), COPY_SUFFIX)
+}
+
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        let source = match find_quiz(&db, quiz_id, user_id, Permission::View).await {
+            Ok(quiz) => quiz,
+            Err(response) => return response,
+        };
+
+        // The copy's owner can't read someone else's bank, so referenced questions are copied in.
+        // Random draws stay, and draw from the new owner's bank.
+        let slides = if source.owner_id == user_id {
+            source.slides.clone()
+        } else {
+            match BankItem::inline_items(&db, source.owner_id, &source.slides).await {
+                Ok(slides) => slides,
+                Err(_) => return response_internal_server_error(),
+            }
+        };
+
+        // Uploaded images are shared by key; the new owner gets their own claim on each one.
+        if source.owner_id != user_id && Media::grant(&db, user_id, Media::keys_in(&slides)).await.is_err() {
+            return response_internal_server_error();
+        }
+
+        let created_at = DateTime::now();
+
+        let quiz = Quiz {
+            id: QuizId::new(),
+            owner_id: user_id,
+            title: copy_title(&source.title),
+            description: source.description.clone(),
+            slides,
+            revision: 1,
+            forked_from: Some(ForkedFrom {
+                quiz_id: source.id,
+                revision: source.revision,
+            }),
+            collaborators: Vec::new(),
+            folder_id: None,
+            visibility: Visibility::Private,
+            tags: source.tags.clone(),
+            subject: source.subject.clone(),
+            grade_level: source.grade_level.clone(),
+            language: source.language.clone(),
+            play_count: 0,
+            updated_at: created_at,
+            created_at,
+            is_deleted: false,
+            deleted_at: None,
+        };
+
+        let mut session = match db.client().start_session().await {
+            Ok(session) => session,
+            Err(_) => return response_internal_server_error(),
+        };
+
+        let result: mongodb::error::Result<()> = async {
+            session.start_transaction().await?;
+
+            Quiz::collection(&db)
+                .insert_one(&quiz)
+                .session(&mut session)
+                .await?;
+
+            QuizRevision::record(&db, &mut session, &quiz, user_id, None).await?;
+
+            session.commit_transaction().await?;
+
+            Ok(())
+        }.await;
+
+        match result {
+            Ok(()) => {
+                response_ok_builder().json(json!({
+                    "quiz_id": quiz.id.to_string(),
+                    "title": quiz.title,
+                    "forked_from": source.id.to_string(),
+                    "created_at": created_at,
+                }))
+            }
+            Err(_) => {
+                let _ = session.abort_transaction().await;
+
+                response_internal_server_error()
+            }
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::post().to(handler)));
+}
@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use actix::{Actor, Addr, AsyncContext, Context, Handler, Message};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::Database;
+use tokio::sync::Mutex;
+
+use crate::libraries::redis::{subscribe_forever, RedisConn};
+use crate::libraries::{response_internal_server_error, response_not_found};
+use crate::models::edit_session::{Editor, LoggedEdit};
+use crate::models::id::{QuizId, UserId};
+use crate::models::quiz::{Permission, Quiz};
+use crate::models::slide::SlideEdit;
+use crate::models::user::User;
+use super::find_quiz;
+use super::slides::{save_edit, EditOutcome};
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/edit";
+
+const CHANNEL: &str = "quiz_edit";
+
+/// How often an edit is retried when another node saves the quiz between our read and write.
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Register {
+    quiz_id: QuizId,
+    connection_id: String,
+    addr: Addr<EditorWebSocket>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Unregister {
+    quiz_id: QuizId,
+    connection_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct EditorMessage(String);
+
+#[derive(Serialize, Deserialize)]
+struct BroadcastPayload {
+    quiz_id: QuizId,
+    message: String,
+}
+
+/// Messages a client sends. Edits name the revision they were made against; the server rebases
+/// them over anything saved since, so every client ends up applying the same edits in the same
+/// order, the one broadcast back as `edit`.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum EditorAction {
+    Focus {
+        slide: Option<usize>,
+    },
+    Edit {
+        request_id: Option<String>,
+        base_revision: i64,
+        edit: Box<SlideEdit>,
+    },
+}
+
+struct EditorWebSocket {
+    connection_id: String,
+    user_id: UserId,
+    name: String,
+    quiz_id: QuizId,
+    /// Whether the editor could edit when it connected, for `init`. Edits check again.
+    can_edit: bool,
+    db: web::Data<Database>,
+    manager: Addr<EditManager>,
+}
+
+type QuizConnections = HashMap<QuizId, HashMap<String, Addr<EditorWebSocket>>>;
+
+struct EditManager {
+    connections: Arc<Mutex<QuizConnections>>,
+}
+
+impl Actor for EditManager {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _: &mut Self::Context) {
+        let connections = self.connections.clone();
+
+        thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                subscribe_forever(&[CHANNEL], |msg| {
+                    let payload: String = msg.get_payload().unwrap_or_default();
+
+                    let broadcast: BroadcastPayload = match serde_json::from_str(&payload) {
+                        Ok(broadcast) => broadcast,
+                        Err(_) => return,
+                    };
+                    let connections = connections.clone();
+
+                    tokio::spawn(async move {
+                        let conns = connections.lock().await;
+
+                        if let Some(quiz_conns) = conns.get(&broadcast.quiz_id) {
+                            for addr in quiz_conns.values() {
+                                addr.do_send(EditorMessage(broadcast.message.clone()));
+                            }
+                        }
+                    });
+                })
+            });
+        });
+    }
+}
+
+impl Handler<Register> for EditManager {
+    type Result = actix::ResponseFuture<()>;
+
+    fn handle(&mut self, msg: Register, _: &mut Self::Context) -> Self::Result {
+        let connections = self.connections.clone();
+
+        Box::pin(async move {
+            let mut conns = connections.lock().await;
+
+            conns.entry(msg.quiz_id).or_default().insert(msg.connection_id, msg.addr);
+        })
+    }
+}
+
+impl Handler<Unregister> for EditManager {
+    type Result = actix::ResponseFuture<()>;
+
+    fn handle(&mut self, msg: Unregister, _: &mut Self::Context) -> Self::Result {
+        let connections = self.connections.clone();
+
+        Box::pin(async move {
+            let mut conns = connections.lock().await;
+
+            if let Some(quiz_conns) = conns.get_mut(&msg.quiz_id) {
+                quiz_conns.remove(&msg.connection_id);
+
+                if quiz_conns.is_empty() {
+                    conns.remove(&msg.quiz_id);
+                }
+            }
+        })
+    }
+}
+
+/// Sends `message` to every editor of the quiz, on every node.
+fn broadcast(redis_connect: &mut RedisConn, quiz_id: QuizId, message: Value) {
+    let payload = BroadcastPayload {
+        quiz_id,
+        message: message.to_string(),
+    };
+
+    if let Ok(payload) = serde_json::to_string(&payload) {
+        redis_connect.publish(CHANNEL, &payload).unwrap_or(0);
+    }
+}
+
+/// Tells every open editor of the quiz about `edit`, saved as `quiz`'s latest revision at slide
+/// `index`. `connection_id` and `request_id` let the author's socket match it to its request;
+/// edits made over REST have neither.
+pub fn publish_edit(
+    quiz: &Quiz,
+    index: usize,
+    edit: &SlideEdit,
+    author_id: UserId,
+    connection_id: Option<&str>,
+    request_id: Option<String>,
+) {
+    if let Ok(mut redis_connect) = RedisConn::get_connection() {
+        broadcast(&mut redis_connect, quiz.id, json!({
+            "action": "edit",
+            "revision": quiz.revision,
+            "edit": edit,
+            "index": index,
+            "author_id": author_id.to_string(),
+            "connection_id": connection_id,
+            "request_id": request_id,
+        }));
+    }
+}
+
+/// Tells every open editor of the quiz to take it as it is now, after a save that replaced it
+/// whole rather than through a slide edit.
+pub fn publish_refresh(quiz: &Quiz, author_id: UserId) {
+    if let Ok(mut redis_connect) = RedisConn::get_connection() {
+        broadcast(&mut redis_connect, quiz.id, json!({
+            "action": "refresh",
+            "revision": quiz.revision,
+            "quiz": quiz.to_json(),
+            "author_id": author_id.to_string(),
+        }));
+    }
+}
+
+fn broadcast_presence(redis_connect: &mut RedisConn, quiz_id: QuizId) {
+    let editors: Vec<Value> = Editor::list(redis_connect, quiz_id).iter().map(Editor::to_json).collect();
+
+    broadcast(redis_connect, quiz_id, json!({
+        "action": "presence",
+        "editors": editors,
+    }));
+}
+
+fn rejected(request_id: Option<String>, reason: &str, errors: Value) -> Value {
+    json!({
+        "action": "rejected",
+        "request_id": request_id,
+        "reason": reason,
+        "errors": errors,
+    })
+}
+
+/// Rebases the edit onto the latest revision, saves it and broadcasts it. Returns the reply for
+/// the author when the edit can't be applied; on success everyone, the author included, gets the
+/// broadcast instead.
+async fn apply_edit(
+    db: &Database,
+    editor: &Editor,
+    quiz_id: QuizId,
+    request_id: Option<String>,
+    base_revision: i64,
+    edit: SlideEdit,
+) -> Option<Value> {
+    for _ in 0..MAX_ATTEMPTS {
+        let quiz = match Quiz::collection(db).find_one(doc! {
+            "_id": quiz_id,
+            "is_deleted": { "$ne": true },
+        }).await {
+            Ok(Some(quiz)) => quiz,
+            Ok(None) => return Some(rejected(request_id, "not_found", Value::Null)),
+            Err(_) => return Some(rejected(request_id, "internal_error", Value::Null)),
+        };
+
+        // The role can change while the socket is open, so it is checked on every edit rather
+        // than trusted from when the editor connected.
+        if !quiz.allows(editor.user_id, Permission::Edit) {
+            return Some(rejected(request_id, "forbidden", Value::Null));
+        }
+
+        if base_revision > quiz.revision {
+            return Some(rejected(request_id, "resync", Value::Null));
+        }
+
+        let rebased = if base_revision == quiz.revision {
+            Some(edit.clone())
+        } else {
+            match LoggedEdit::since(db, quiz_id, base_revision, quiz.revision).await {
+                Ok(Some(applied)) => applied
+                    .iter()
+                    .try_fold(edit.clone(), |edit, applied| edit.transform(applied)),
+                Ok(None) => return Some(rejected(request_id, "resync", Value::Null)),
+                Err(_) => return Some(rejected(request_id, "internal_error", Value::Null)),
+            }
+        };
+
+        let rebased = match rebased {
+            Some(rebased) => rebased,
+            None => return Some(rejected(request_id, "conflict", Value::Null)),
+        };
+
+        match save_edit(db, &quiz, editor.user_id, rebased).await {
+            Ok(EditOutcome::Saved(quiz, index, edit)) => {
+                // The edit was stored with the revision in the same transaction, so there is no
+                // separate log to fall behind.
+                publish_edit(&quiz, index, &edit, editor.user_id, Some(&editor.connection_id), request_id);
+
+                return None;
+            }
+            Ok(EditOutcome::Stale) => continue,
+            Ok(EditOutcome::OutOfRange) => return Some(rejected(request_id, "out_of_range", Value::Null)),
+            Ok(EditOutcome::Invalid(errors)) => return Some(rejected(request_id, "invalid", json!(errors))),
+            Err(_) => return Some(rejected(request_id, "internal_error", Value::Null)),
+        }
+    }
+
+    Some(rejected(request_id, "resync", Value::Null))
+}
+
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+    stream: web::Payload,
+    manager: web::Data<Addr<EditManager>>,
+) -> Result<HttpResponse, Error> {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return Ok(response_error);
+    }
+
+    let user_id = match crate::middlewares::jwt::user_id(&req) {
+        Some(user_id) => user_id,
+        None => return Ok(response_internal_server_error()),
+    };
+
+    let quiz_id = match QuizId::parse(&path.quiz_id) {
+        Some(quiz_id) => quiz_id,
+        None => return Ok(response_not_found()),
+    };
+
+    let quiz = match find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+        Ok(quiz) => quiz,
+        Err(response) => return Ok(response),
+    };
+
+    let name = match User::collection(&db).find_one(doc! { "_id": user_id }).await {
+        Ok(Some(user)) => user.name,
+        Ok(None) => return Ok(response_not_found()),
+        Err(_) => return Ok(response_internal_server_error()),
+    };
+
+    ws::start(
+        EditorWebSocket {
+            connection_id: ObjectId::new().to_hex(),
+            user_id,
+            name,
+            quiz_id,
+            can_edit: quiz.allows(user_id, Permission::Edit),
+            db,
+            manager: manager.get_ref().clone(),
+        },
+        &req,
+        stream,
+    )
+}
+
+impl EditorWebSocket {
+    fn editor(&self, slide: Option<usize>) -> Editor {
+        Editor {
+            connection_id: self.connection_id.clone(),
+            user_id: self.user_id,
+            name: self.name.clone(),
+            slide,
+        }
+    }
+}
+
+impl Actor for EditorWebSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let editor = self.editor(None);
+        let quiz_id = self.quiz_id;
+        let can_edit = self.can_edit;
+        let db = self.db.clone();
+        let addr = ctx.address();
+        let manager = self.manager.clone();
+
+        ctx.spawn(actix::fut::wrap_future(async move {
+            let mut redis_connect = match RedisConn::get_connection() {
+                Ok(conn) => conn,
+                Err(_) => {
+                    addr.do_send(EditorMessage(json!({ "error": "Internal server error." }).to_string()));
+                    addr.do_send(EditorMessage("close".to_string()));
+                    return;
+                }
+            };
+
+            let quiz = match Quiz::collection(&db).find_one(doc! {
+                "_id": quiz_id,
+                "is_deleted": { "$ne": true },
+            }).await {
+                Ok(Some(quiz)) => quiz,
+                _ => {
+                    addr.do_send(EditorMessage(json!({ "error": "Not found." }).to_string()));
+                    addr.do_send(EditorMessage("close".to_string()));
+                    return;
+                }
+            };
+
+            manager.do_send(Register {
+                quiz_id,
+                connection_id: editor.connection_id.clone(),
+                addr: addr.clone(),
+            });
+
+            let _ = editor.save(&mut redis_connect, quiz_id);
+
+            addr.do_send(EditorMessage(json!({
+                "action": "init",
+                "connection_id": editor.connection_id,
+                "can_edit": can_edit,
+                "revision": quiz.revision,
+                "quiz": quiz.to_json_for(editor.user_id),
+                "editors": Editor::list(&mut redis_connect, quiz_id).iter().map(Editor::to_json).collect::<Vec<_>>(),
+            }).to_string()));
+
+            broadcast_presence(&mut redis_connect, quiz_id);
+        }));
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        let quiz_id = self.quiz_id;
+        let connection_id = self.connection_id.clone();
+        let manager = self.manager.clone();
+
+        ctx.spawn(actix::fut::wrap_future(async move {
+            if let Ok(mut redis_connect) = RedisConn::get_connection() {
+                let _ = Editor::remove(&mut redis_connect, quiz_id, &connection_id);
+
+                broadcast_presence(&mut redis_connect, quiz_id);
+            }
+
+            manager.do_send(Unregister {
+                quiz_id,
+                connection_id,
+            });
+        }));
+    }
+}
+
+impl Handler<EditorMessage> for EditorWebSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: EditorMessage, ctx: &mut Self::Context) {
+        if msg.0 == "close" {
+            ctx.close(None);
+        } else {
+            ctx.text(msg.0);
+        }
+    }
+}
+
+impl actix::StreamHandler<Result<ws::Message, ws::ProtocolError>> for EditorWebSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Text(text)) => {
+                let action: EditorAction = match serde_json::from_str(text.trim()) {
+                    Ok(action) => action,
+                    Err(_) => {
+                        ctx.text(json!({ "error": "Bad request." }).to_string());
+                        return;
+                    }
+                };
+
+                let quiz_id = self.quiz_id;
+                let addr = ctx.address();
+
+                match action {
+                    EditorAction::Focus { slide } => {
+                        let editor = self.editor(slide);
+
+                        ctx.spawn(actix::fut::wrap_future(async move {
+                            if let Ok(mut redis_connect) = RedisConn::get_connection() {
+                                let _ = editor.save(&mut redis_connect, quiz_id);
+
+                                broadcast_presence(&mut redis_connect, quiz_id);
+                            }
+                        }));
+                    }
+                    EditorAction::Edit { request_id, base_revision, edit } => {
+                        let db = self.db.clone();
+                        let editor = self.editor(None);
+
+                        ctx.spawn(actix::fut::wrap_future(async move {
+                            if let Some(reply) = apply_edit(&db, &editor, quiz_id, request_id, base_revision, *edit).await {
+                                addr.do_send(EditorMessage(reply.to_string()));
+                            }
+                        }));
+                    }
+                }
+            }
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(_)) => ctx.close(None),
+            _ => (),
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let manager = EditManager {
+        connections: Arc::new(Mutex::new(HashMap::new())),
+    }.start();
+    cfg.app_data(web::Data::new(manager));
+    cfg.service(web::resource(PATH).route(web::get().to(handler)));
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use actix_web::http::header::{Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue, CONTENT_TYPE};
+use actix_web::{web, HttpRequest, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use mongodb::Database;
+
+use crate::libraries::media::{kind, MediaKind};
+use crate::libraries::interchange::{self, Format, Image};
+use crate::libraries::storage::get_storage;
+use crate::libraries::{response_bad_request, response_internal_server_error, response_not_found, response_ok_builder};
+use crate::models::bank::BankItem;
+use crate::models::id::QuizId;
+use crate::models::media::Media;
+use crate::models::quiz::Permission;
+use crate::models::slide::Slide;
+use super::find_quiz;
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/export";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// `gift`, `moodle_xml`, `qti21` or `qti30`.
+    format: String,
+    /// Only report what the format can't hold, without the file.
+    #[serde(default)]
+    warnings_only: bool,
+}
+
+/// Content-Disposition for downloading `file_name`. A name that isn't ASCII goes in `filename*`,
+/// with an ASCII stand-in for clients that don't read it.
+fn attachment(file_name: String) -> ContentDisposition {
+    if file_name.is_ascii() {
+        return ContentDisposition::attachment(file_name);
+    }
+
+    let fallback: String = file_name.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect();
+
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: file_name.into_bytes(),
+            }),
+            DispositionParam::Filename(fallback),
+        ],
+    }
+}
+
+/// The uploaded images of `slides`, by slide index, for formats that embed them.
+async fn load_images(slides: &[Slide]) -> std::io::Result<HashMap<usize, Image>> {
+    let mut images = HashMap::new();
+
+    for (index, slide) in slides.iter().enumerate() {
+        let key = match slide.image_path().and_then(Media::key_from_path) {
+            Some(key) if kind(key) == Some(MediaKind::Image) => key,
+            _ => continue,
+        };
+
+        if let Some(bytes) = get_storage().get(key).await? {
+            images.insert(index, Image { name: key.to_string(), bytes });
+        }
+    }
+
+    Ok(images)
+}
+
+/// Downloads the quiz as a Moodle question file or QTI package. With `warnings_only`, returns what
+/// the format couldn't hold as JSON instead, so it can be shown before the download.
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        let format = match Format::parse(&query.format) {
+            Some(format) => format,
+            None => return response_bad_request(),
+        };
+
+        // Exports carry the answers, so the link alone isn't enough.
+        let quiz = match find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+            Ok(quiz) => quiz,
+            Err(response) => return response,
+        };
+
+        // Bank questions are written out as ordinary questions; random draws have no equivalent.
+        let slides = match BankItem::inline_items(&db, quiz.owner_id, &quiz.slides).await {
+            Ok(slides) => slides,
+            Err(_) => return response_internal_server_error(),
+        };
+
+        let images = if format.has_images() {
+            match load_images(&slides).await {
+                Ok(images) => images,
+                Err(_) => return response_internal_server_error(),
+            }
+        } else {
+            HashMap::new()
+        };
+
+        let title = quiz.title.clone();
+
+        let (content, warnings) = match web::block(move || interchange::export(format, &title, &slides, images)).await {
+            Ok(Ok(exported)) => exported,
+            _ => return response_internal_server_error(),
+        };
+
+        let file_name = format.file_name(&quiz.title);
+
+        if query.warnings_only {
+            return response_ok_builder().json(json!({
+                "format": query.format,
+                "filename": file_name,
+                "warnings": warnings,
+            }));
+        }
+
+        response_ok_builder()
+            .insert_header((CONTENT_TYPE, format.content_type()))
+            .insert_header(attachment(file_name))
+            .body(content)
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::get().to(handler)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attaches_non_ascii_names_with_a_fallback() {
+        assert_eq!(attachment("quiz.qti21.zip".to_string()).to_string(), "attachment; filename=\"quiz.qti21.zip\"");
+        assert_eq!(
+            attachment("Géographie.xml".to_string()).to_string(),
+            "attachment; filename*=UTF-8''G%C3%A9ographie.xml; filename=\"G_ographie.xml\"",
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use actix_web::{web, HttpRequest, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use mongodb::bson::doc;
+use mongodb::Database;
+
+use crate::libraries::{response_bad_request, response_internal_server_error, response_not_found, response_ok_builder};
+use crate::models::folder::Folder;
+use crate::models::id::{FolderId, QuizId};
+use crate::models::quiz::{Permission, Quiz};
+use super::find_quiz;
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/folder";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+#[derive(Deserialize)]
+struct FolderMove {
+    /// `null` moves the quiz back to the root.
+    folder_id: Option<String>,
+}
+
+async fn handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    body: web::Json<FolderMove>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        if let Err(response) = find_quiz(&db, quiz_id, user_id, Permission::Manage).await {
+            return response;
+        }
+
+        let folder_id = match body.folder_id.as_deref() {
+            Some(folder_id) => match FolderId::parse(folder_id) {
+                Some(folder_id) => Some(folder_id),
+                None => return response_bad_request(),
+            },
+            None => None,
+        };
+
+        if let Some(folder_id) = folder_id {
+            match Folder::find_owned(&db, folder_id, user_id).await {
+                Ok(Some(_)) => {}
+                Ok(None) => return response_not_found(),
+                Err(_) => return response_internal_server_error(),
+            }
+        }
+
+        match Quiz::collection(&db).update_one(
+            doc! { "_id": quiz_id, "owner_id": user_id },
+            doc! { "$set": { "folder_id": folder_id } },
+        ).await {
+            Ok(_) => {
+                response_ok_builder().json(json!({
+                    "quiz_id": quiz_id.to_string(),
+                    "folder_id": folder_id.map(|folder_id| folder_id.to_string()),
+                }))
+            }
+            Err(_) => response_internal_server_error(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::put().to(handler)));
+}
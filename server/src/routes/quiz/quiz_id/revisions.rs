@@ -0,0 +1,391 @@
+use actix_web::{web, HttpRequest, Responder};
+use actix_web::http::header::ETAG;
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use mongodb::bson::{doc, to_bson, DateTime};
+use mongodb::options::ReturnDocument;
+use mongodb::Database;
+
+use crate::libraries::{response_bad_request, response_internal_server_error, response_not_found, response_ok_builder, response_precondition_required, response_unprocessable_entity};
+use crate::models::id::QuizId;
+use crate::models::quiz::{Permission, Quiz};
+use crate::models::revision::QuizRevision;
+use crate::models::slide::Slide;
+use crate::routes::quiz::validation::{validate_quiz_media, validate_slides};
+use super::edit::publish_refresh;
+use super::{etag, find_quiz, if_match, response_precondition_failed};
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/revisions";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+#[derive(Deserialize)]
+struct RevisionRequest {
+    quiz_id: String,
+    revision: i64,
+}
+
+/// `?revision=N` (or `If-Match`) names the quiz's current revision, as for a save: 428 without
+/// one, 412 if someone else saved in the meantime.
+#[derive(Deserialize)]
+struct RevisionGuard {
+    revision: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct DiffQuery {
+    from: i64,
+    to: i64,
+}
+
+async fn find_revision(db: &Database, quiz_id: QuizId, revision: i64) -> Result<QuizRevision, actix_web::HttpResponse> {
+    match QuizRevision::collection(db).find_one(doc! {
+        "quiz_id": quiz_id,
+        "revision": revision,
+    }).await {
+        Ok(Some(revision)) => Ok(revision),
+        Ok(None) => Err(response_not_found()),
+        Err(_) => Err(response_internal_server_error()),
+    }
+}
+
+/// Pairs up slides that are identical in both revisions (longest common subsequence), so an
+/// inserted or removed slide doesn't show every following slide as modified.
+fn diff_slides(from: &[Slide], to: &[Slide]) -> Vec<Value> {
+    let from: Vec<Value> = from.iter().map(|slide| serde_json::to_value(slide).unwrap_or(Value::Null)).collect();
+    let to: Vec<Value> = to.iter().map(|slide| serde_json::to_value(slide).unwrap_or(Value::Null)).collect();
+
+    let mut lengths = vec![vec![0usize; to.len() + 1]; from.len() + 1];
+
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            lengths[i][j] = if from[i] == to[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut removed: Vec<usize> = Vec::new();
+    let mut added: Vec<usize> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    let flush = |removed: &mut Vec<usize>, added: &mut Vec<usize>, changes: &mut Vec<Value>| {
+        let paired = removed.len().min(added.len());
+
+        for (&from_index, &to_index) in removed.iter().zip(added.iter()).take(paired) {
+            changes.push(json!({
+                "status": "modified",
+                "from_index": from_index,
+                "to_index": to_index,
+                "from": from[from_index],
+                "to": to[to_index],
+            }));
+        }
+
+        for &from_index in &removed[paired..] {
+            changes.push(json!({
+                "status": "removed",
+                "from_index": from_index,
+                "from": from[from_index],
+            }));
+        }
+
+        for &to_index in &added[paired..] {
+            changes.push(json!({
+                "status": "added",
+                "to_index": to_index,
+                "to": to[to_index],
+            }));
+        }
+
+        removed.clear();
+        added.clear();
+    };
+
+    while i < from.len() || j < to.len() {
+        if i < from.len() && j < to.len() && from[i] == to[j] {
+            flush(&mut removed, &mut added, &mut changes);
+            changes.push(json!({
+                "status": "unchanged",
+                "from_index": i,
+                "to_index": j,
+            }));
+            i += 1;
+            j += 1;
+        } else if j < to.len() && (i == from.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            added.push(j);
+            j += 1;
+        } else {
+            removed.push(i);
+            i += 1;
+        }
+    }
+
+    flush(&mut removed, &mut added, &mut changes);
+
+    changes
+}
+
+async fn list_handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        if let Err(response) = find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+            return response;
+        }
+
+        let cursor = match QuizRevision::collection(&db)
+            .find(doc! { "quiz_id": quiz_id })
+            .sort(doc! { "revision": -1 })
+            .await
+        {
+            Ok(cursor) => cursor,
+            Err(_) => return response_internal_server_error(),
+        };
+
+        let revisions: Vec<QuizRevision> = match cursor.try_collect().await {
+            Ok(revisions) => revisions,
+            Err(_) => return response_internal_server_error(),
+        };
+
+        let revisions: Vec<Value> = revisions.iter().map(QuizRevision::to_summary_json).collect();
+
+        response_ok_builder().json(json!({
+            "quiz_id": quiz_id.to_string(),
+            "revisions": revisions,
+        }))
+    } else {
+        response_internal_server_error()
+    }
+}
+
+async fn diff_handler(
+    path: web::Path<Request>,
+    query: web::Query<DiffQuery>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        if let Err(response) = find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+            return response;
+        }
+
+        let from = match find_revision(&db, quiz_id, query.from).await {
+            Ok(revision) => revision,
+            Err(response) => return response,
+        };
+
+        let to = match find_revision(&db, quiz_id, query.to).await {
+            Ok(revision) => revision,
+            Err(response) => return response,
+        };
+
+        let mut fields = Vec::new();
+
+        if from.title != to.title {
+            fields.push(json!({ "field": "title", "from": from.title, "to": to.title }));
+        }
+
+        if from.description != to.description {
+            fields.push(json!({ "field": "description", "from": from.description, "to": to.description }));
+        }
+
+        response_ok_builder().json(json!({
+            "quiz_id": quiz_id.to_string(),
+            "from": from.revision,
+            "to": to.revision,
+            "fields": fields,
+            "slides": diff_slides(&from.slides, &to.slides),
+        }))
+    } else {
+        response_internal_server_error()
+    }
+}
+
+async fn revision_handler(
+    path: web::Path<RevisionRequest>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        if let Err(response) = find_quiz(&db, quiz_id, user_id, Permission::Collaborate).await {
+            return response;
+        }
+
+        match find_revision(&db, quiz_id, path.revision).await {
+            Ok(revision) => response_ok_builder().json(revision.to_json()),
+            Err(response) => response,
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+async fn restore_handler(
+    path: web::Path<RevisionRequest>,
+    query: web::Query<RevisionGuard>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        let expected_revision = match if_match(&req).or(query.revision) {
+            Some(revision) => revision,
+            None => return response_precondition_required(),
+        };
+
+        let quiz = match find_quiz(&db, quiz_id, user_id, Permission::Edit).await {
+            Ok(quiz) => quiz,
+            Err(response) => return response,
+        };
+
+        if quiz.revision != expected_revision {
+            return response_precondition_failed(&quiz, user_id);
+        }
+
+        let revision = match find_revision(&db, quiz_id, path.revision).await {
+            Ok(revision) => revision,
+            Err(response) => return response,
+        };
+
+        // An old revision may predate today's rules or use media the restorer can't reach.
+        if let Err(errors) = validate_slides(&revision.slides) {
+            return response_unprocessable_entity(errors);
+        }
+
+        match validate_quiz_media(&db, &quiz, &revision.slides, user_id).await {
+            Ok(Ok(())) => {}
+            Ok(Err(errors)) => return response_unprocessable_entity(errors),
+            Err(_) => return response_internal_server_error(),
+        }
+
+        let slides = match to_bson(&revision.slides) {
+            Ok(slides) => slides,
+            Err(_) => return response_bad_request(),
+        };
+
+        let mut session = match db.client().start_session().await {
+            Ok(session) => session,
+            Err(_) => return response_internal_server_error(),
+        };
+
+        let updated_at = DateTime::now();
+
+        let result: mongodb::error::Result<Option<Quiz>> = async {
+            session.start_transaction().await?;
+
+            // Matching on the revision checked above, so a save in between turns this into a 412
+            // instead of being silently replaced.
+            let quiz = match Quiz::collection(&db).find_one_and_update(
+                    doc! {
+                        "_id": quiz_id,
+                        "revision": quiz.revision,
+                        "is_deleted": { "$ne": true },
+                    },
+                    doc! {
+                        "$set": {
+                            "title": revision.title.clone(),
+                            "description": revision.description.clone(),
+                            "slides": slides,
+                            "updated_at": updated_at,
+                        },
+                        "$inc": { "revision": 1 },
+                    },
+                )
+                .return_document(ReturnDocument::After)
+                .session(&mut session)
+                .await?
+            {
+                Some(quiz) => quiz,
+                None => {
+                    session.abort_transaction().await?;
+
+                    return Ok(None);
+                }
+            };
+
+            QuizRevision::record(&db, &mut session, &quiz, user_id, Some(revision.revision)).await?;
+
+            session.commit_transaction().await?;
+
+            Ok(Some(quiz))
+        }.await;
+
+        match result {
+            Ok(None) => match find_quiz(&db, quiz_id, user_id, Permission::View).await {
+                Ok(quiz) => response_precondition_failed(&quiz, user_id),
+                Err(response) => response,
+            },
+            Ok(Some(quiz)) => {
+                publish_refresh(&quiz, user_id);
+
+                response_ok_builder()
+                    .insert_header((ETAG, etag(quiz.revision)))
+                    .json(json!({
+                        "quiz_id": quiz_id.to_string(),
+                        "revision": quiz.revision,
+                        "restored_from": revision.revision,
+                        "updated_at": updated_at,
+                    }))
+            }
+            Err(_) => {
+                let _ = session.abort_transaction().await;
+
+                response_internal_server_error()
+            }
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::get().to(list_handler)));
+    cfg.service(web::resource(format!("{}/diff", PATH)).route(web::get().to(diff_handler)));
+    cfg.service(web::resource(format!("{}/{{revision}}", PATH)).route(web::get().to(revision_handler)));
+    cfg.service(web::resource(format!("{}/{{revision}}/restore", PATH)).route(web::post().to(restore_handler)));
+}
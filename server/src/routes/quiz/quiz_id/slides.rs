@@ -0,0 +1,274 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::http::header::ETAG;
+use serde::Deserialize;
+use serde_json::json;
+use mongodb::bson::{doc, to_bson, DateTime};
+use mongodb::options::ReturnDocument;
+use mongodb::Database;
+
+use crate::libraries::{response_bad_request, response_internal_server_error, response_not_found, response_ok_builder, response_precondition_required, response_unprocessable_entity};
+use crate::models::id::{QuizId, UserId};
+use crate::models::quiz::{Permission, Quiz};
+use crate::models::revision::QuizRevision;
+use crate::models::slide::{Slide, SlideEdit};
+use crate::routes::quiz::validation::{validate_quiz_media, validate_slides, FieldError};
+use super::edit::publish_edit;
+use super::{etag, find_quiz, if_match, response_precondition_failed};
+
+pub const PATH: &str = "/api/quiz/{quiz_id}/slides";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+#[derive(Deserialize)]
+struct SlideRequest {
+    quiz_id: String,
+    index: usize,
+}
+
+/// `?revision=N` (or `If-Match`) names the revision the edit was made against. One of them is
+/// required (428 otherwise), and the edit fails with 412 if someone else saved the quiz in the
+/// meantime, since slide indexes from an older revision may point at a different slide.
+#[derive(Deserialize)]
+struct RevisionGuard {
+    revision: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct SlideInsertion {
+    slide: Slide,
+    /// Defaults to appending at the end.
+    position: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SlideUpdate {
+    slide: Slide,
+}
+
+#[derive(Deserialize)]
+struct SlideMove {
+    to: usize,
+}
+
+pub enum EditOutcome {
+    /// The quiz after the edit, the index of the slide it touched, and the edit with its
+    /// positions resolved.
+    Saved(Box<Quiz>, usize, Box<SlideEdit>),
+    OutOfRange,
+    Invalid(Vec<FieldError>),
+    /// Someone else saved the quiz after `quiz` was read.
+    Stale,
+}
+
+/// Applies `edit` on top of `quiz` and saves it as the next revision, provided nobody else has
+/// saved in between.
+pub async fn save_edit(db: &Database, quiz: &Quiz, user_id: UserId, edit: SlideEdit) -> mongodb::error::Result<EditOutcome> {
+    let edit = edit.resolve(quiz.slides.len());
+    let mut slides = quiz.slides.clone();
+
+    let index = match edit.clone().apply(&mut slides) {
+        Some(index) => index,
+        None => return Ok(EditOutcome::OutOfRange),
+    };
+
+    if let Err(errors) = validate_slides(&slides) {
+        return Ok(EditOutcome::Invalid(errors));
+    }
+
+    if let Err(errors) = validate_quiz_media(db, quiz, &slides, user_id).await? {
+        return Ok(EditOutcome::Invalid(errors));
+    }
+
+    let slides = to_bson(&slides)?;
+
+    let mut session = db.client().start_session().await?;
+
+    let result: mongodb::error::Result<Option<Quiz>> = async {
+        session.start_transaction().await?;
+
+        // Matching on the revision we read keeps two concurrent slide edits from overwriting
+        // each other; the loser sees `Stale` and retries against the fresh quiz.
+        let saved = Quiz::collection(db).find_one_and_update(
+                doc! {
+                    "_id": quiz.id,
+                    "revision": quiz.revision,
+                    "is_deleted": { "$ne": true },
+                },
+                doc! {
+                    "$set": {
+                        "slides": slides,
+                        "updated_at": DateTime::now(),
+                    },
+                    "$inc": { "revision": 1 },
+                },
+            )
+            .return_document(ReturnDocument::After)
+            .session(&mut session)
+            .await?;
+
+        if let Some(saved) = &saved {
+            QuizRevision::record_edit(db, &mut session, saved, user_id, &edit).await?;
+        }
+
+        session.commit_transaction().await?;
+
+        Ok(saved)
+    }.await;
+
+    match result {
+        Ok(Some(saved)) => Ok(EditOutcome::Saved(Box::new(saved), index, Box::new(edit))),
+        Ok(None) => Ok(EditOutcome::Stale),
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+
+            Err(e)
+        }
+    }
+}
+
+async fn edit_slides(
+    req: &HttpRequest,
+    db: &web::Data<Database>,
+    quiz_id: &str,
+    expected_revision: Option<i64>,
+    edit: SlideEdit,
+) -> HttpResponse {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(req, db).await {
+        return response_error;
+    }
+
+    let user_id = match crate::middlewares::jwt::user_id(req) {
+        Some(user_id) => user_id,
+        None => return response_internal_server_error(),
+    };
+
+    let quiz_id = match QuizId::parse(quiz_id) {
+        Some(quiz_id) => quiz_id,
+        None => return response_not_found(),
+    };
+
+    let expected_revision = match if_match(req).or(expected_revision) {
+        Some(revision) => revision,
+        None => return response_precondition_required(),
+    };
+
+    let quiz = match find_quiz(db, quiz_id, user_id, Permission::Edit).await {
+        Ok(quiz) => quiz,
+        Err(response) => return response,
+    };
+
+    if quiz.revision != expected_revision {
+        return response_precondition_failed(&quiz, user_id);
+    }
+
+    match save_edit(db, &quiz, user_id, edit).await {
+        Ok(EditOutcome::Saved(quiz, index, edit)) => {
+            publish_edit(&quiz, index, &edit, user_id, None, None);
+
+            response_ok_builder()
+                .insert_header((ETAG, etag(quiz.revision)))
+                .json(json!({
+                    "quiz_id": quiz_id.to_string(),
+                    "revision": quiz.revision,
+                    "index": index,
+                    "slide": quiz.slides.get(index).map(Slide::to_json),
+                    "slide_count": quiz.slides.len(),
+                    "updated_at": quiz.updated_at,
+                }))
+        }
+        Ok(EditOutcome::OutOfRange) => response_not_found(),
+        Ok(EditOutcome::Invalid(errors)) => response_unprocessable_entity(errors),
+        Ok(EditOutcome::Stale) => match find_quiz(db, quiz_id, user_id, Permission::View).await {
+            Ok(quiz) => response_precondition_failed(&quiz, user_id),
+            Err(response) => response,
+        },
+        Err(_) => response_internal_server_error(),
+    }
+}
+
+async fn insert_handler(
+    path: web::Path<Request>,
+    query: web::Query<RevisionGuard>,
+    req: HttpRequest,
+    body: Option<web::Json<SlideInsertion>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let body = match body.ok_or_else(response_bad_request) {
+        Ok(body) => body.into_inner(),
+        Err(response) => return response,
+    };
+
+    let edit = SlideEdit::Insert { position: body.position, slide: body.slide };
+
+    edit_slides(&req, &db, &path.quiz_id, query.revision, edit).await
+}
+
+async fn update_handler(
+    path: web::Path<SlideRequest>,
+    query: web::Query<RevisionGuard>,
+    req: HttpRequest,
+    body: Option<web::Json<SlideUpdate>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let body = match body.ok_or_else(response_bad_request) {
+        Ok(body) => body.into_inner(),
+        Err(response) => return response,
+    };
+
+    let edit = SlideEdit::Update { index: path.index, slide: body.slide };
+
+    edit_slides(&req, &db, &path.quiz_id, query.revision, edit).await
+}
+
+async fn delete_handler(
+    path: web::Path<SlideRequest>,
+    query: web::Query<RevisionGuard>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let edit = SlideEdit::Delete { index: path.index };
+
+    edit_slides(&req, &db, &path.quiz_id, query.revision, edit).await
+}
+
+async fn move_handler(
+    path: web::Path<SlideRequest>,
+    query: web::Query<RevisionGuard>,
+    req: HttpRequest,
+    body: Option<web::Json<SlideMove>>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let body = match body.ok_or_else(response_bad_request) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let edit = SlideEdit::Move { from: path.index, to: body.to };
+
+    edit_slides(&req, &db, &path.quiz_id, query.revision, edit).await
+}
+
+async fn duplicate_handler(
+    path: web::Path<SlideRequest>,
+    query: web::Query<RevisionGuard>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let edit = SlideEdit::Duplicate { index: path.index };
+
+    edit_slides(&req, &db, &path.quiz_id, query.revision, edit).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::post().to(insert_handler)));
+    cfg.service(
+        web::resource(format!("{}/{{index}}", PATH))
+            .route(web::put().to(update_handler))
+            .route(web::delete().to(delete_handler))
+    );
+    cfg.service(web::resource(format!("{}/{{index}}/move", PATH)).route(web::post().to(move_handler)));
+    cfg.service(web::resource(format!("{}/{{index}}/duplicate", PATH)).route(web::post().to(duplicate_handler)));
+}
@@ -0,0 +1,137 @@
+use actix_web::{web, HttpRequest, Responder};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use mongodb::bson::{doc, DateTime};
+use mongodb::Database;
+
+use crate::libraries::trash::{purge, retention_millis};
+use crate::libraries::{response_internal_server_error, response_not_found, response_ok_builder};
+use crate::models::id::QuizId;
+use crate::models::quiz::{Quiz, QuizSummary};
+
+pub const PATH: &str = "/api/quiz/trash";
+
+#[derive(Deserialize)]
+struct Request {
+    quiz_id: String,
+}
+
+async fn list_handler(
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let cursor = QuizSummary::collection(&db)
+            .find(doc! {
+                "owner_id": user_id,
+                "is_deleted": true,
+            })
+            .projection(QuizSummary::projection())
+            .sort(doc! { "deleted_at": -1 })
+            .await;
+
+        let quizzes: Vec<QuizSummary> = match cursor {
+            Ok(cursor) => match cursor.try_collect().await {
+                Ok(quizzes) => quizzes,
+                Err(_) => return response_internal_server_error(),
+            },
+            Err(_) => return response_internal_server_error(),
+        };
+
+        let quizzes: Vec<Value> = quizzes.iter().map(|quiz| {
+            let mut value = quiz.to_json_for(user_id);
+
+            value["deleted_at"] = json!(quiz.deleted_at);
+            value["purge_at"] = json!(quiz.deleted_at.map(|deleted_at| {
+                DateTime::from_millis(deleted_at.timestamp_millis() + retention_millis())
+            }));
+
+            value
+        }).collect();
+
+        response_ok_builder().json(json!({ "quizzes": quizzes }))
+    } else {
+        response_internal_server_error()
+    }
+}
+
+async fn restore_handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        let updated_at = DateTime::now();
+
+        match Quiz::collection(&db).update_one(
+            doc! {
+                "_id": quiz_id,
+                "owner_id": user_id,
+                "is_deleted": true,
+            },
+            doc! {
+                "$set": {
+                    "is_deleted": false,
+                    "updated_at": updated_at,
+                },
+                "$unset": { "deleted_at": "" },
+            },
+        ).await {
+            Ok(result) if result.matched_count == 0 => response_not_found(),
+            Ok(_) => {
+                response_ok_builder().json(json!({
+                    "quiz_id": quiz_id.to_string(),
+                    "updated_at": updated_at,
+                }))
+            }
+            Err(_) => response_internal_server_error(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+async fn purge_handler(
+    path: web::Path<Request>,
+    req: HttpRequest,
+    db: web::Data<Database>,
+) -> impl Responder {
+    if let Some(response_error) = crate::middlewares::jwt::middleware(&req, &db).await {
+        return response_error;
+    }
+
+    if let Some(user_id) = crate::middlewares::jwt::user_id(&req) {
+        let quiz_id = match QuizId::parse(&path.quiz_id) {
+            Some(quiz_id) => quiz_id,
+            None => return response_not_found(),
+        };
+
+        match purge(&db, doc! { "_id": quiz_id, "owner_id": user_id }).await {
+            Ok(0) => response_not_found(),
+            Ok(_) => response_ok_builder().json(json!({ "quiz_id": quiz_id.to_string() })),
+            Err(_) => response_internal_server_error(),
+        }
+    } else {
+        response_internal_server_error()
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(PATH).route(web::get().to(list_handler)));
+    cfg.service(web::resource(format!("{}/{{quiz_id}}", PATH)).route(web::delete().to(purge_handler)));
+    cfg.service(web::resource(format!("{}/{{quiz_id}}/restore", PATH)).route(web::post().to(restore_handler)));
+}
@@ -0,0 +1,479 @@
+use mongodb::Database;
+use serde::Serialize;
+
+use crate::routes::play::OPEN_ENDED_MAX_LENGTH;
+use crate::models::id::{BankItemId, UserId};
+use crate::libraries::media::{MediaKind, CLIP_MAX_SECONDS};
+use crate::libraries::rich_text;
+use crate::models::media::Media;
+use crate::models::quiz::Quiz;
+use crate::models::slide::Slide;
+use crate::routes::quiz::QuizCreation;
+
+pub const TITLE_MAX_LENGTH: usize = 200;
+
+pub const DESCRIPTION_MAX_LENGTH: usize = 2000;
+
+pub const QUESTION_MAX_LENGTH: usize = 500;
+
+pub const ANSWER_MAX_LENGTH: usize = 200;
+
+pub const CONTENT_BODY_MAX_LENGTH: usize = 2000;
+
+pub const MAX_SLIDES: usize = 200;
+
+pub const MIN_ANSWERS: usize = 2;
+
+pub const MAX_ANSWERS: usize = 6;
+
+pub const MAX_TIME_LIMIT: u32 = 600;
+
+pub const MAX_POINTS: u32 = 2000;
+
+pub const MAX_TAGS: usize = 10;
+
+pub const TAG_MAX_LENGTH: usize = 30;
+
+pub const CATEGORY_MAX_LENGTH: usize = 50;
+
+pub const MAX_DRAW_COUNT: u32 = 50;
+
+/// Clip paths starting with this name a file on the host's own device instead of an upload.
+pub const LOCAL_CLIP_PREFIX: &str = "local:";
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FieldError {
+    pub slide: Option<usize>,
+    pub field: String,
+    pub reason: String,
+}
+
+struct Errors(Vec<FieldError>);
+
+impl Errors {
+    fn push(&mut self, slide: Option<usize>, field: &str, reason: &str) {
+        self.0.push(FieldError {
+            slide,
+            field: field.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn text(&mut self, slide: Option<usize>, field: &str, value: &str, max_length: usize) {
+        if value.trim().is_empty() {
+            self.push(slide, field, "must not be empty");
+        } else if value.chars().count() > max_length {
+            self.push(slide, field, &format!("must be at most {} characters", max_length));
+        }
+    }
+
+    /// `text`, plus the formatting must stay within the rich text subset.
+    fn rich_text(&mut self, slide: Option<usize>, field: &str, value: &str, max_length: usize) {
+        self.text(slide, field, value, max_length);
+
+        if let Err(reason) = rich_text::validate(value) {
+            self.push(slide, field, reason);
+        }
+    }
+
+    /// `rich_text` for a field that may be left empty.
+    fn optional_rich_text(&mut self, slide: Option<usize>, field: &str, value: &str, max_length: usize) {
+        if !value.trim().is_empty() {
+            self.rich_text(slide, field, value, max_length);
+        }
+    }
+
+    fn time_limit(&mut self, slide: usize, time_limit: Option<u32>) {
+        if let Some(time_limit) = time_limit {
+            if time_limit == 0 || time_limit > MAX_TIME_LIMIT {
+                self.push(Some(slide), "time_limit", &format!("must be between 1 and {} seconds", MAX_TIME_LIMIT));
+            }
+        }
+    }
+
+    fn points(&mut self, slide: usize, points: Option<u32>) {
+        if let Some(points) = points {
+            if points > MAX_POINTS {
+                self.push(Some(slide), "points", &format!("must be at most {}", MAX_POINTS));
+            }
+        }
+    }
+
+    fn answers(
+        &mut self,
+        slide: usize,
+        answers: &Option<Vec<String>>,
+        correct_answers: &Option<Vec<bool>>,
+        expected_count: Option<usize>,
+    ) {
+        let answers = match answers {
+            Some(answers) => answers,
+            None => return self.push(Some(slide), "answers", "is required"),
+        };
+
+        match expected_count {
+            Some(count) if answers.len() != count => {
+                self.push(Some(slide), "answers", &format!("must contain exactly {} answers", count));
+            }
+            None if answers.len() < MIN_ANSWERS || answers.len() > MAX_ANSWERS => {
+                self.push(Some(slide), "answers", &format!("must contain between {} and {} answers", MIN_ANSWERS, MAX_ANSWERS));
+            }
+            _ => {}
+        }
+
+        for (index, answer) in answers.iter().enumerate() {
+            self.rich_text(Some(slide), &format!("answers[{}]", index), answer, ANSWER_MAX_LENGTH);
+        }
+
+        let correct_answers = match correct_answers {
+            Some(correct_answers) => correct_answers,
+            None => return self.push(Some(slide), "correct_answers", "is required"),
+        };
+
+        if correct_answers.len() != answers.len() {
+            self.push(Some(slide), "correct_answers", "must have the same length as answers");
+        }
+
+        if !correct_answers.iter().any(|correct| *correct) {
+            self.push(Some(slide), "correct_answers", "must mark at least one answer as correct");
+        }
+    }
+
+    fn tags(&mut self, tags: &[String]) {
+        if tags.len() > MAX_TAGS {
+            self.push(None, "tags", &format!("must contain at most {} tags", MAX_TAGS));
+        }
+
+        for (index, tag) in tags.iter().enumerate() {
+            self.text(None, &format!("tags[{}]", index), tag, TAG_MAX_LENGTH);
+        }
+    }
+
+    fn slides(&mut self, slides: &[Slide]) {
+        if slides.len() > MAX_SLIDES {
+            self.push(None, "slides", &format!("must contain at most {} slides", MAX_SLIDES));
+        }
+
+        for (index, slide) in slides.iter().enumerate() {
+            match slide {
+                Slide::Question(slide) => {
+                    self.rich_text(Some(index), "question", &slide.question, QUESTION_MAX_LENGTH);
+                    self.time_limit(index, slide.time_limit);
+                    self.points(index, slide.points);
+                    self.answers(index, &slide.answers, &slide.correct_answers, None);
+                }
+                Slide::TrueOrFalse(slide) => {
+                    self.rich_text(Some(index), "question", &slide.question, QUESTION_MAX_LENGTH);
+                    self.time_limit(index, slide.time_limit);
+                    self.points(index, slide.points);
+                    self.answers(index, &slide.answers, &slide.correct_answers, Some(2));
+
+                    if let Some(correct_answers) = &slide.correct_answers {
+                        if correct_answers.iter().filter(|correct| **correct).count() > 1 {
+                            self.push(Some(index), "correct_answers", "must mark exactly one answer as correct");
+                        }
+                    }
+                }
+                Slide::OpenEnded(slide) => {
+                    self.rich_text(Some(index), "question", &slide.question, QUESTION_MAX_LENGTH);
+                    self.time_limit(index, slide.time_limit);
+
+                    if let Some(max_length) = slide.max_length {
+                        if max_length == 0 || max_length > OPEN_ENDED_MAX_LENGTH {
+                            self.push(Some(index), "max_length", &format!("must be between 1 and {}", OPEN_ENDED_MAX_LENGTH));
+                        }
+                    }
+                }
+                Slide::ShortAnswer(slide) => {
+                    self.rich_text(Some(index), "question", &slide.question, QUESTION_MAX_LENGTH);
+                    self.time_limit(index, slide.time_limit);
+                    self.points(index, slide.points);
+
+                    if slide.accepted_answers.is_empty() || slide.accepted_answers.len() > MAX_ANSWERS {
+                        self.push(Some(index), "accepted_answers", &format!("must contain between 1 and {} answers", MAX_ANSWERS));
+                    }
+
+                    for (answer_index, answer) in slide.accepted_answers.iter().enumerate() {
+                        self.text(Some(index), &format!("accepted_answers[{}]", answer_index), answer, ANSWER_MAX_LENGTH);
+                    }
+                }
+                Slide::Numerical(slide) => {
+                    self.rich_text(Some(index), "question", &slide.question, QUESTION_MAX_LENGTH);
+                    self.time_limit(index, slide.time_limit);
+                    self.points(index, slide.points);
+
+                    if !slide.correct_value.is_finite() {
+                        self.push(Some(index), "correct_value", "must be a number");
+                    }
+
+                    if !slide.tolerance.is_finite() || slide.tolerance < 0.0 {
+                        self.push(Some(index), "tolerance", "must not be negative");
+                    }
+                }
+                Slide::Content(slide) => {
+                    if slide.title.trim().is_empty() && slide.body.trim().is_empty() {
+                        self.push(Some(index), "body", "title and body must not both be empty");
+                    }
+
+                    self.optional_rich_text(Some(index), "title", &slide.title, TITLE_MAX_LENGTH);
+                    self.optional_rich_text(Some(index), "body", &slide.body, CONTENT_BODY_MAX_LENGTH);
+                }
+                Slide::BankItem(slide) => {
+                    if BankItemId::parse(&slide.item_id).is_none() {
+                        self.push(Some(index), "item_id", "must be a question bank item id");
+                    }
+                }
+                Slide::RandomDraw(slide) => {
+                    if slide.count == 0 || slide.count > MAX_DRAW_COUNT {
+                        self.push(Some(index), "count", &format!("must be between 1 and {}", MAX_DRAW_COUNT));
+                    }
+
+                    if slide.tags.len() > MAX_TAGS {
+                        self.push(Some(index), "tags", &format!("must contain at most {} tags", MAX_TAGS));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks the structure of a quiz before it is saved and returns every problem found, so the
+/// editor can highlight all of them at once instead of one per request.
+pub fn validate_quiz(quiz: &QuizCreation) -> Result<(), Vec<FieldError>> {
+    let mut errors = Errors(Vec::new());
+
+    errors.text(None, "title", &quiz.title, TITLE_MAX_LENGTH);
+
+    if let Some(description) = &quiz.description {
+        if description.chars().count() > DESCRIPTION_MAX_LENGTH {
+            errors.push(None, "description", &format!("must be at most {} characters", DESCRIPTION_MAX_LENGTH));
+        }
+    }
+
+    errors.tags(&quiz.tags);
+
+    for (field, value) in [("subject", &quiz.subject), ("grade_level", &quiz.grade_level), ("language", &quiz.language)] {
+        if let Some(value) = value {
+            errors.text(None, field, value, CATEGORY_MAX_LENGTH);
+        }
+    }
+
+    errors.slides(&quiz.slides);
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.0)
+    }
+}
+
+/// Validation for the slide-level endpoints, which change slides without touching the rest of
+/// the quiz.
+pub fn validate_slides(slides: &[Slide]) -> Result<(), Vec<FieldError>> {
+    let mut errors = Errors(Vec::new());
+
+    errors.slides(slides);
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.0)
+    }
+}
+
+/// A bank item is a single real question: it can't itself point at the bank or draw from it.
+pub fn validate_bank_item(slide: &Slide, tags: &[String]) -> Result<(), Vec<FieldError>> {
+    let mut errors = Errors(Vec::new());
+
+    errors.tags(tags);
+
+    if slide.is_placeholder() {
+        errors.push(None, "slide", "must not be a bank reference or random draw");
+    } else {
+        errors.slides(std::slice::from_ref(slide));
+    }
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.0)
+    }
+}
+
+/// Every `image_path` and clip must point at media uploaded by one of `owner_ids`, so a quiz
+/// can't hotlink arbitrary URLs or someone else's uploads. Clips may also name a `local:` file
+/// the host plays themselves. Needs the database, so it runs after the structural checks pass.
+pub async fn validate_media(db: &Database, slides: &[Slide], owner_ids: &[UserId]) -> mongodb::error::Result<Result<(), Vec<FieldError>>> {
+    let owned = Media::find_owned(db, owner_ids, Media::keys_in(slides)).await?;
+    let uploaded = |path: &str| Media::key_from_path(path).and_then(|key| owned.get(key));
+    let mut errors = Errors(Vec::new());
+
+    for (index, slide) in slides.iter().enumerate() {
+        if let Some(path) = slide.image_path() {
+            if !uploaded(path).is_some_and(|media| media.kind == MediaKind::Image) {
+                errors.push(Some(index), "image_path", "must be an image you uploaded");
+            }
+        }
+
+        let clip = match slide.clip() {
+            Some(clip) => clip,
+            None => continue,
+        };
+
+        let duration = match uploaded(&clip.path) {
+            Some(media) if media.kind != MediaKind::Image => media.duration,
+            _ if clip.path.strip_prefix(LOCAL_CLIP_PREFIX).is_some_and(|name| !name.trim().is_empty()) => None,
+            _ => {
+                errors.push(Some(index), "clip.path", "must be an audio or video clip you uploaded, or a local: file");
+                continue;
+            }
+        };
+
+        if !clip.start.is_finite() || clip.start < 0.0 {
+            errors.push(Some(index), "clip.start", "must not be negative");
+        }
+
+        let end = clip.end.or(duration);
+
+        if let Some(end) = clip.end {
+            if !end.is_finite() || end <= clip.start {
+                errors.push(Some(index), "clip.end", "must be after clip.start");
+            } else if duration.is_some_and(|duration| end > duration) {
+                errors.push(Some(index), "clip.end", "must not be past the end of the clip");
+            }
+        }
+
+        if end.is_some_and(|end| end - clip.start > CLIP_MAX_SECONDS) {
+            errors.push(Some(index), "clip", &format!("must play for at most {} seconds", CLIP_MAX_SECONDS));
+        }
+    }
+
+    if errors.0.is_empty() {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(errors.0))
+    }
+}
+
+/// `validate_media` for a save to `quiz` by `user_id`, who may be a collaborator. Media the
+/// collaborator brings in is granted to the owner as well, so it keeps passing the check on the
+/// owner's saves and on other collaborators' saves.
+pub async fn validate_quiz_media(db: &Database, quiz: &Quiz, slides: &[Slide], user_id: UserId) -> mongodb::error::Result<Result<(), Vec<FieldError>>> {
+    if let Err(errors) = validate_media(db, slides, &[quiz.owner_id, user_id]).await? {
+        return Ok(Err(errors));
+    }
+
+    if user_id != quiz.owner_id {
+        Media::grant(db, quiz.owner_id, Media::keys_in(slides)).await?;
+    }
+
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn content(title: &str, body: &str) -> Slide {
+        serde_json::from_value(json!({
+            "question_type": "content", "theme": "", "title": title, "body": body, "image_path": null,
+        })).unwrap()
+    }
+
+    fn question(answers: &[&str], correct_answers: &[bool], time_limit: Option<u32>) -> Slide {
+        serde_json::from_value(json!({
+            "question_type": "question", "theme": "", "time_limit": time_limit, "points": null,
+            "answer_options": "single", "image_reveal": "", "image_path": "", "question": "Capital of France?",
+            "answers": answers, "correct_answers": correct_answers,
+        })).unwrap()
+    }
+
+    fn true_or_false(answers: &[&str], correct_answers: &[bool]) -> Slide {
+        serde_json::from_value(json!({
+            "question_type": "true_or_false", "theme": "", "time_limit": 20, "points": null,
+            "image_reveal": "", "image_path": "", "question": "Paris is in France.",
+            "answers": answers, "correct_answers": correct_answers,
+        })).unwrap()
+    }
+
+    /// The errors for `slide` played second, after a valid slide, so the index is checked too.
+    fn slide_errors(slide: Slide) -> Vec<FieldError> {
+        validate_slides(&[content("Intro", ""), slide]).err().unwrap_or_default()
+    }
+
+    fn error(slide: Option<usize>, field: &str, reason: &str) -> FieldError {
+        FieldError { slide, field: field.to_string(), reason: reason.to_string() }
+    }
+
+    fn failed_fields(slide: Slide) -> Vec<String> {
+        validate_slides(&[slide]).err().unwrap_or_default().into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn checks_content_slide_text_like_question_text() {
+        assert!(failed_fields(content("Round *two*", "")).is_empty());
+        assert!(failed_fields(content("", "Math: $x^2$")).is_empty());
+        assert_eq!(failed_fields(content("<img src=x onerror=alert(1)>", "")), ["title"]);
+        assert_eq!(failed_fields(content("Break", "<script>alert(1)</script>")), ["body"]);
+        assert_eq!(failed_fields(content("Break", &"a".repeat(CONTENT_BODY_MAX_LENGTH + 1))), ["body"]);
+        assert_eq!(failed_fields(content(" ", "")), ["body"]);
+    }
+
+    #[test]
+    fn accepts_a_valid_question() {
+        assert!(slide_errors(question(&["Lyon", "Paris"], &[false, true], Some(20))).is_empty());
+        assert!(slide_errors(true_or_false(&["True", "False"], &[true, false])).is_empty());
+    }
+
+    #[test]
+    fn rejects_correct_answers_of_another_length() {
+        assert_eq!(
+            slide_errors(question(&["Lyon", "Paris", "Nice"], &[false, true], Some(20))),
+            [error(Some(1), "correct_answers", "must have the same length as answers")],
+        );
+    }
+
+    #[test]
+    fn rejects_questions_without_a_correct_answer() {
+        assert_eq!(
+            slide_errors(question(&["Lyon", "Paris"], &[false, false], Some(20))),
+            [error(Some(1), "correct_answers", "must mark at least one answer as correct")],
+        );
+    }
+
+    #[test]
+    fn rejects_true_or_false_without_two_answers() {
+        assert_eq!(
+            slide_errors(true_or_false(&["True", "False", "Maybe"], &[true, false, false])),
+            [error(Some(1), "answers", "must contain exactly 2 answers")],
+        );
+    }
+
+    #[test]
+    fn rejects_an_empty_title() {
+        let quiz: QuizCreation = serde_json::from_value(json!({
+            "title": "  ", "description": null, "slides": [],
+            "subject": null, "grade_level": null, "language": null,
+        })).unwrap();
+
+        assert_eq!(validate_quiz(&quiz).err().unwrap_or_default(), [error(None, "title", "must not be empty")]);
+    }
+
+    #[test]
+    fn rejects_a_zero_time_limit() {
+        assert_eq!(
+            slide_errors(question(&["Lyon", "Paris"], &[false, true], Some(0))),
+            [error(Some(1), "time_limit", &format!("must be between 1 and {} seconds", MAX_TIME_LIMIT))],
+        );
+    }
+
+    #[test]
+    fn renders_content_slides_to_html() {
+        let value = content("Round *two*", "Ready?").to_json();
+
+        assert_eq!(value["title_html"], "<p>Round <em>two</em></p>");
+        assert_eq!(value["body_html"], "<p>Ready?</p>");
+    }
+}